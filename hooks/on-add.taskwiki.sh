@@ -1,4 +1,4 @@
 #!/bin/sh
 
-# eval taskwiki --debug add
-eval ~/workspace/taskw/target/debug/taskwiki --debug add
+# taskwarrior passes api:, args:, command:, rc:, data: and version: arguments
+exec ~/workspace/taskw/target/debug/taskwiki --debug add "$@"
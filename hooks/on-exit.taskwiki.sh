@@ -0,0 +1,3 @@
+#!/bin/sh
+
+exec ~/workspace/taskw/target/debug/taskwiki --debug exit "$@"
@@ -1,3 +1,3 @@
 #!/bin/sh
 
-eval ~/workspace/taskw/target/debug/taskwiki --debug modify
+exec ~/workspace/taskw/target/debug/taskwiki --debug modify "$@"
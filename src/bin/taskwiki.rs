@@ -1,33 +1,127 @@
 use clap::Parser;
 use env_logger::Env;
+use log::debug;
+use std::path::PathBuf;
 
-use taskw::cli::{task_from_stdin, Cli, Commands};
+use taskw::cli::{Cli, Commands};
 use taskw::config::Config;
-use taskw::hooks::Hooks;
+use taskw::hooks::{HookRunner, Hooks};
+use taskw::taskrc::Taskrc;
+use taskw::taskwarrior::TaskWarrior;
+use taskw::{Error, Result};
 
-fn main() -> Result<(), &'static str> {
+fn main() {
     let cli = Cli::parse();
-    let cfg = Config::default().to_static();
     let env = match cli.debug {
         true => Env::default().filter_or("RUST_LOG", "DEBUG"),
         false => Env::default().filter_or("RUST_LOG", "ERROR"),
     };
     env_logger::init_from_env(env);
 
-    let hooks = Hooks::with_config(cfg);
-    match &cli.command {
-        Commands::Add => {
-            let added_task = task_from_stdin()?;
-            let (task, feedback) = hooks.on_add(added_task)?;
-            println!("{}\n{}", task, feedback);
+    let cfg = match load_config(&cli) {
+        Ok(cfg) => cfg.to_static(),
+        Err(err) => {
+            debug!("{:?}", err);
+            // taskwarrior shows the standard output of a failing hook to the user
+            match cli.command.hook_kind() {
+                Some(_) => println!("taskwiki: {}", err.chain()),
+                None => eprintln!("taskwiki: {}", err.chain()),
+            }
+            std::process::exit(1);
         }
-        Commands::Modify => {
-            let original_task = task_from_stdin()?;
-            let modified_task = task_from_stdin()?;
-            let (task, feedback) = hooks.on_modify(original_task, modified_task)?;
-            println!("{}\n{}", task, feedback);
+    };
+    debug!("configuration:\n{}", cfg.describe());
+
+    if let Some(kind) = cli.command.hook_kind() {
+        let hooks = Hooks::with_config(cfg);
+        let status = HookRunner::new(&hooks).run(
+            kind,
+            std::io::stdin().lock(),
+            &mut std::io::stdout().lock(),
+        );
+        std::process::exit(status);
+    }
+
+    let result = match &cli.command {
+        Commands::Sync(args) => taskw::sync::run(
+            cfg,
+            &taskwarrior(cfg),
+            args.dry_run,
+            &mut std::io::stdout().lock(),
+        ),
+        Commands::History(args) => match data_location(cfg) {
+            Some(data_location) => taskw::history::run(
+                cfg,
+                &data_location,
+                &args.uuid,
+                args.append,
+                &mut std::io::stdout().lock(),
+            ),
+            None => Err(Error::History(String::from(
+                "cannot find the data location",
+            ))),
+        },
+        Commands::Graph(args) => match args.filter() {
+            Ok(filter) => taskw::graph::run(
+                cfg,
+                &taskwarrior(cfg),
+                filter.as_ref(),
+                args.format,
+                args.file.as_deref(),
+                &mut std::io::stdout().lock(),
+            ),
+            Err(message) => Err(Error::Filter(message)),
+        },
+        Commands::Render(args) => taskw::wiki::run(
+            cfg,
+            &taskwarrior(cfg),
+            &args.file,
+            args.dry_run,
+            &mut std::io::stdout().lock(),
+        ),
+        _ => unreachable!("hooks are handled above"),
+    };
+    if let Err(err) = result {
+        debug!("{:?}", err);
+        eprintln!("taskwiki: {}", err.chain());
+        std::process::exit(1);
+    }
+}
+
+/// Client for the task database the configuration refers to
+fn taskwarrior(cfg: &Config) -> TaskWarrior {
+    match &cfg.data_location {
+        Some(data_location) => TaskWarrior::new().with_data_location(data_location),
+        None => TaskWarrior::new(),
+    }
+}
+
+/// The directory of the task database the configuration refers to
+fn data_location(cfg: &Config) -> Option<PathBuf> {
+    cfg.data_location
+        .clone()
+        .or_else(Taskrc::default_data_location)
+}
+
+/// Load the configuration honoring the rc file and data location of the invoking task command
+fn load_config(cli: &Cli) -> Result<Config> {
+    let hook_args = cli.command.hook_args();
+
+    let rc = hook_args.and_then(|args| args.rc());
+    let mut taskrc = match rc.or_else(Taskrc::default_path) {
+        Some(path) if path.exists() => Taskrc::load(&path)?,
+        _ => Taskrc::default(),
+    };
+    if let Some(hook_args) = hook_args {
+        taskrc.apply_overrides(hook_args.command_line());
+        if let Some(data) = hook_args.data() {
+            taskrc.set_override("data.location", &data.to_string_lossy());
         }
     }
 
-    Ok(())
+    Ok(Config::load(
+        &taskrc,
+        cli.config.as_deref(),
+        cli.config_layer(),
+    )?)
 }
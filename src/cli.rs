@@ -1,7 +1,14 @@
-use clap::{Parser, Subcommand};
-use std::str::FromStr;
+use chrono::Weekday;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
-use crate::Task;
+use crate::config::{ConfigLayer, NotesPolicy};
+use crate::dates::Zone;
+use crate::filename::FilenameTemplate;
+use crate::filter::Filter;
+use crate::graph::GraphFormat;
+use crate::hooks::HookKind;
+use crate::notes::HeaderFields;
 
 /// taskwarrior hooks into vimwiki
 #[derive(Parser)]
@@ -11,22 +18,351 @@ pub struct Cli {
     pub command: Commands,
 
     /// Enable debug logging to stderr
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub debug: bool,
+
+    /// Config file to use instead of $XDG_CONFIG_HOME/taskwiki/config.yaml
+    #[clap(short, long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Taskwarrior tag marking tasks eligible for notes files
+    #[clap(long, global = true, value_name = "TAG")]
+    pub notes_tag: Option<String>,
+
+    /// Taskwarrior filter selecting tasks eligible for notes files, e.g. "project:work +wiki"
+    #[clap(long, global = true, value_name = "FILTER")]
+    pub notes_filter: Option<Filter>,
+
+    /// Base directory where notes files are created
+    #[clap(long, global = true, value_name = "DIR")]
+    pub notes_dir: Option<PathBuf>,
+
+    /// File extension used for notes files
+    #[clap(long, global = true, value_name = "EXT")]
+    pub notes_ext: Option<String>,
+
+    /// Template for notes file names, e.g. "{project}/{date}-{slug(description)}"
+    #[clap(long, global = true, value_name = "TEMPLATE")]
+    pub notes_name: Option<FilenameTemplate>,
+
+    /// Maximum length of notes file names, excluding the extension
+    #[clap(long, global = true, value_name = "LEN")]
+    pub notes_name_max: Option<usize>,
+
+    /// Whether notes files follow changes of the task's description or project: true or false
+    #[clap(long, global = true, value_name = "BOOL")]
+    pub notes_rename: Option<bool>,
+
+    /// Whether instances of recurring tasks share one notes file with a section each: true or
+    /// false
+    #[clap(long, global = true, value_name = "BOOL")]
+    pub notes_series: Option<bool>,
+
+    /// Task attributes mirrored into notes headers, e.g. "project,tags" or "none"
+    #[clap(long, global = true, value_name = "FIELDS")]
+    pub notes_fields: Option<HeaderFields>,
+
+    /// What happens to notes of completed tasks: keep, archive, delete or stamp
+    #[clap(long, global = true, value_name = "POLICY")]
+    pub notes_on_complete: Option<NotesPolicy>,
+
+    /// What happens to notes of deleted tasks: keep, archive, delete or stamp
+    #[clap(long, global = true, value_name = "POLICY")]
+    pub notes_on_delete: Option<NotesPolicy>,
+
+    /// First day of the week for dates like "sow" and "eow", e.g. "monday"
+    #[clap(long, global = true, value_name = "DAY")]
+    pub week_start: Option<Weekday>,
+
+    /// Time zone dates are interpreted in: "local" or a name like "Europe/Berlin"
+    #[clap(long, global = true, value_name = "ZONE")]
+    pub timezone: Option<Zone>,
+}
+
+impl Cli {
+    /// Configuration values overridden on the command line
+    pub fn config_layer(&self) -> ConfigLayer {
+        ConfigLayer {
+            notes_tag: self.notes_tag.clone(),
+            notes_filter: self.notes_filter.clone(),
+            notes_dir: self.notes_dir.clone(),
+            notes_ext: self.notes_ext.clone(),
+            notes_name: self.notes_name.clone(),
+            notes_name_max: self.notes_name_max,
+            notes_rename: self.notes_rename,
+            notes_series: self.notes_series,
+            notes_fields: self.notes_fields.clone(),
+            notes_on_complete: self.notes_on_complete,
+            notes_on_delete: self.notes_on_delete,
+            week_start: self.week_start,
+            timezone: self.timezone,
+        }
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// called with taskwarriors on-launch hook
+    Launch(HookArgs),
     /// called with taskwarriors on-add hook
-    Add,
+    Add(HookArgs),
     /// called with taskwarriors on-modify hook
-    Modify,
+    Modify(HookArgs),
+    /// called with taskwarriors on-exit hook
+    Exit(HookArgs),
+    /// Synchronize notes headers and tasks, the last edited side wins
+    Sync(SyncArgs),
+    /// Show how a task evolved, as recorded in taskwarrior's undo.data
+    History(HistoryArgs),
+    /// Draw which tasks block which, e.g. into a project's notes file
+    Graph(GraphArgs),
+    /// Apply the edits to the task lists of a wiki page, marked by
+    /// `<!-- taskwiki: FILTER -->`, then fill them with the matching tasks
+    Render(RenderArgs),
+}
+
+impl Commands {
+    /// The hook event this command handles, if it is called as a hook
+    pub fn hook_kind(&self) -> Option<HookKind> {
+        match self {
+            Commands::Launch(_) => Some(HookKind::Launch),
+            Commands::Add(_) => Some(HookKind::Add),
+            Commands::Modify(_) => Some(HookKind::Modify),
+            Commands::Exit(_) => Some(HookKind::Exit),
+            Commands::Sync(_) | Commands::History(_) | Commands::Graph(_) | Commands::Render(_) => {
+                None
+            }
+        }
+    }
+
+    /// The arguments taskwarrior passed to the hook, if called as a hook
+    pub fn hook_args(&self) -> Option<&HookArgs> {
+        match self {
+            Commands::Launch(args)
+            | Commands::Add(args)
+            | Commands::Modify(args)
+            | Commands::Exit(args) => Some(args),
+            Commands::Sync(_) | Commands::History(_) | Commands::Graph(_) | Commands::Render(_) => {
+                None
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct SyncArgs {
+    /// Only show what would be changed
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct HistoryArgs {
+    /// UUID of the task, may be abbreviated
+    #[clap(value_name = "UUID")]
+    pub uuid: String,
+
+    /// Also write the history to a section of the task's notes file
+    #[clap(short, long)]
+    pub append: bool,
 }
 
-pub fn task_from_stdin() -> Result<Task, &'static str> {
-    let mut json = String::new();
-    std::io::stdin()
-        .read_line(&mut json)
-        .map_err(|_| "cannot read from stdin")?;
-    Task::from_str(json.trim())
+#[derive(Args, Debug, Default)]
+pub struct GraphArgs {
+    /// Taskwarrior filter selecting the tasks to draw, all pending tasks by default. Terms
+    /// starting with a hyphen like "-home" follow a "--". A quoted argument with spaces like
+    /// "description.has:foo bar" is a single term.
+    #[clap(value_name = "FILTER")]
+    pub filter: Vec<String>,
+
+    /// Diagram language: mermaid or dot
+    #[clap(long, value_name = "FORMAT", default_value = "mermaid")]
+    pub format: GraphFormat,
+
+    /// Notes file, relative to the notes directory, to write the diagram into instead of
+    /// showing it
+    #[clap(short, long, value_name = "FILE")]
+    pub file: Option<PathBuf>,
+}
+
+impl GraphArgs {
+    /// The filter given on the command line, if any
+    pub fn filter(&self) -> Result<Option<Filter>, String> {
+        // arguments with whitespace are quoted, so the filter keeps them together
+        let quote = |word: &String| match word.contains(char::is_whitespace) {
+            false => word.clone(),
+            true if word.contains('\'') => format!("\"{}\"", word),
+            true => format!("'{}'", word),
+        };
+        match self.filter.is_empty() {
+            true => Ok(None),
+            false => {
+                let words: Vec<_> = self.filter.iter().map(quote).collect();
+                words.join(" ").parse().map(Some)
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct RenderArgs {
+    /// Wiki page to update in place
+    #[clap(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Only show the edits that would be applied
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+/// Arguments passed to hooks by taskwarrior 2.4+, e.g.
+/// `api:2 args:'task add foo' command:add rc:/home/me/.taskrc data:/home/me/.task version:2.6.0`
+#[derive(Args, Debug, Default)]
+pub struct HookArgs {
+    /// Hook arguments passed by taskwarrior (api:, args:, command:, rc:, data:, version:)
+    #[clap(value_name = "ARGS", allow_hyphen_values = true)]
+    pub raw: Vec<String>,
+}
+
+impl HookArgs {
+    /// The value of the `<key>:<value>` argument named `key`
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.raw
+            .iter()
+            .find_map(|arg| arg.strip_prefix(key)?.strip_prefix(':'))
+    }
+
+    /// The rc file used by the invoking task command
+    pub fn rc(&self) -> Option<PathBuf> {
+        self.value("rc").map(PathBuf::from)
+    }
+
+    /// The data location used by the invoking task command
+    pub fn data(&self) -> Option<PathBuf> {
+        self.value("data").map(PathBuf::from)
+    }
+
+    /// The command line of the invoking task command, split into words
+    pub fn command_line(&self) -> impl Iterator<Item = &str> {
+        self.value("args").unwrap_or_default().split_whitespace()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Task;
+
+    #[test]
+    fn parse_taskwarrior_hook_arguments() {
+        let cli = Cli::parse_from([
+            "taskwiki",
+            "add",
+            "api:2",
+            "args:task rc.taskwiki.notes.tag=notes add +wiki Dummy Task",
+            "command:add",
+            "rc:/home/me/.taskrc",
+            "data:/home/me/.task",
+            "version:2.6.1",
+        ]);
+        let args = cli.command.hook_args().expect("add is a hook");
+
+        assert_eq!(args.value("api"), Some("2"));
+        assert_eq!(args.value("version"), Some("2.6.1"));
+        assert_eq!(args.rc(), Some(PathBuf::from("/home/me/.taskrc")));
+        assert_eq!(args.data(), Some(PathBuf::from("/home/me/.task")));
+        assert!(args
+            .command_line()
+            .any(|word| word == "rc.taskwiki.notes.tag=notes"));
+    }
+
+    #[test]
+    fn hook_arguments_are_optional() {
+        let cli = Cli::parse_from(["taskwiki", "--debug", "modify"]);
+        assert!(cli.debug);
+        let args = cli.command.hook_args().expect("modify is a hook");
+        assert_eq!(args.rc(), None);
+        assert_eq!(args.command_line().count(), 0);
+    }
+
+    #[test]
+    fn parse_sync_command() {
+        let cli = Cli::parse_from(["taskwiki", "sync", "--dry-run"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Sync(SyncArgs { dry_run: true })
+        ));
+        assert!(cli.command.hook_kind().is_none());
+    }
+
+    #[test]
+    fn parse_history_command() {
+        let cli = Cli::parse_from(["taskwiki", "history", "dde3720b", "--append"]);
+        match cli.command {
+            Commands::History(args) => {
+                assert_eq!(args.uuid, "dde3720b");
+                assert!(args.append);
+            }
+            _ => panic!("history command expected"),
+        }
+    }
+
+    #[test]
+    fn parse_graph_command() {
+        let cli = Cli::parse_from([
+            "taskwiki",
+            "graph",
+            "--format",
+            "dot",
+            "-f",
+            "projects/work",
+            "project:work",
+            "--",
+            "-home",
+        ]);
+        match cli.command {
+            Commands::Graph(args) => {
+                assert_eq!(args.filter, ["project:work", "-home"]);
+                assert_eq!(
+                    args.filter().unwrap().unwrap().to_string(),
+                    "project:work -home"
+                );
+                assert_eq!(args.format, GraphFormat::Dot);
+                assert_eq!(args.file, Some(PathBuf::from("projects/work")));
+            }
+            _ => panic!("graph command expected"),
+        }
+
+        let cli = Cli::parse_from(["taskwiki", "graph", "description.has:foo bar", "+next"]);
+        match cli.command {
+            Commands::Graph(args) => {
+                let filter = args.filter().unwrap().unwrap();
+                assert!(filter.matches(&Task::new("Say foo bar").with_tag("next")));
+                assert!(!filter.matches(&Task::new("Say bar foo").with_tag("next")));
+            }
+            _ => panic!("graph command expected"),
+        }
+
+        let cli = Cli::parse_from(["taskwiki", "graph"]);
+        match cli.command {
+            Commands::Graph(args) => {
+                assert!(args.filter().unwrap().is_none());
+                assert_eq!(args.format, GraphFormat::Mermaid);
+            }
+            _ => panic!("graph command expected"),
+        }
+    }
+
+    #[test]
+    fn parse_render_command() {
+        let cli = Cli::parse_from(["taskwiki", "render", "wiki/work.md", "-n"]);
+        match cli.command {
+            Commands::Render(args) => {
+                assert_eq!(args.file, PathBuf::from("wiki/work.md"));
+                assert!(args.dry_run);
+            }
+            _ => panic!("render command expected"),
+        }
+        assert!(Cli::try_parse_from(["taskwiki", "render"]).is_err());
+    }
 }
@@ -1,13 +1,58 @@
-use std::path::PathBuf;
+use crate::dates::{DateContext, Zone};
+use crate::filename::FilenameTemplate;
+use crate::filter::Filter;
+use crate::notes::HeaderFields;
+use crate::taskrc::Taskrc;
+use crate::uda::{UdaError, UdaRegistry};
+use crate::urgency::Urgency;
+use crate::Task;
+use chrono::Weekday;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Configuration for the taskwiki executable
 pub struct Config {
     /// The taskwarrior tag indicating this task is eligible for notes file creation
     pub notes_tag: String,
+    /// Filter selecting the tasks eligible for notes files, used in place of `notes_tag`. Tasks
+    /// that stop passing it keep their notes file, unless a `+notes_tag` term of the filter
+    /// stops matching because the tag has been removed.
+    pub notes_filter: Option<Filter>,
     /// Base directory where notes files are created
     pub notes_dir: PathBuf,
     /// File extension used for notes files
     pub notes_ext: String,
+    /// Template for the paths of new notes files relative to `notes_dir`
+    pub notes_name: FilenameTemplate,
+    /// Maximum length of a notes file name, excluding the extension
+    pub notes_name_max: usize,
+    /// Whether notes files are moved to their new path when the task's description or
+    /// project changes
+    pub notes_rename: bool,
+    /// Whether the instances of a recurring task share the notes file of their parent, each
+    /// adding a section headed by its due date
+    pub notes_series: bool,
+    /// Task attributes mirrored into the YAML header of notes files
+    pub notes_fields: HeaderFields,
+    /// What happens to the notes file when its task is completed
+    pub notes_on_complete: NotesPolicy,
+    /// What happens to the notes file when its task is deleted
+    pub notes_on_delete: NotesPolicy,
+    /// The first day of the week for dates like `sow` and `eow`
+    pub week_start: Weekday,
+    /// The time zone dates are interpreted and written in
+    pub timezone: Zone,
+    /// Directory taskwarrior stores its data files in, if known
+    pub data_location: Option<PathBuf>,
+    /// User defined attributes declared in the taskrc
+    pub udas: UdaRegistry,
+    /// Urgency coefficients set in the taskrc
+    pub urgency: Urgency,
+
+    /// The source each of the above values has been taken from
+    sources: HashMap<&'static str, Source>,
 }
 
 impl Config {
@@ -16,14 +61,675 @@ impl Config {
         let static_box = Box::new(self);
         Box::leak(static_box)
     }
+
+    /// Load the configuration by layering (in increasing precedence) the built-in defaults, the
+    /// `taskwiki.` namespace of the `taskrc`, the config file, `TASKWIKI_*` environment
+    /// variables, `rc.taskwiki.` overrides and the given command line overrides.
+    ///
+    /// If `config_file` is `None` the default location `$XDG_CONFIG_HOME/taskwiki/config.yaml`
+    /// is used, which may be absent.
+    pub fn load(
+        taskrc: &Taskrc,
+        config_file: Option<&Path>,
+        cli: ConfigLayer,
+    ) -> Result<Self, ConfigError> {
+        let mut cfg = Self::default();
+
+        let taskrc_source = match taskrc.path() {
+            Some(path) => Source::Taskrc(path.to_path_buf()),
+            None => Source::RcOverride,
+        };
+        if let Some(data_location) = taskrc.data_location() {
+            cfg.data_location = Some(data_location);
+            cfg.sources.insert("data_location", taskrc_source.clone());
+        }
+        // taskwarrior's own `weekstart`, which `taskwiki.week.start` may still override
+        if let Some(week_start) = parse_setting(
+            "weekstart",
+            taskrc.get("weekstart").map(String::from),
+            &taskrc_source,
+        )? {
+            cfg.week_start = week_start;
+            cfg.sources.insert("week_start", taskrc_source.clone());
+        }
+        cfg.udas = UdaRegistry::from_taskrc(taskrc).map_err(ConfigError::Uda)?;
+        cfg.urgency = Urgency::from_taskrc(taskrc, &taskrc_source)?;
+        cfg.merge(taskrc.file_layer(&taskrc_source)?, taskrc_source);
+
+        match config_file {
+            Some(path) => cfg.merge(ConfigLayer::from_file(path)?, Source::File(path.into())),
+            None => {
+                if let Some(path) = default_config_file().filter(|path| path.exists()) {
+                    cfg.merge(ConfigLayer::from_file(&path)?, Source::File(path));
+                }
+            }
+        }
+        cfg.merge(ConfigLayer::from_env()?, Source::Env);
+        cfg.merge(taskrc.override_layer()?, Source::RcOverride);
+        cfg.merge(cli, Source::Cli);
+
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    /// Override all values set in `layer` and remember `source` as their origin
+    pub fn merge(&mut self, layer: ConfigLayer, source: Source) {
+        if let Some(notes_tag) = layer.notes_tag {
+            self.notes_tag = notes_tag;
+            self.sources.insert("notes_tag", source.clone());
+        }
+        if let Some(notes_filter) = layer.notes_filter {
+            self.notes_filter = Some(notes_filter);
+            self.sources.insert("notes_filter", source.clone());
+        }
+        if let Some(notes_dir) = layer.notes_dir {
+            self.notes_dir = expand_tilde(&notes_dir);
+            self.sources.insert("notes_dir", source.clone());
+        }
+        if let Some(notes_ext) = layer.notes_ext {
+            self.notes_ext = notes_ext;
+            self.sources.insert("notes_ext", source.clone());
+        }
+        if let Some(notes_name) = layer.notes_name {
+            self.notes_name = notes_name;
+            self.sources.insert("notes_name", source.clone());
+        }
+        if let Some(notes_name_max) = layer.notes_name_max {
+            self.notes_name_max = notes_name_max;
+            self.sources.insert("notes_name_max", source.clone());
+        }
+        if let Some(notes_rename) = layer.notes_rename {
+            self.notes_rename = notes_rename;
+            self.sources.insert("notes_rename", source.clone());
+        }
+        if let Some(notes_series) = layer.notes_series {
+            self.notes_series = notes_series;
+            self.sources.insert("notes_series", source.clone());
+        }
+        if let Some(notes_fields) = layer.notes_fields {
+            self.notes_fields = notes_fields;
+            self.sources.insert("notes_fields", source.clone());
+        }
+        if let Some(policy) = layer.notes_on_complete {
+            self.notes_on_complete = policy;
+            self.sources.insert("notes_on_complete", source.clone());
+        }
+        if let Some(policy) = layer.notes_on_delete {
+            self.notes_on_delete = policy;
+            self.sources.insert("notes_on_delete", source.clone());
+        }
+        if let Some(week_start) = layer.week_start {
+            self.week_start = week_start;
+            self.sources.insert("week_start", source.clone());
+        }
+        if let Some(timezone) = layer.timezone {
+            self.timezone = timezone;
+            self.sources.insert("timezone", source);
+        }
+    }
+
+    /// Dates relative to the current time, with the configured week start and time zone
+    pub fn date_context(&self) -> DateContext {
+        DateContext::new()
+            .with_week_start(self.week_start)
+            .with_zone(self.timezone)
+    }
+
+    /// Whether `task` is eligible for a notes file: it passes `notes_filter` if one is set,
+    /// otherwise it has the `notes_tag`
+    pub fn wants_notes(&self, task: &Task) -> bool {
+        match &self.notes_filter {
+            Some(filter) => filter.matches_in(task, &self.date_context()),
+            None => task.has_tag(&self.notes_tag),
+        }
+    }
+
+    /// The source the value of the setting `key` has been taken from
+    pub fn source_of(&self, key: &str) -> &Source {
+        self.sources.get(key).unwrap_or(&Source::Default)
+    }
+
+    /// Human readable listing of all settings together with their sources
+    pub fn describe(&self) -> String {
+        let data_location = match &self.data_location {
+            Some(path) => path.display().to_string(),
+            None => String::from("<unknown>"),
+        };
+        let notes_filter = match &self.notes_filter {
+            Some(filter) => filter.to_string(),
+            None => String::from("<none>"),
+        };
+        let settings = [
+            ("notes_tag", self.notes_tag.clone()),
+            ("notes_filter", notes_filter),
+            ("notes_dir", self.notes_dir.display().to_string()),
+            ("notes_ext", self.notes_ext.clone()),
+            ("notes_name", self.notes_name.to_string()),
+            ("notes_name_max", self.notes_name_max.to_string()),
+            ("notes_rename", self.notes_rename.to_string()),
+            ("notes_series", self.notes_series.to_string()),
+            ("notes_fields", self.notes_fields.to_string()),
+            ("notes_on_complete", self.notes_on_complete.to_string()),
+            ("notes_on_delete", self.notes_on_delete.to_string()),
+            ("week_start", self.week_start.to_string()),
+            ("timezone", self.timezone.to_string()),
+            ("data_location", data_location),
+        ];
+        settings
+            .iter()
+            .map(|(key, value)| format!("{} = {} ({})", key, value, self.source_of(key)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !self.notes_dir.is_dir() {
+            return Err(ConfigError::NotesDirMissing {
+                path: self.notes_dir.clone(),
+                source: self.source_of("notes_dir").clone(),
+            });
+        }
+        Ok(())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             notes_tag: String::from("wiki"),
-            notes_dir: PathBuf::from("/home/pweinshec/scratch"),
+            notes_filter: None,
+            notes_dir: expand_tilde(Path::new("~/vimwiki")),
             notes_ext: String::from("md"),
+            notes_name: FilenameTemplate::default(),
+            notes_name_max: 100,
+            notes_rename: true,
+            notes_series: false,
+            notes_fields: HeaderFields::default(),
+            notes_on_complete: NotesPolicy::Stamp,
+            notes_on_delete: NotesPolicy::Archive,
+            week_start: Weekday::Sun,
+            timezone: Zone::Local,
+            data_location: None,
+            udas: UdaRegistry::default(),
+            urgency: Urgency::default(),
+            sources: HashMap::new(),
+        }
+    }
+}
+
+/// A partial configuration as provided by a single source
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub notes_tag: Option<String>,
+    pub notes_filter: Option<Filter>,
+    pub notes_dir: Option<PathBuf>,
+    pub notes_ext: Option<String>,
+    pub notes_name: Option<FilenameTemplate>,
+    pub notes_name_max: Option<usize>,
+    pub notes_rename: Option<bool>,
+    pub notes_series: Option<bool>,
+    pub notes_fields: Option<HeaderFields>,
+    pub notes_on_complete: Option<NotesPolicy>,
+    pub notes_on_delete: Option<NotesPolicy>,
+    pub week_start: Option<Weekday>,
+    pub timezone: Option<Zone>,
+}
+
+impl ConfigLayer {
+    /// Read a layer from a YAML config file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|err| ConfigError::Read {
+            path: path.to_path_buf(),
+            source: err,
+        })?;
+        Self::from_yaml(&content).map_err(|err| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: err,
+        })
+    }
+
+    fn from_yaml(s: &str) -> Result<Self, serde_yaml::Error> {
+        // an empty config file is valid and simply sets nothing
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(s)
+    }
+
+    /// Read a layer from the `TASKWIKI_*` environment variables, e.g. `TASKWIKI_NOTES_DIR`
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(
+            |name| std::env::var(format!("TASKWIKI_{}", name.to_uppercase())).ok(),
+            &Source::Env,
+        )
+    }
+
+    /// Build a layer from string settings, where `lookup` maps a setting name like `notes_dir`
+    /// to its value, if set
+    pub(crate) fn from_lookup<F>(lookup: F, source: &Source) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Ok(Self {
+            notes_tag: lookup("notes_tag"),
+            notes_filter: parse_setting("notes_filter", lookup("notes_filter"), source)?,
+            notes_dir: lookup("notes_dir").map(PathBuf::from),
+            notes_ext: lookup("notes_ext"),
+            notes_name: parse_setting("notes_name", lookup("notes_name"), source)?,
+            notes_name_max: parse_setting("notes_name_max", lookup("notes_name_max"), source)?,
+            notes_rename: parse_flag("notes_rename", lookup("notes_rename"), source)?,
+            notes_series: parse_flag("notes_series", lookup("notes_series"), source)?,
+            notes_fields: parse_setting("notes_fields", lookup("notes_fields"), source)?,
+            notes_on_complete: parse_setting(
+                "notes_on_complete",
+                lookup("notes_on_complete"),
+                source,
+            )?,
+            notes_on_delete: parse_setting("notes_on_delete", lookup("notes_on_delete"), source)?,
+            week_start: parse_setting("week_start", lookup("week_start"), source)?,
+            timezone: parse_setting("timezone", lookup("timezone"), source)?,
+        })
+    }
+}
+
+/// Parse a boolean setting, accepting the spellings taskwarrior accepts in its rc file
+pub(crate) fn parse_flag(
+    name: &str,
+    value: Option<String>,
+    source: &Source,
+) -> Result<Option<bool>, ConfigError> {
+    value
+        .map(|value| match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "y" | "1" => Ok(true),
+            "false" | "no" | "off" | "n" | "0" => Ok(false),
+            _ => Err(ConfigError::InvalidValue {
+                key: name.to_string(),
+                value,
+                source: source.clone(),
+            }),
+        })
+        .transpose()
+}
+
+fn parse_setting<T: FromStr>(
+    name: &str,
+    value: Option<String>,
+    source: &Source,
+) -> Result<Option<T>, ConfigError> {
+    value
+        .map(|value| {
+            value.parse().map_err(|_| ConfigError::InvalidValue {
+                key: name.to_string(),
+                value,
+                source: source.clone(),
+            })
+        })
+        .transpose()
+}
+
+/// What happens to a notes file when the status of its task changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotesPolicy {
+    /// Leave the notes file untouched
+    Keep,
+    /// Move the notes file into the `archive/` subdirectory of the notes directory
+    Archive,
+    /// Remove the notes file
+    Delete,
+    /// Record the task's `status` and `end` in the YAML header of the notes file
+    Stamp,
+}
+
+impl FromStr for NotesPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(NotesPolicy::Keep),
+            "archive" => Ok(NotesPolicy::Archive),
+            "delete" => Ok(NotesPolicy::Delete),
+            "stamp" => Ok(NotesPolicy::Stamp),
+            _ => Err(format!(
+                "'{}' is not one of keep, archive, delete or stamp",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for NotesPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotesPolicy::Keep => write!(f, "keep"),
+            NotesPolicy::Archive => write!(f, "archive"),
+            NotesPolicy::Delete => write!(f, "delete"),
+            NotesPolicy::Stamp => write!(f, "stamp"),
         }
     }
 }
+
+/// Origin of a configuration value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// Built-in default value
+    Default,
+    /// Taskwarrior rc file at the given path
+    Taskrc(PathBuf),
+    /// Config file at the given path
+    File(PathBuf),
+    /// `TASKWIKI_*` environment variable
+    Env,
+    /// `rc.` override passed to taskwarrior on the command line
+    RcOverride,
+    /// Command line flag
+    Cli,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Taskrc(path) => write!(f, "taskrc {}", path.display()),
+            Source::File(path) => write!(f, "config file {}", path.display()),
+            Source::Env => write!(f, "environment"),
+            Source::RcOverride => write!(f, "rc override"),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Errors that can occur while loading the configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The config file is not valid YAML or contains unknown keys
+    Parse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    /// The taskwarrior rc file contains an invalid line
+    Taskrc {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// A setting has a value that cannot be interpreted
+    InvalidValue {
+        key: String,
+        value: String,
+        source: Source,
+    },
+    /// A user defined attribute is declared incorrectly
+    Uda(UdaError),
+    /// The configured notes directory does not exist
+    NotesDirMissing { path: PathBuf, source: Source },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, .. } => {
+                write!(f, "cannot read config file {}", path.display())
+            }
+            ConfigError::Parse { path, .. } => {
+                write!(f, "malformed config file {}", path.display())
+            }
+            ConfigError::Taskrc {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ConfigError::InvalidValue { key, value, source } => write!(
+                f,
+                "invalid value '{}' for {} (set by {})",
+                value, key, source
+            ),
+            ConfigError::Uda(_) => write!(f, "invalid UDA declaration"),
+            ConfigError::NotesDirMissing { path, source } => write!(
+                f,
+                "notes_dir {} (set by {}) does not exist or is not a directory",
+                path.display(),
+                source
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Uda(err) => Some(err),
+            ConfigError::Taskrc { .. }
+            | ConfigError::InvalidValue { .. }
+            | ConfigError::NotesDirMissing { .. } => None,
+        }
+    }
+}
+
+/// Location of the config file following the XDG base directory specification
+pub fn default_config_file() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("taskwiki").join("config.yaml"))
+}
+
+/// Replace a leading `~` by the user's home directory
+pub(crate) fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, NamedTempFile};
+
+    #[test]
+    fn parse_partial_yaml_layer() {
+        let layer = ConfigLayer::from_yaml("notes_tag: notes\nnotes_ext: wiki").expect("parses");
+        assert_eq!(layer.notes_tag, Some("notes".to_string()));
+        assert_eq!(layer.notes_dir, None);
+        assert_eq!(layer.notes_ext, Some("wiki".to_string()));
+    }
+
+    #[test]
+    fn notes_filter_replaces_notes_tag() {
+        let layer = ConfigLayer::from_yaml("notes_filter: project:work +wiki").expect("parses");
+        let mut cfg = Config::default();
+        cfg.merge(layer, Source::Env);
+        assert_eq!(cfg.source_of("notes_filter"), &Source::Env);
+
+        let mut task = Task::new("Dummy Task").with_tag("wiki");
+        assert!(!cfg.wants_notes(&task));
+        task.project = Some(String::from("work"));
+        assert!(cfg.wants_notes(&task));
+
+        assert!(ConfigLayer::from_yaml("notes_filter: (+wiki").is_err());
+        let err = ConfigLayer::from_lookup(
+            |name| (name == "notes_filter").then(|| String::from("due.before:sometime")),
+            &Source::Env,
+        )
+        .expect_err("invalid filter");
+        assert!(err.to_string().contains("notes_filter"));
+    }
+
+    #[test]
+    fn configure_week_start_and_timezone() {
+        let layer = ConfigLayer::from_lookup(
+            |name| match name {
+                "week_start" => Some(String::from("monday")),
+                "timezone" => Some(String::from("Europe/Berlin")),
+                _ => None,
+            },
+            &Source::Env,
+        )
+        .expect("valid settings");
+        let mut cfg = Config::default();
+        cfg.merge(layer, Source::Env);
+        assert_eq!(cfg.week_start, Weekday::Mon);
+        assert_eq!(cfg.timezone.to_string(), "Europe/Berlin");
+        assert!(cfg
+            .describe()
+            .contains("timezone = Europe/Berlin (environment)"));
+
+        let layer = ConfigLayer::from_yaml("week_start: Sat\ntimezone: local").expect("parses");
+        assert_eq!(layer.week_start, Some(Weekday::Sat));
+        assert_eq!(layer.timezone, Some(Zone::Local));
+        assert!(ConfigLayer::from_yaml("timezone: Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn reject_unknown_keys_in_yaml_layer() {
+        assert!(ConfigLayer::from_yaml("notes_tags: typo").is_err());
+    }
+
+    #[test]
+    fn later_layers_take_precedence_and_record_their_source() {
+        let mut cfg = Config::default();
+        let file = PathBuf::from("/etc/taskwiki.yaml");
+        cfg.merge(
+            ConfigLayer {
+                notes_tag: Some("file".to_string()),
+                notes_ext: Some("wiki".to_string()),
+                ..Default::default()
+            },
+            Source::File(file.clone()),
+        );
+        let env = ConfigLayer::from_lookup(
+            |name| match name {
+                "notes_tag" => Some("env".to_string()),
+                "notes_on_delete" => Some("delete".to_string()),
+                _ => None,
+            },
+            &Source::Env,
+        )
+        .expect("valid settings");
+        cfg.merge(env, Source::Env);
+
+        assert_eq!(cfg.notes_tag, "env");
+        assert_eq!(cfg.source_of("notes_tag"), &Source::Env);
+        assert_eq!(cfg.notes_ext, "wiki");
+        assert_eq!(cfg.source_of("notes_ext"), &Source::File(file));
+        assert_eq!(cfg.source_of("notes_dir"), &Source::Default);
+        assert_eq!(cfg.notes_on_delete, NotesPolicy::Delete);
+        assert_eq!(cfg.notes_on_complete, NotesPolicy::Stamp);
+    }
+
+    #[test]
+    fn reject_invalid_policy() {
+        let err = ConfigLayer::from_lookup(
+            |name| match name {
+                "notes_on_complete" => Some("shred".to_string()),
+                _ => None,
+            },
+            &Source::Env,
+        )
+        .expect_err("invalid policy");
+        assert!(err.to_string().contains("notes_on_complete"));
+        assert!(err.to_string().contains("shred"));
+
+        let layer = ConfigLayer::from_yaml("notes_on_complete: archive").expect("parses");
+        assert_eq!(layer.notes_on_complete, Some(NotesPolicy::Archive));
+    }
+
+    #[test]
+    fn load_from_file_with_cli_override() {
+        let notes_dir = tempdir().expect("tempdir creation succeeds");
+        let mut config_file = NamedTempFile::new().expect("created tempfile");
+        std::io::Write::write_all(
+            &mut config_file,
+            format!(
+                "notes_dir: {}\nnotes_tag: notes",
+                notes_dir.path().display()
+            )
+            .as_bytes(),
+        )
+        .expect("writing tempfile");
+
+        let cli = ConfigLayer {
+            notes_tag: Some("cli".to_string()),
+            ..Default::default()
+        };
+        let cfg = Config::load(&Taskrc::default(), Some(config_file.path()), cli)
+            .expect("loading succeeds");
+
+        assert_eq!(cfg.notes_dir, notes_dir.path());
+        assert_eq!(
+            cfg.source_of("notes_dir"),
+            &Source::File(config_file.path().to_path_buf())
+        );
+        assert_eq!(cfg.notes_tag, "cli");
+        assert_eq!(cfg.source_of("notes_tag"), &Source::Cli);
+    }
+
+    #[test]
+    fn load_fails_for_malformed_file() {
+        let mut config_file = NamedTempFile::new().expect("created tempfile");
+        std::io::Write::write_all(&mut config_file, b"notes_dir: [unclosed").expect("writing");
+
+        let err = Config::load(
+            &Taskrc::default(),
+            Some(config_file.path()),
+            ConfigLayer::default(),
+        )
+        .err()
+        .expect("loading fails");
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert!(err.to_string().contains("malformed config file"));
+    }
+
+    #[test]
+    fn load_fails_for_missing_notes_dir() {
+        let cli = ConfigLayer {
+            notes_dir: Some(PathBuf::from("/does/not/exist")),
+            ..Default::default()
+        };
+        let err = Config::load(&Taskrc::default(), Some(Path::new("/dev/null")), cli)
+            .err()
+            .expect("loading fails");
+        assert!(err.to_string().contains("/does/not/exist"));
+        assert!(err.to_string().contains("command line"));
+    }
+
+    #[test]
+    fn taskrc_settings_rank_below_config_file_but_overrides_above() {
+        let notes_dir = tempdir().expect("tempdir creation succeeds");
+        let rc_path = notes_dir.path().join("taskrc");
+        std::fs::write(
+            &rc_path,
+            format!(
+                "data.location=/data\ntaskwiki.notes.dir={}\ntaskwiki.notes.ext=wiki\n",
+                notes_dir.path().display()
+            ),
+        )
+        .expect("writing taskrc");
+        let mut taskrc = Taskrc::load(&rc_path).expect("loading taskrc");
+        taskrc.set_override("taskwiki.notes.tag", "override");
+
+        let cfg = Config::load(
+            &taskrc,
+            Some(Path::new("/dev/null")),
+            ConfigLayer::default(),
+        )
+        .expect("loading succeeds");
+
+        assert_eq!(cfg.notes_dir, notes_dir.path());
+        assert_eq!(cfg.source_of("notes_dir"), &Source::Taskrc(rc_path.clone()));
+        assert_eq!(cfg.notes_ext, "wiki");
+        assert_eq!(cfg.notes_tag, "override");
+        assert_eq!(cfg.source_of("notes_tag"), &Source::RcOverride);
+        assert_eq!(cfg.data_location, Some(PathBuf::from("/data")));
+    }
+}
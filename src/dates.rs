@@ -0,0 +1,817 @@
+use crate::datetime_format;
+use chrono::{
+    DateTime, Datelike, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// The time zone dates are interpreted in, e.g. what `today` or `2022-02-18` means
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Zone {
+    /// The time zone of the system
+    #[default]
+    Local,
+    /// A time zone of the IANA database like `Europe/Berlin` or `UTC`
+    Named(Tz),
+}
+
+impl Zone {
+    /// The wall clock time in this zone at `date`
+    pub fn to_naive(self, date: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => date.with_timezone(&Local).naive_local(),
+            Zone::Named(tz) => date.with_timezone(&tz).naive_local(),
+        }
+    }
+
+    /// The instant the wall clock in this zone shows `naive`. Ambiguous times resolve to the
+    /// earlier instant, times skipped by a DST change to the instant an hour later.
+    pub fn from_naive(self, naive: NaiveDateTime) -> DateTime<Utc> {
+        let resolve = |result: LocalResult<DateTime<Utc>>, later: LocalResult<DateTime<Utc>>| {
+            result
+                .earliest()
+                .or_else(|| later.earliest())
+                .unwrap_or_else(|| Utc.from_utc_datetime(&naive))
+        };
+        let later = naive + chrono::Duration::hours(1);
+        match self {
+            Zone::Local => resolve(
+                Local
+                    .from_local_datetime(&naive)
+                    .map(|d| d.with_timezone(&Utc)),
+                Local
+                    .from_local_datetime(&later)
+                    .map(|d| d.with_timezone(&Utc)),
+            ),
+            Zone::Named(tz) => resolve(
+                tz.from_local_datetime(&naive)
+                    .map(|d| d.with_timezone(&Utc)),
+                tz.from_local_datetime(&later)
+                    .map(|d| d.with_timezone(&Utc)),
+            ),
+        }
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        Tz::from_str(s)
+            .map(Zone::Named)
+            .map_err(|_| format!("'{}' is neither 'local' nor a known time zone", s))
+    }
+}
+
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Zone::Local => write!(f, "local"),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Zone {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parse a weekday like `monday` or `Mon`, as in taskwarrior's `weekstart` setting
+pub fn parse_weekday(s: &str) -> Result<Weekday, String> {
+    Weekday::from_str(s).map_err(|_| format!("'{}' is not a day of the week", s))
+}
+
+/// Everything relative dates depend on: the current time, the first day of the week and the
+/// time zone
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateContext {
+    now: DateTime<Utc>,
+    week_start: Weekday,
+    zone: Zone,
+}
+
+/// The period `so<period>`, `eo<period>`, `soc<period>` and `eoc<period>` refer to
+#[derive(Clone, Copy)]
+enum CalendarPeriod {
+    Week,
+    WorkWeek,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl DateContext {
+    /// Dates relative to the current time in the local time zone, weeks starting on Sunday
+    /// like taskwarrior's default
+    pub fn new() -> Self {
+        Self::at(Utc::now())
+    }
+
+    /// Dates relative to `now`
+    pub fn at(now: DateTime<Utc>) -> Self {
+        Self {
+            now,
+            week_start: Weekday::Sun,
+            zone: Zone::Local,
+        }
+    }
+
+    pub fn with_week_start(mut self, week_start: Weekday) -> Self {
+        self.week_start = week_start;
+        self
+    }
+
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    /// The calendar day of `date` in the time zone
+    pub fn local_date(&self, date: DateTime<Utc>) -> NaiveDate {
+        self.zone.to_naive(date).date()
+    }
+
+    /// Format `date` in the time zone using a chrono format string like `%Y-%m-%d`
+    pub fn format(&self, date: DateTime<Utc>, format: &str) -> String {
+        self.zone.to_naive(date).format(format).to_string()
+    }
+
+    /// Parse a date expression: a named date like `eow`, `tomorrow` or `friday`, an absolute
+    /// date like `2022-02-18`, `2022-02-18T10:00` or `20220218T100000Z`, optionally followed
+    /// by durations to add or subtract, e.g. `now+3d` or `eom-1w+2h`
+    pub fn parse_date(&self, s: &str) -> Result<DateTime<Utc>, String> {
+        let s = s.trim();
+        if let Some(date) = self.parse_single(s) {
+            return Ok(date);
+        }
+
+        // the rightmost operator whose right hand side is a duration, dates contain dashes
+        for (idx, op) in s
+            .char_indices()
+            .rev()
+            .filter(|(_, c)| matches!(c, '+' | '-'))
+        {
+            let (date, duration) = (&s[..idx], &s[idx + 1..]);
+            let duration = match Duration::from_str(duration) {
+                Ok(duration) if !date.is_empty() => duration,
+                _ => continue,
+            };
+            if let Ok(date) = self.parse_date(date) {
+                let duration = if op == '-' {
+                    duration.negate()
+                } else {
+                    duration
+                };
+                return Ok(duration.add_to(date, self.zone));
+            }
+        }
+        Err(format!("'{}' is not a valid date", s))
+    }
+
+    fn parse_single(&self, s: &str) -> Option<DateTime<Utc>> {
+        self.parse_named(&s.to_lowercase())
+            .or_else(|| self.parse_absolute(s))
+    }
+
+    fn midnight(&self, date: NaiveDate) -> DateTime<Utc> {
+        self.zone.from_naive(date.and_time(NaiveTime::MIN))
+    }
+
+    fn parse_named(&self, s: &str) -> Option<DateTime<Utc>> {
+        let today = self.local_date(self.now);
+        let end_of_day = |date: NaiveDate| {
+            self.midnight(date + chrono::Duration::days(1)) - chrono::Duration::seconds(1)
+        };
+
+        let date = match s {
+            "now" => return Some(self.now),
+            "today" | "sod" => self.midnight(today),
+            "eod" => end_of_day(today),
+            "yesterday" => self.midnight(today - chrono::Duration::days(1)),
+            "tomorrow" => self.midnight(today + chrono::Duration::days(1)),
+            "later" | "someday" => self.midnight(NaiveDate::from_ymd_opt(9999, 12, 30)?),
+            _ => {
+                if let Some(date) = self.parse_anchor(s, today) {
+                    return Some(date);
+                }
+                if let Ok(weekday) = Weekday::from_str(s) {
+                    // the next such day after today
+                    let days = (weekday.num_days_from_monday() as i64
+                        - today.weekday().num_days_from_monday() as i64)
+                        .rem_euclid(7);
+                    let days = if days == 0 { 7 } else { days };
+                    return Some(self.midnight(today + chrono::Duration::days(days)));
+                }
+                if let Some(month) = parse_month(s) {
+                    // the first of the next such month after this one
+                    let year = if month > today.month() {
+                        today.year()
+                    } else {
+                        today.year() + 1
+                    };
+                    return Some(self.midnight(NaiveDate::from_ymd_opt(year, month, 1)?));
+                }
+                return self.parse_ordinal(s, today);
+            }
+        };
+        Some(date)
+    }
+
+    /// `sow`, `eom`, `socq`, `eocy` and the like
+    fn parse_anchor(&self, s: &str, today: NaiveDate) -> Option<DateTime<Utc>> {
+        let (current, rest) = if let Some(rest) = s.strip_prefix("soc") {
+            (true, rest)
+        } else if let Some(rest) = s.strip_prefix("eoc") {
+            (false, rest)
+        } else {
+            (
+                false,
+                s.strip_prefix("so").or_else(|| s.strip_prefix("eo"))?,
+            )
+        };
+        let start = s.starts_with('s');
+        let period = match rest {
+            "w" => CalendarPeriod::Week,
+            "ww" => CalendarPeriod::WorkWeek,
+            "m" => CalendarPeriod::Month,
+            "q" => CalendarPeriod::Quarter,
+            "y" => CalendarPeriod::Year,
+            _ => return None,
+        };
+
+        let (start_of_current, start_of_next) = self.period_bounds(period, today)?;
+        Some(match (start, current) {
+            // `so<period>` is the start of the next period, `soc<period>` of the current one
+            (true, false) => self.midnight(start_of_next),
+            (true, true) => self.midnight(start_of_current),
+            // the end is always that of the current period
+            (false, _) => {
+                let end = match period {
+                    CalendarPeriod::WorkWeek => start_of_current + chrono::Duration::days(5),
+                    _ => start_of_next,
+                };
+                self.midnight(end) - chrono::Duration::seconds(1)
+            }
+        })
+    }
+
+    /// The first day of the period containing `today` and of the period after it
+    fn period_bounds(
+        &self,
+        period: CalendarPeriod,
+        today: NaiveDate,
+    ) -> Option<(NaiveDate, NaiveDate)> {
+        let week_start = |first: Weekday| {
+            let days = (today.weekday().num_days_from_monday() as i64
+                - first.num_days_from_monday() as i64)
+                .rem_euclid(7);
+            today - chrono::Duration::days(days)
+        };
+        let first_of = |year: i32, month: u32| NaiveDate::from_ymd_opt(year, month, 1);
+        let next_month = |year: i32, month: u32| match month {
+            12 => first_of(year + 1, 1),
+            _ => first_of(year, month + 1),
+        };
+
+        Some(match period {
+            CalendarPeriod::Week => {
+                let start = week_start(self.week_start);
+                (start, start + chrono::Duration::days(7))
+            }
+            CalendarPeriod::WorkWeek => {
+                let start = week_start(Weekday::Mon);
+                (start, start + chrono::Duration::days(7))
+            }
+            CalendarPeriod::Month => (
+                first_of(today.year(), today.month())?,
+                next_month(today.year(), today.month())?,
+            ),
+            CalendarPeriod::Quarter => {
+                let month = (today.month0() / 3) * 3 + 1;
+                let start = first_of(today.year(), month)?;
+                let next = match month {
+                    10 => first_of(today.year() + 1, 1)?,
+                    _ => first_of(today.year(), month + 3)?,
+                };
+                (start, next)
+            }
+            CalendarPeriod::Year => (first_of(today.year(), 1)?, first_of(today.year() + 1, 1)?),
+        })
+    }
+
+    /// `1st`, `2nd`, `15th`: the next such day of a month, starting today
+    fn parse_ordinal(&self, s: &str, today: NaiveDate) -> Option<DateTime<Utc>> {
+        let day: u32 = ["st", "nd", "rd", "th"]
+            .iter()
+            .find_map(|suffix| s.strip_suffix(suffix))?
+            .parse()
+            .ok()
+            .filter(|day| (1..=31).contains(day))?;
+        let (mut year, mut month) = (today.year(), today.month());
+        if day <= today.day() {
+            (year, month) = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+        }
+        // skip months without such a day
+        loop {
+            if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+                return Some(self.midnight(date));
+            }
+            (year, month) = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+        }
+    }
+
+    fn parse_absolute(&self, s: &str) -> Option<DateTime<Utc>> {
+        if let Ok(date) = datetime_format::parse(s) {
+            return Some(date);
+        }
+        if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+            return Some(date.with_timezone(&Utc));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Some(self.midnight(date));
+        }
+        // taskwarrior accepts epoch seconds, but not small numbers which are more likely ids
+        if s.len() >= 9 && s.chars().all(|c| c.is_ascii_digit()) {
+            return Utc.timestamp_opt(s.parse().ok()?, 0).single();
+        }
+
+        let (naive, utc) = match s.strip_suffix('Z') {
+            Some(naive) => (naive, true),
+            None => (s, false),
+        };
+        let naive = [
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%d %H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(naive, format).ok())?;
+        Some(match utc {
+            true => Utc.from_utc_datetime(&naive),
+            false => self.zone.from_naive(naive),
+        })
+    }
+}
+
+impl Default for DateContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_month(s: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    MONTHS
+        .iter()
+        .position(|month| *month == s || (s.len() == 3 && month.starts_with(s)))
+        .map(|idx| idx as u32 + 1)
+}
+
+/// A duration like `3d`, `2weeks`, `monthly` or the ISO-8601 `P1W`. Months and years are
+/// calendar months and years and days are calendar days in the time zone dates are computed
+/// in, so `now+1mo` is the same day of the next month and `now+1d` the same time tomorrow,
+/// even across DST changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Duration {
+    months: i64,
+    days: i64,
+    seconds: i64,
+}
+
+impl Duration {
+    pub fn months(months: i64) -> Self {
+        Self {
+            months,
+            ..Self::default()
+        }
+    }
+
+    pub fn days(days: i64) -> Self {
+        Self {
+            days,
+            ..Self::default()
+        }
+    }
+
+    pub fn seconds(seconds: i64) -> Self {
+        Self {
+            seconds,
+            ..Self::default()
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Self {
+            months: -self.months,
+            days: -self.days,
+            seconds: -self.seconds,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `date` moved by this duration, calendar units counted in `zone`. The day of the month
+    /// is clamped, e.g. January 31st plus one month is the last day of February.
+    pub fn add_to(&self, date: DateTime<Utc>, zone: Zone) -> DateTime<Utc> {
+        let date = if self.months != 0 || self.days != 0 {
+            let naive = zone.to_naive(date);
+            let day = add_months(naive.date(), self.months) + chrono::Duration::days(self.days);
+            zone.from_naive(day.and_time(naive.time()))
+        } else {
+            date
+        };
+        date + chrono::Duration::seconds(self.seconds)
+    }
+
+    /// The approximate length, counting months as 30 and years as 365 days
+    pub fn approximate(&self) -> chrono::Duration {
+        let days = (self.months / 12) * 365 + (self.months % 12) * 30 + self.days;
+        chrono::Duration::days(days) + chrono::Duration::seconds(self.seconds)
+    }
+}
+
+fn add_months(date: NaiveDate, months: i64) -> NaiveDate {
+    let month0 = date.year() as i64 * 12 + date.month0() as i64 + months;
+    let (year, month) = (
+        month0.div_euclid(12) as i32,
+        month0.rem_euclid(12) as u32 + 1,
+    );
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or(date)
+}
+
+impl std::ops::Add for Duration {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            months: self.months + other.months,
+            days: self.days + other.days,
+            seconds: self.seconds + other.seconds,
+        }
+    }
+}
+
+impl std::ops::Mul<i64> for Duration {
+    type Output = Self;
+
+    fn mul(self, factor: i64) -> Self {
+        Self {
+            months: self.months * factor,
+            days: self.days * factor,
+            seconds: self.seconds * factor,
+        }
+    }
+}
+
+impl FromStr for Duration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a valid duration", s);
+        let lower = s.trim().to_lowercase();
+        let (sign, rest) = match lower.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, lower.strip_prefix('+').unwrap_or(&lower)),
+        };
+        if let Some(iso) = rest.strip_prefix('p') {
+            return Ok(parse_iso_duration(iso).ok_or_else(invalid)? * sign);
+        }
+        if let Some(duration) = named_duration(rest) {
+            return Ok(duration * sign);
+        }
+
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (number, unit) = rest.split_at(split);
+        let number: i64 = match number {
+            "" => 1,
+            number => number.parse().map_err(|_| invalid())?,
+        };
+        let unit = duration_unit(unit).ok_or_else(invalid)?;
+        Ok(unit * (number * sign))
+    }
+}
+
+/// A duration like `weekly` or `fortnight`
+fn named_duration(s: &str) -> Option<Duration> {
+    Some(match s {
+        "hourly" => Duration::seconds(3600),
+        "daily" => Duration::days(1),
+        "weekly" | "sennight" => Duration::days(7),
+        "biweekly" | "fortnight" => Duration::days(14),
+        "monthly" => Duration::months(1),
+        "bimonthly" => Duration::months(2),
+        "quarterly" => Duration::months(3),
+        "semiannual" => Duration::months(6),
+        "yearly" | "annual" => Duration::months(12),
+        // like in taskwarrior, where biannual means every other year
+        "biyearly" | "biannual" => Duration::months(24),
+        _ => return None,
+    })
+}
+
+fn duration_unit(unit: &str) -> Option<Duration> {
+    Some(match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Duration::seconds(1),
+        "min" | "mins" | "minute" | "minutes" => Duration::seconds(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Duration::seconds(3600),
+        "d" | "day" | "days" => Duration::days(1),
+        "w" | "wk" | "wks" | "week" | "weeks" => Duration::days(7),
+        "mo" | "mth" | "mths" | "month" | "months" => Duration::months(1),
+        "q" | "qtr" | "qtrs" | "quarter" | "quarters" => Duration::months(3),
+        "y" | "yr" | "yrs" | "year" | "years" => Duration::months(12),
+        _ => return None,
+    })
+}
+
+/// The part of an ISO-8601 duration like `P1Y2M3DT4H5M6S` after the `P`, lowercased
+fn parse_iso_duration(s: &str) -> Option<Duration> {
+    let (date, time) = match s.split_once('t') {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return None,
+        None => (s, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return None;
+    }
+
+    let mut duration = Duration::default();
+    for (part, units) in [
+        (
+            date,
+            &[
+                ('y', Duration::months(12)),
+                ('m', Duration::months(1)),
+                ('w', Duration::days(7)),
+                ('d', Duration::days(1)),
+            ][..],
+        ),
+        (
+            time.unwrap_or_default(),
+            &[
+                ('h', Duration::seconds(3600)),
+                ('m', Duration::seconds(60)),
+                ('s', Duration::seconds(1)),
+            ][..],
+        ),
+    ] {
+        let mut rest = part;
+        // designators must appear in order, each at most once
+        let mut units = units.iter();
+        while !rest.is_empty() {
+            let split = rest.find(|c: char| !c.is_ascii_digit())?;
+            let number: i64 = rest[..split].parse().ok()?;
+            let designator = rest[split..].chars().next()?;
+            let (_, unit) = units.find(|(d, _)| *d == designator)?;
+            duration = duration + *unit * number;
+            rest = &rest[split + designator.len_utf8()..];
+        }
+    }
+    Some(duration)
+}
+
+impl std::fmt::Display for Duration {
+    /// The ISO-8601 representation, e.g. `P1M2DT3H` or `P2W`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "PT0S");
+        }
+        if self.months < 0 || self.days < 0 || self.seconds < 0 {
+            write!(f, "-")?;
+            return write!(f, "{}", self.negate());
+        }
+
+        write!(f, "P")?;
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            write!(f, "{}Y", years)?;
+        }
+        if months != 0 {
+            write!(f, "{}M", months)?;
+        }
+        if self.days != 0 && self.days % 7 == 0 && self.months == 0 && self.seconds == 0 {
+            return write!(f, "{}W", self.days / 7);
+        }
+        if self.days != 0 {
+            write!(f, "{}D", self.days)?;
+        }
+        if self.seconds != 0 {
+            write!(f, "T")?;
+            let (hours, minutes, seconds) = (
+                self.seconds / 3600,
+                self.seconds % 3600 / 60,
+                self.seconds % 60,
+            );
+            if hours != 0 {
+                write!(f, "{}H", hours)?;
+            }
+            if minutes != 0 {
+                write!(f, "{}M", minutes)?;
+            }
+            if seconds != 0 {
+                write!(f, "{}S", seconds)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wednesday, February 16th 2022, noon in UTC
+    fn context() -> DateContext {
+        let now = Utc.with_ymd_and_hms(2022, 2, 16, 12, 0, 0).unwrap();
+        DateContext::at(now).with_zone(Zone::Named(Tz::UTC))
+    }
+
+    fn date(s: &str) -> String {
+        date_in(&context(), s)
+    }
+
+    fn date_in(context: &DateContext, s: &str) -> String {
+        let date = context.parse_date(s).expect("valid date");
+        context.format(date, "%Y-%m-%dT%H:%M:%S")
+    }
+
+    #[test]
+    fn parse_named_dates() {
+        assert_eq!(date("now"), "2022-02-16T12:00:00");
+        assert_eq!(date("today"), "2022-02-16T00:00:00");
+        assert_eq!(date("sod"), "2022-02-16T00:00:00");
+        assert_eq!(date("eod"), "2022-02-16T23:59:59");
+        assert_eq!(date("yesterday"), "2022-02-15T00:00:00");
+        assert_eq!(date("Tomorrow"), "2022-02-17T00:00:00");
+        assert_eq!(date("friday"), "2022-02-18T00:00:00");
+        assert_eq!(date("wed"), "2022-02-23T00:00:00");
+        assert_eq!(date("march"), "2022-03-01T00:00:00");
+        assert_eq!(date("feb"), "2023-02-01T00:00:00");
+        assert_eq!(date("20th"), "2022-02-20T00:00:00");
+        assert_eq!(date("15th"), "2022-03-15T00:00:00");
+        assert_eq!(date("31st"), "2022-03-31T00:00:00");
+        assert_eq!(date("someday"), "9999-12-30T00:00:00");
+    }
+
+    #[test]
+    fn parse_start_and_end_of_periods() {
+        assert_eq!(date("socw"), "2022-02-13T00:00:00");
+        assert_eq!(date("sow"), "2022-02-20T00:00:00");
+        assert_eq!(date("eow"), "2022-02-19T23:59:59");
+        assert_eq!(date("eocw"), "2022-02-19T23:59:59");
+        assert_eq!(date("soww"), "2022-02-21T00:00:00");
+        assert_eq!(date("eoww"), "2022-02-18T23:59:59");
+        assert_eq!(date("som"), "2022-03-01T00:00:00");
+        assert_eq!(date("socm"), "2022-02-01T00:00:00");
+        assert_eq!(date("eom"), "2022-02-28T23:59:59");
+        assert_eq!(date("soq"), "2022-04-01T00:00:00");
+        assert_eq!(date("eoq"), "2022-03-31T23:59:59");
+        assert_eq!(date("soy"), "2023-01-01T00:00:00");
+        assert_eq!(date("socy"), "2022-01-01T00:00:00");
+        assert_eq!(date("eoy"), "2022-12-31T23:59:59");
+
+        let monday = context().with_week_start(Weekday::Mon);
+        assert_eq!(date_in(&monday, "socw"), "2022-02-14T00:00:00");
+        assert_eq!(date_in(&monday, "sow"), "2022-02-21T00:00:00");
+        assert_eq!(date_in(&monday, "eow"), "2022-02-20T23:59:59");
+    }
+
+    #[test]
+    fn parse_absolute_dates() {
+        assert_eq!(date("2022-02-18"), "2022-02-18T00:00:00");
+        assert_eq!(date("2022-02-18T10:00"), "2022-02-18T10:00:00");
+        assert_eq!(date("2022-02-18T10:00:30"), "2022-02-18T10:00:30");
+        assert_eq!(date("2022-02-18T10:00Z"), "2022-02-18T10:00:00");
+        assert_eq!(date("20220218T100000Z"), "2022-02-18T10:00:00");
+        assert_eq!(date("2022-02-18T10:00:00+01:00"), "2022-02-18T09:00:00");
+        assert_eq!(date("1645178400"), "2022-02-18T10:00:00");
+        assert!(context().parse_date("2022-02-30").is_err());
+        assert!(context().parse_date("someday soon").is_err());
+        assert!(context().parse_date("12").is_err());
+    }
+
+    #[test]
+    fn compute_relative_dates() {
+        assert_eq!(date("now+3d"), "2022-02-19T12:00:00");
+        assert_eq!(date("now-1h"), "2022-02-16T11:00:00");
+        assert_eq!(date("eow-1d"), "2022-02-18T23:59:59");
+        assert_eq!(date("2022-02-18-1w"), "2022-02-11T00:00:00");
+        assert_eq!(date("2022-01-31+1mo"), "2022-02-28T00:00:00");
+        assert_eq!(date("today+1d+2h"), "2022-02-17T02:00:00");
+        assert_eq!(date("som+P1W"), "2022-03-08T00:00:00");
+        assert!(context().parse_date("now+3x").is_err());
+    }
+
+    #[test]
+    fn dates_depend_on_the_time_zone() {
+        let berlin = context().with_zone("Europe/Berlin".parse().unwrap());
+        let today = berlin.parse_date("today").unwrap();
+        assert_eq!(today, Utc.with_ymd_and_hms(2022, 2, 15, 23, 0, 0).unwrap());
+        assert_eq!(date_in(&berlin, "now"), "2022-02-16T13:00:00");
+
+        // a day is a calendar day, also when the clocks change
+        let dst = DateContext::at(Utc.with_ymd_and_hms(2022, 3, 26, 12, 0, 0).unwrap())
+            .with_zone("Europe/Berlin".parse().unwrap());
+        assert_eq!(date_in(&dst, "now+1d"), "2022-03-27T13:00:00");
+        assert_eq!(
+            dst.parse_date("now+1d").unwrap() - dst.now(),
+            chrono::Duration::hours(23)
+        );
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+        assert_eq!("local".parse::<Zone>(), Ok(Zone::Local));
+    }
+
+    #[test]
+    fn parse_durations() {
+        let parse = |s: &str| Duration::from_str(s).expect("valid duration");
+        assert_eq!(parse("3d"), Duration::days(3));
+        assert_eq!(parse("2weeks"), Duration::days(14));
+        assert_eq!(parse("week"), Duration::days(7));
+        assert_eq!(parse("-5min"), Duration::seconds(-300));
+        assert_eq!(parse("1y"), Duration::months(12));
+        assert_eq!(parse("2q"), Duration::months(6));
+        assert_eq!(parse("monthly"), Duration::months(1));
+        assert_eq!(parse("fortnight"), Duration::days(14));
+        assert_eq!(parse("semiannual"), Duration::months(6));
+        assert_eq!(parse("biannual"), Duration::months(24));
+        assert_eq!(parse("biyearly"), Duration::months(24));
+        assert_eq!(parse("P1W"), Duration::days(7));
+        assert_eq!(parse("PT36H"), Duration::seconds(36 * 3600));
+        assert_eq!(
+            parse("P1Y2M3DT4H5M6S"),
+            Duration::months(14) + Duration::days(3) + Duration::seconds(4 * 3600 + 5 * 60 + 6)
+        );
+        for invalid in [
+            "",
+            "P",
+            "PT",
+            "P1H",
+            "P1D2Y",
+            "3x",
+            "d3",
+            "1.5d",
+            "biannually",
+        ] {
+            assert!(
+                Duration::from_str(invalid).is_err(),
+                "{} is invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn display_durations_in_iso_format() {
+        for iso in ["P2W", "P1Y2M3DT4H5M6S", "PT1H30M", "P10D", "-P1M", "PT0S"] {
+            assert_eq!(Duration::from_str(iso).unwrap_or_default().to_string(), iso);
+        }
+        assert_eq!(Duration::from_str("quarterly").unwrap().to_string(), "P3M");
+        assert_eq!(
+            Duration::months(14).approximate(),
+            chrono::Duration::days(425)
+        );
+    }
+}
@@ -1,14 +1,23 @@
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use serde::{self, Deserialize, Deserializer, Serializer};
 
 const FORMAT: &str = "%Y%m%dT%H%M%SZ";
 
+/// Format a datetime in the taskwarrior wire format, e.g. `20220110T171619Z`
+pub fn format(date: &DateTime<Utc>) -> String {
+    format!("{}", date.format(FORMAT))
+}
+
+/// Parse a datetime given in the taskwarrior wire format
+pub fn parse(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    NaiveDateTime::parse_from_str(s, FORMAT).map(|datetime| Utc.from_utc_datetime(&datetime))
+}
+
 pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let s = format!("{}", date.format(FORMAT));
-    serializer.serialize_str(&s)
+    serializer.serialize_str(&format(date))
 }
 
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
@@ -16,8 +25,23 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    Utc.datetime_from_str(&s, FORMAT)
-        .map_err(serde::de::Error::custom)
+    parse(&s).map_err(serde::de::Error::custom)
+}
+
+/// Deserialization of optional datetimes given in the taskwarrior wire format
+pub mod option {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => super::parse(&s).map(Some).map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -33,7 +57,7 @@ mod tests {
     #[test]
     fn deserialize_taskwarrior_datetime_format() {
         let json_str = r#"{"datetime":"20220110T171619Z"}"#;
-        let datetime = Utc.ymd(2022, 1, 10).and_hms(17, 16, 19);
+        let datetime = Utc.with_ymd_and_hms(2022, 1, 10, 17, 16, 19).unwrap();
 
         let testdt: TestDateTime =
             serde_json::from_str(json_str).expect("deserialization succeeded");
@@ -44,8 +68,27 @@ mod tests {
     fn serialize_taskwarrior_datetime_format() {
         let json_str = r#"{"datetime":"20220110T171619Z"}"#;
         let testdt = TestDateTime {
-            datetime: Utc.ymd(2022, 1, 10).and_hms(17, 16, 19),
+            datetime: Utc.with_ymd_and_hms(2022, 1, 10, 17, 16, 19).unwrap(),
         };
         assert_eq!(json_str, serde_json::to_string(&testdt).expect(""));
     }
+
+    #[derive(serde::Deserialize)]
+    struct TestOptionalDateTime {
+        #[serde(default, with = "super::option")]
+        datetime: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn deserialize_optional_taskwarrior_datetime_format() {
+        let testdt: TestOptionalDateTime =
+            serde_json::from_str(r#"{"datetime":"20220110T171619Z"}"#).expect("deserializes");
+        assert_eq!(
+            testdt.datetime,
+            Some(Utc.with_ymd_and_hms(2022, 1, 10, 17, 16, 19).unwrap())
+        );
+
+        let testdt: TestOptionalDateTime = serde_json::from_str("{}").expect("deserializes");
+        assert_eq!(testdt.datetime, None);
+    }
 }
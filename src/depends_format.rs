@@ -0,0 +1,59 @@
+use serde::{self, Deserialize, Deserializer};
+use uuid::Uuid;
+
+/// The `depends` attribute either as JSON array or in the legacy comma separated form
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Depends {
+    List(Vec<Uuid>),
+    CommaSeparated(String),
+}
+
+/// Parse the legacy comma separated form of the `depends` attribute
+pub fn parse(s: &str) -> Result<Vec<Uuid>, uuid::Error> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|uuid| !uuid.is_empty())
+        .map(Uuid::parse_str)
+        .collect()
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Uuid>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Depends::deserialize(deserializer)? {
+        Depends::List(depends) => Ok(depends),
+        Depends::CommaSeparated(s) => parse(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct TestDepends {
+        #[serde(with = "super")]
+        depends: Vec<Uuid>,
+    }
+
+    const UUID_A: &str = "dde3720b-003f-4776-8e15-61e5d90376af";
+    const UUID_B: &str = "9d417e14-efac-4173-930e-a59fd743d23b";
+
+    #[test]
+    fn deserialize_depends_array() {
+        let json_str = format!(r#"{{"depends":["{}","{}"]}}"#, UUID_A, UUID_B);
+        let test: TestDepends = serde_json::from_str(&json_str).expect("deserializes");
+        assert_eq!(test.depends.len(), 2);
+        assert_eq!(test.depends[1].to_string(), UUID_B);
+    }
+
+    #[test]
+    fn deserialize_legacy_comma_separated_depends() {
+        let json_str = format!(r#"{{"depends":"{},{}"}}"#, UUID_A, UUID_B);
+        let test: TestDepends = serde_json::from_str(&json_str).expect("deserializes");
+        assert_eq!(test.depends.len(), 2);
+        assert_eq!(test.depends[0].to_string(), UUID_A);
+    }
+}
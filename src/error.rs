@@ -0,0 +1,187 @@
+use crate::config::ConfigError;
+use crate::taskwarrior::TaskError;
+use crate::uda::UdaError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the fallible APIs of this crate
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from or writing to a file or stream failed
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    /// (De-)serializing JSON, i.e. taskwarrior tasks, failed
+    Json {
+        context: String,
+        source: serde_json::Error,
+    },
+    /// (De-)serializing YAML, i.e. notes file headers, failed
+    Yaml {
+        context: String,
+        source: serde_yaml::Error,
+    },
+    /// Accessing a TaskChampion SQLite database failed
+    Sqlite {
+        context: String,
+        source: rusqlite::Error,
+    },
+    /// The configuration could not be loaded
+    Config(ConfigError),
+    /// A user defined attribute has been used incorrectly
+    Uda(UdaError),
+    /// Running the taskwarrior `task` binary failed
+    Task(TaskError),
+    /// A notes template is malformed
+    Template(String),
+    /// A line of taskwarrior's `pending.data` or `completed.data` is malformed
+    TaskData(String),
+    /// The history of a task is not available, e.g. because it has never been changed
+    History(String),
+    /// The dependencies among tasks cannot be resolved, e.g. because they form a cycle
+    Graph(String),
+    /// A recurring task cannot be expanded into instances, e.g. because it has no due date
+    Recurrence(String),
+    /// A taskwarrior filter given on the command line is malformed
+    Filter(String),
+    /// Taskwarrior did not follow the hook protocol, e.g. by providing too few input lines
+    Hook(String),
+    /// A hook deliberately rejects the change, the message is shown to the user
+    Rejected(String),
+}
+
+impl Error {
+    /// Wrap an I/O error, describing what has been attempted in `context`
+    pub fn io<C: Into<String>>(context: C) -> impl FnOnce(std::io::Error) -> Self {
+        move |source| Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Wrap a JSON error, describing what has been attempted in `context`
+    pub fn json<C: Into<String>>(context: C) -> impl FnOnce(serde_json::Error) -> Self {
+        move |source| Error::Json {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Wrap a YAML error, describing what has been attempted in `context`
+    pub fn yaml<C: Into<String>>(context: C) -> impl FnOnce(serde_yaml::Error) -> Self {
+        move |source| Error::Yaml {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Wrap an SQLite error, describing what has been attempted in `context`
+    pub fn sqlite<C: Into<String>>(context: C) -> impl FnOnce(rusqlite::Error) -> Self {
+        move |source| Error::Sqlite {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// This error followed by all of its sources, separated by colons
+    pub fn chain(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            message.push_str(&format!(": {}", err));
+            source = err.source();
+        }
+        message
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io { context, .. }
+            | Error::Json { context, .. }
+            | Error::Yaml { context, .. }
+            | Error::Sqlite { context, .. } => {
+                write!(f, "{}", context)
+            }
+            Error::Config(_) => write!(f, "invalid configuration"),
+            Error::Uda(_) => write!(f, "invalid user defined attribute"),
+            Error::Task(err) => write!(f, "{}", err),
+            Error::Template(message) => write!(f, "invalid notes template {}", message),
+            Error::TaskData(message) => write!(f, "malformed task data: {}", message),
+            Error::History(message) => write!(f, "{}", message),
+            Error::Graph(message) => write!(f, "{}", message),
+            Error::Recurrence(message) => write!(f, "{}", message),
+            Error::Filter(message) => write!(f, "invalid filter: {}", message),
+            Error::Hook(message) => write!(f, "hook protocol violation: {}", message),
+            Error::Rejected(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Yaml { source, .. } => Some(source),
+            Error::Sqlite { source, .. } => Some(source),
+            Error::Config(err) => Some(err),
+            Error::Uda(err) => Some(err),
+            Error::Task(err) => std::error::Error::source(err),
+            Error::Template(_)
+            | Error::TaskData(_)
+            | Error::History(_)
+            | Error::Graph(_)
+            | Error::Recurrence(_)
+            | Error::Filter(_)
+            | Error::Hook(_)
+            | Error::Rejected(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Self {
+        Error::Config(err)
+    }
+}
+
+impl From<TaskError> for Error {
+    fn from(err: TaskError) -> Self {
+        Error::Task(err)
+    }
+}
+
+impl From<UdaError> for Error {
+    fn from(err: UdaError) -> Self {
+        Error::Uda(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn chain_includes_all_sources() {
+        let err = Error::from(ConfigError::Read {
+            path: PathBuf::from("/etc/taskwiki.yaml"),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "not found"),
+        });
+        assert_eq!(
+            err.chain(),
+            "invalid configuration: cannot read config file /etc/taskwiki.yaml: not found"
+        );
+    }
+
+    #[test]
+    fn io_errors_carry_their_context() {
+        let source = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let err = Error::io("cannot write notes file /tmp/note.md")(source);
+        assert_eq!(err.to_string(), "cannot write notes file /tmp/note.md");
+        assert_eq!(err.chain(), "cannot write notes file /tmp/note.md: denied");
+    }
+}
@@ -0,0 +1,590 @@
+use crate::uda::{UdaRegistry, UdaType};
+use crate::{datetime_format, Error, Result, Status, Task};
+use chrono::{TimeZone, Utc};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Attributes taskwarrior stores as epoch seconds
+const DATE_ATTRIBUTES: [&str; 8] = [
+    "entry",
+    "modified",
+    "due",
+    "scheduled",
+    "wait",
+    "until",
+    "start",
+    "end",
+];
+
+/// Attributes taskwarrior computes when loading tasks, which are never stored
+const COMPUTED_ATTRIBUTES: [&str; 2] = ["id", "urgency"];
+
+/// The task database of taskwarrior 2.x, i.e. the `pending.data` and `completed.data` files
+/// in the data location, which store one task per line in the FF4 format:
+///
+/// `[description:"Buy milk" entry:"1641835000" status:"pending" uuid:"..."]`
+///
+/// It is opened read-only, writing requires [`TaskData::writable`].
+pub struct TaskData {
+    dir: PathBuf,
+    udas: UdaRegistry,
+    writable: bool,
+}
+
+impl TaskData {
+    /// Open the task database in the data location `dir`, read-only
+    pub fn open(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            udas: UdaRegistry::default(),
+            writable: false,
+        }
+    }
+
+    /// Convert date and numeric UDAs as declared in `udas`, which are strings otherwise
+    pub fn with_udas(mut self, udas: UdaRegistry) -> Self {
+        self.udas = udas;
+        self
+    }
+
+    /// Allow changing the task database
+    pub fn writable(mut self) -> Self {
+        self.writable = true;
+        self
+    }
+
+    fn pending_path(&self) -> PathBuf {
+        self.dir.join("pending.data")
+    }
+
+    fn completed_path(&self) -> PathBuf {
+        self.dir.join("completed.data")
+    }
+
+    /// The tasks in `pending.data`, numbered like taskwarrior does: tasks that are neither
+    /// completed nor deleted get consecutive ids starting at 1, all others get id 0
+    pub fn pending(&self) -> Result<Vec<Task>> {
+        let mut tasks = self.read(&self.pending_path())?;
+        let mut next_id = 1;
+        for task in &mut tasks {
+            match task.status {
+                Status::Completed | Status::Deleted => task.id = Some(0),
+                _ => {
+                    task.id = Some(next_id);
+                    next_id += 1;
+                }
+            }
+        }
+        Ok(tasks)
+    }
+
+    /// The tasks in `completed.data`, all with id 0
+    pub fn completed(&self) -> Result<Vec<Task>> {
+        let mut tasks = self.read(&self.completed_path())?;
+        for task in &mut tasks {
+            task.id = Some(0);
+        }
+        Ok(tasks)
+    }
+
+    /// All tasks, pending ones first
+    pub fn tasks(&self) -> Result<Vec<Task>> {
+        let mut tasks = self.pending()?;
+        tasks.extend(self.completed()?);
+        Ok(tasks)
+    }
+
+    /// Replace the content of `pending.data` by `tasks`
+    pub fn write_pending(&self, tasks: &[Task]) -> Result<()> {
+        self.write(&self.pending_path(), tasks)
+    }
+
+    /// Replace the content of `completed.data` by `tasks`
+    pub fn write_completed(&self, tasks: &[Task]) -> Result<()> {
+        self.write(&self.completed_path(), tasks)
+    }
+
+    /// The tasks in the data file at `path`, none if the file does not exist
+    fn read(&self, path: &Path) -> Result<Vec<Task>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(Error::io(format!(
+                    "cannot read task data {}",
+                    path.display()
+                ))(err))
+            }
+        };
+
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                parse_line(line, &self.udas).map_err(|err| {
+                    let message = match err {
+                        Error::TaskData(message) => message,
+                        err => err.chain(),
+                    };
+                    Error::TaskData(format!("{}:{}: {}", path.display(), idx + 1, message))
+                })
+            })
+            .collect()
+    }
+
+    /// Write `tasks` to the data file at `path` by replacing it atomically
+    fn write(&self, path: &Path, tasks: &[Task]) -> Result<()> {
+        let context = || format!("cannot write task data {}", path.display());
+        if !self.writable {
+            return Err(Error::io(context())(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "task data has been opened read-only",
+            )));
+        }
+
+        let mut content = String::new();
+        for task in tasks {
+            content.push_str(&compose_line(task, &self.udas)?);
+            content.push('\n');
+        }
+        let temp_path = path.with_extension("data.tmp");
+        let mut file = std::fs::File::create(&temp_path).map_err(Error::io(context()))?;
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(Error::io(context()))?;
+        std::fs::rename(&temp_path, path).map_err(Error::io(context()))
+    }
+}
+
+/// Parse a single line of a taskwarrior 2.x data file
+pub fn parse_line(line: &str, udas: &UdaRegistry) -> Result<Task> {
+    let line = line.trim();
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(|| Error::TaskData(String::from("line is not enclosed in brackets")))?;
+
+    let mut attributes = Map::new();
+    let mut annotations = vec![];
+    for (key, value) in split_attributes(inner)? {
+        if let Some(epoch) = key.strip_prefix("annotation_") {
+            let entry = epoch_to_wire(epoch).ok_or_else(|| {
+                Error::TaskData(format!("invalid annotation timestamp '{}'", epoch))
+            })?;
+            annotations.push((entry, value));
+            continue;
+        }
+
+        let value = match key.as_str() {
+            "tags" => Value::from(
+                value
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .collect::<Vec<_>>(),
+            ),
+            "imask" => parse_number(&value),
+            name if is_date(name, udas) => match epoch_to_wire(&value) {
+                Some(date) => Value::from(date),
+                None => Value::from(value),
+            },
+            name if is_numeric_uda(name, udas) => parse_number(&value),
+            _ => Value::from(value),
+        };
+        attributes.insert(key, value);
+    }
+
+    if !annotations.is_empty() {
+        annotations.sort();
+        let annotations = annotations
+            .into_iter()
+            .map(|(entry, description)| {
+                let mut annotation = Map::new();
+                annotation.insert(String::from("entry"), Value::from(entry));
+                annotation.insert(String::from("description"), Value::from(description));
+                Value::Object(annotation)
+            })
+            .collect();
+        attributes.insert(String::from("annotations"), Value::Array(annotations));
+    }
+
+    Task::from_value(Value::Object(attributes))
+}
+
+/// Compose the line representing `task` in a taskwarrior 2.x data file
+pub fn compose_line(task: &Task, udas: &UdaRegistry) -> Result<String> {
+    let attributes = match serde_json::to_value(task) {
+        Ok(Value::Object(attributes)) => attributes,
+        Ok(_) => unreachable!("tasks are serialized as objects"),
+        Err(err) => return Err(Error::json("cannot serialize task")(err)),
+    };
+
+    // sorted by name like taskwarrior does
+    let mut fields = BTreeMap::new();
+    for (name, value) in attributes {
+        if COMPUTED_ATTRIBUTES.contains(&name.as_str()) {
+            continue;
+        }
+        match (name.as_str(), value) {
+            ("annotations", Value::Array(annotations)) => {
+                for annotation in annotations {
+                    let description = annotation["description"].as_str().unwrap_or_default();
+                    let epoch = annotation["entry"].as_str().and_then(wire_to_epoch);
+                    // annotations made within the same second must not overwrite each other
+                    let mut epoch = epoch.unwrap_or_default();
+                    while fields.contains_key(&format!("annotation_{}", epoch)) {
+                        epoch += 1;
+                    }
+                    fields.insert(format!("annotation_{}", epoch), description.to_string());
+                }
+            }
+            ("tags" | "depends", Value::Array(values)) => {
+                let values: Vec<_> = values.iter().filter_map(Value::as_str).collect();
+                fields.insert(name, values.join(","));
+            }
+            (name, Value::String(date)) if is_date(name, udas) => {
+                let epoch = wire_to_epoch(&date).map(|epoch| epoch.to_string());
+                fields.insert(name.to_string(), epoch.unwrap_or(date));
+            }
+            (_, Value::String(s)) => {
+                fields.insert(name, s);
+            }
+            (_, Value::Null) => {}
+            (_, value) => {
+                fields.insert(name, value.to_string());
+            }
+        }
+    }
+
+    let fields: Vec<_> = fields
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{}:\"{}\"", name, encode(&value)))
+        .collect();
+    Ok(format!("[{}]", fields.join(" ")))
+}
+
+pub(crate) fn is_date(name: &str, udas: &UdaRegistry) -> bool {
+    DATE_ATTRIBUTES.contains(&name)
+        || udas
+            .get(name)
+            .is_some_and(|uda| uda.uda_type == UdaType::Date)
+}
+
+pub(crate) fn is_numeric_uda(name: &str, udas: &UdaRegistry) -> bool {
+    udas.get(name)
+        .is_some_and(|uda| uda.uda_type == UdaType::Numeric)
+}
+
+/// A JSON number for numeric strings, which taskwarrior may write as `1` or `1.000000`
+pub(crate) fn parse_number(value: &str) -> Value {
+    match value.parse::<f64>() {
+        Ok(n) if n.fract() == 0.0 && n >= 0.0 && n < u64::MAX as f64 => Value::from(n as u64),
+        Ok(n) => Value::from(n),
+        Err(_) => Value::from(value),
+    }
+}
+
+pub(crate) fn epoch_to_wire(epoch: &str) -> Option<String> {
+    let seconds = epoch.parse::<i64>().ok()?;
+    let date = Utc.timestamp_opt(seconds, 0).single()?;
+    Some(datetime_format::format(&date))
+}
+
+pub(crate) fn wire_to_epoch(date: &str) -> Option<i64> {
+    datetime_format::parse(date)
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+/// Split the content of an FF4 line into its decoded `key:"value"` pairs
+fn split_attributes(s: &str) -> Result<Vec<(String, String)>> {
+    let malformed = |message: &str| Error::TaskData(message.to_string());
+    let mut attributes = vec![];
+    let mut rest = s.trim_start();
+
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .split_once(":\"")
+            .ok_or_else(|| malformed("expected key:\"value\""))?;
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(malformed(&format!("invalid attribute name '{}'", key)));
+        }
+
+        // the value ends at the first quote that is not escaped
+        let mut end = None;
+        let mut escaped = false;
+        for (idx, c) in after.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    end = Some(idx);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.ok_or_else(|| malformed(&format!("unterminated value of '{}'", key)))?;
+
+        attributes.push((key.to_string(), decode(&after[..end])?));
+        rest = after[end + 1..].trim_start();
+    }
+    Ok(attributes)
+}
+
+/// Escape a value like taskwarrior does: JSON string escapes, plus `&open;` and `&close;` for
+/// the brackets delimiting a line
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '/' => encoded.push_str("\\/"),
+            '\u{08}' => encoded.push_str("\\b"),
+            '\u{0c}' => encoded.push_str("\\f"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            '[' => encoded.push_str("&open;"),
+            ']' => encoded.push_str("&close;"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Reverse [`encode`], also accepting the `&dquot;` escape of older taskwarrior versions
+fn decode(value: &str) -> Result<String> {
+    let value = value
+        .replace("&open;", "[")
+        .replace("&close;", "]")
+        .replace("&dquot;", "\\\"");
+
+    let mut decoded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => decoded.push('"'),
+            Some('\\') => decoded.push('\\'),
+            Some('/') => decoded.push('/'),
+            Some('b') => decoded.push('\u{08}'),
+            Some('f') => decoded.push('\u{0c}'),
+            Some('n') => decoded.push('\n'),
+            Some('r') => decoded.push('\r'),
+            Some('t') => decoded.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| Error::TaskData(format!("invalid escape '\\u{}'", hex)))?;
+                decoded.push(c);
+            }
+            other => {
+                // taskwarrior keeps unknown escapes as they are
+                decoded.push('\\');
+                decoded.extend(other);
+            }
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taskwarrior::TaskWarrior;
+    use crate::uda::Uda;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    const PENDING_DATA: &str = concat!(
+        r#"[annotation_1641886872:"note:dp" description:"Dummy Task" entry:"1641834979" modified:"1641886872" project:"dummy" status:"pending" tags:"wiki" uuid:"dde3720b-003f-4776-8e15-61e5d90376af"]"#,
+        "\n",
+        r#"[annotation_1641835000:"see https:\/\/example.com &open;1&close;" depends:"dde3720b-003f-4776-8e15-61e5d90376af" description:"Say \"hi\"\ttwice" due:"1641942000" entry:"1641834979" estimate:"2.5" modified:"1641834979" status:"pending" tags:"next,wiki" uuid:"0cfe4c2b-b2d0-4bc8-9c49-b81ef9c1af5b"]"#,
+        "\n",
+        r#"[description:"Dummy Task" end:"1641933127" entry:"1641834934" modified:"1641933127" project:"dummy" status:"completed" uuid:"9d2d6a9e-0f12-4a8a-9cb0-70a7e1d1c2a3"]"#,
+        "\n",
+    );
+
+    fn udas() -> UdaRegistry {
+        let mut udas = UdaRegistry::default();
+        udas.insert(Uda::new("estimate", UdaType::Numeric));
+        udas
+    }
+
+    #[test]
+    fn read_pending_data_like_task_export() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        std::fs::write(dir.path().join("pending.data"), PENDING_DATA).unwrap();
+        let tasks = TaskData::open(dir.path())
+            .with_udas(udas())
+            .pending()
+            .expect("reading succeeds");
+
+        let fixture = include_str!("../examples/add_task.json").trim();
+        let mut expected = Task::from_str(fixture).unwrap();
+        expected.id = Some(1);
+        assert_eq!(tasks[0], expected);
+
+        assert_eq!(
+            tasks[1].to_string(),
+            concat!(
+                r#"{"id":2,"depends":["dde3720b-003f-4776-8e15-61e5d90376af"],"#,
+                r#""description":"Say \"hi\"\ttwice","due":"20220111T230000Z","#,
+                r#""entry":"20220110T171619Z","estimate":2.5,"modified":"20220110T171619Z","#,
+                r#""status":"pending","uuid":"0cfe4c2b-b2d0-4bc8-9c49-b81ef9c1af5b","#,
+                r#""annotations":[{"entry":"20220110T171640Z","#,
+                r#""description":"see https://example.com [1]"}],"tags":["next","wiki"]}"#
+            )
+        );
+
+        assert_eq!(tasks[2].id, Some(0));
+        assert_eq!(tasks[2].status, Status::Completed);
+    }
+
+    #[test]
+    fn compose_lines_as_read() {
+        for line in PENDING_DATA.lines() {
+            let task = parse_line(line, &udas()).expect("parsing succeeds");
+            assert_eq!(
+                compose_line(&task, &udas()).expect("composing succeeds"),
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn write_only_when_writable() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let tasks = vec![Task::new("Dummy [Task]").with_tag("wiki")];
+
+        let read_only = TaskData::open(dir.path());
+        assert!(read_only.write_pending(&tasks).is_err());
+        assert!(!dir.path().join("pending.data").exists());
+
+        let writable = TaskData::open(dir.path()).writable();
+        writable.write_pending(&tasks).expect("writing succeeds");
+        let read = writable.pending().expect("reading succeeds");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].description, "Dummy [Task]");
+        assert_eq!(read[0].uuid, tasks[0].uuid);
+        assert_eq!(read[0].entry.timestamp(), tasks[0].entry.timestamp());
+        assert!(writable
+            .completed()
+            .expect("missing file is empty")
+            .is_empty());
+    }
+
+    #[test]
+    fn reject_malformed_lines() {
+        let udas = UdaRegistry::default();
+        assert!(parse_line("description:\"no brackets\"", &udas).is_err());
+        assert!(parse_line("[description:\"unterminated]", &udas).is_err());
+        assert!(parse_line("[description \"no colon\"]", &udas).is_err());
+        assert!(parse_line("[annotation_x:\"bad epoch\"]", &udas).is_err());
+
+        let dir = tempdir().expect("tempdir creation succeeds");
+        std::fs::write(dir.path().join("pending.data"), "\n[status:\"pending\"]\n").unwrap();
+        let err = TaskData::open(dir.path())
+            .pending()
+            .expect_err("uuid missing");
+        assert!(err.to_string().contains("pending.data:2"));
+    }
+
+    /// The tasks of the data files in `examples/ff4` as `task export` of taskwarrior 2.6
+    /// lists them, urgency included
+    fn export_fixture() -> Vec<Task> {
+        let values: Vec<Value> =
+            serde_json::from_str(include_str!("../examples/ff4/export.json")).unwrap();
+        values
+            .into_iter()
+            .map(|value| Task::from_value(value).expect("exported task parses"))
+            .collect()
+    }
+
+    #[test]
+    fn read_data_files_like_task_export() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/ff4");
+        let mut read = TaskData::open(&dir).tasks().expect("reading succeeds");
+        let mut exported = export_fixture();
+        for task in exported.iter_mut() {
+            task.urgency = None;
+        }
+        assert_eq!(read.len(), exported.len());
+        // export lists the tasks in the same order, pending ones first
+        assert_eq!(read, exported);
+
+        // writing the tasks back yields the very same files
+        let copy = tempdir().expect("tempdir creation succeeds");
+        let data = TaskData::open(copy.path()).writable();
+        let completed = read.split_off(5);
+        data.write_pending(&read).expect("writing succeeds");
+        data.write_completed(&completed).expect("writing succeeds");
+        for file in ["pending.data", "completed.data"] {
+            assert_eq!(
+                std::fs::read_to_string(copy.path().join(file)).unwrap(),
+                std::fs::read_to_string(dir.join(file)).unwrap(),
+                "{}",
+                file
+            );
+        }
+    }
+
+    /// Compare with the output of a real `task export`, which needs taskwarrior 2.x installed
+    #[test]
+    #[ignore]
+    fn cross_check_with_task_export() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let taskwarrior = TaskWarrior::new()
+            .with_data_location(dir.path())
+            .with_taskrc(Path::new("/dev/null"));
+        let version = taskwarrior
+            .run(&["--version"], None)
+            .expect("taskwarrior is installed");
+        assert!(
+            String::from_utf8_lossy(&version).starts_with('2'),
+            "taskwarrior 2.x is installed"
+        );
+        taskwarrior
+            .run(
+                &[
+                    "add",
+                    "Say \"hi\" [now]",
+                    "+wiki",
+                    "project:a.b",
+                    "due:tomorrow",
+                ],
+                None,
+            )
+            .expect("adding succeeds");
+        taskwarrior
+            .run(&["1", "annotate", "https://example.com"], None)
+            .expect("annotating succeeds");
+        taskwarrior
+            .run(&["add", "Done already"], None)
+            .expect("adding succeeds");
+        taskwarrior
+            .run(&["2", "done"], None)
+            .expect("completing succeeds");
+
+        let mut exported = taskwarrior.export(&[]).expect("export succeeds");
+        let mut read = TaskData::open(dir.path())
+            .tasks()
+            .expect("reading succeeds");
+        for task in exported.iter_mut().chain(read.iter_mut()) {
+            task.urgency = None;
+        }
+        exported.sort_by_key(|task| task.uuid);
+        read.sort_by_key(|task| task.uuid);
+        assert_eq!(read, exported);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::Task;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Fields that can be used as `{field}` or `{slug(field)}` placeholders in a template
+const FIELDS: [&str; 6] = [
+    "uuid",
+    "short_uuid",
+    "description",
+    "project",
+    "date",
+    "status",
+];
+
+/// Template for the path of a notes file relative to the notes directory, without extension.
+///
+/// Placeholders like `{description}` are replaced by the task's values, `{slug(description)}`
+/// by their slugified form. A `/` in the template starts a subdirectory, as does a `.` in the
+/// project name, e.g. `{project}/{date}-{slug(description)}-{short_uuid}`. Path components
+/// that render empty, like `{project}` of a task without project, are left out.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FilenameTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field { name: String, slug: bool },
+}
+
+impl FilenameTemplate {
+    /// Render the path of the notes file of `task`. The file name is shortened to at most
+    /// `max_len` characters plus `ext` by truncating the description (or other free text).
+    pub fn render(&self, task: &Task, ext: &str, max_len: usize) -> PathBuf {
+        let mut components: Vec<Vec<(String, bool)>> = vec![vec![]];
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => {
+                    let mut parts = text.split('/');
+                    if let Some(first) = parts.next() {
+                        push_part(&mut components, first, false);
+                    }
+                    for part in parts {
+                        components.push(vec![]);
+                        push_part(&mut components, part, false);
+                    }
+                }
+                Segment::Field { name, slug } if name == "project" => {
+                    let project = task.project.as_deref().unwrap_or_default();
+                    let mut parts = project.split('.');
+                    if let Some(first) = parts.next() {
+                        push_part(&mut components, &field_value(first, *slug), false);
+                    }
+                    for part in parts {
+                        components.push(vec![]);
+                        push_part(&mut components, &field_value(part, *slug), false);
+                    }
+                }
+                Segment::Field { name, slug } => {
+                    let value = match name.as_str() {
+                        "uuid" => task.uuid.to_string(),
+                        "short_uuid" => short_uuid(task),
+                        "description" => task.description.clone(),
+                        "date" => task.entry.format("%Y-%m-%d").to_string(),
+                        "status" => task.status.as_str().to_string(),
+                        _ => unreachable!("fields are validated when parsing"),
+                    };
+                    let shortenable = name == "description";
+                    push_part(&mut components, &field_value(&value, *slug), shortenable);
+                }
+            }
+        }
+
+        let mut path = PathBuf::new();
+        let count = components.len();
+        for (idx, mut parts) in components.into_iter().enumerate() {
+            if idx + 1 == count {
+                shorten(&mut parts, max_len);
+            }
+            let component: String = parts.into_iter().map(|(text, _)| text).collect();
+            let component = component.trim();
+            if !component.is_empty() && component != "." && component != ".." {
+                path.push(component);
+            }
+        }
+        if path.as_os_str().is_empty() {
+            path.push(task.uuid.to_string());
+        }
+        // not `with_extension`, which would take anything after a `.` in the name for one
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = format!("{}.{}", name, ext);
+        path.set_file_name(name);
+        path
+    }
+}
+
+fn push_part(components: &mut [Vec<(String, bool)>], text: &str, shortenable: bool) {
+    if let Some(last) = components.last_mut() {
+        last.push((text.to_string(), shortenable));
+    }
+}
+
+/// The value of a field, made safe to be used within a single path component
+fn field_value(value: &str, slug: bool) -> String {
+    if slug {
+        slugify(value)
+    } else {
+        value.replace(['/', '\\', '\0'], "-")
+    }
+}
+
+/// Truncate the shortenable parts, last one first, until the total length fits `max_len`
+fn shorten(parts: &mut [(String, bool)], max_len: usize) {
+    let mut excess = parts
+        .iter()
+        .map(|(text, _)| text.chars().count())
+        .sum::<usize>()
+        .saturating_sub(max_len);
+
+    for (text, _) in parts
+        .iter_mut()
+        .rev()
+        .filter(|(_, shortenable)| *shortenable)
+    {
+        if excess == 0 {
+            break;
+        }
+        let len = text.chars().count();
+        let keep = len.saturating_sub(excess);
+        excess -= len - keep;
+        *text = text.chars().take(keep).collect::<String>();
+        *text = text.trim_end_matches(['-', ' ']).to_string();
+    }
+}
+
+/// The first block of the task's UUID, as shown by taskwarrior's short ids
+pub fn short_uuid(task: &Task) -> String {
+    task.uuid.to_string().chars().take(8).collect()
+}
+
+/// Lowercase `s` and replace every run of non-alphanumeric characters by a single `-`
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in '{}'", s))?;
+            let placeholder = rest[start + 1..start + end].trim();
+            let (name, slug) = match placeholder
+                .strip_prefix("slug(")
+                .and_then(|inner| inner.strip_suffix(')'))
+            {
+                Some(name) => (name.trim(), true),
+                None => (placeholder, false),
+            };
+            if !FIELDS.contains(&name) {
+                return Err(format!("unknown placeholder '{{{}}}'", placeholder));
+            }
+            segments.push(Segment::Field {
+                name: name.to_string(),
+                slug,
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        if segments.is_empty() {
+            return Err(String::from("template must not be empty"));
+        }
+
+        Ok(Self {
+            template: s.to_string(),
+            segments,
+        })
+    }
+}
+
+impl TryFrom<String> for FilenameTemplate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        "{date}-{slug(description)}-{short_uuid}"
+            .parse()
+            .expect("default template is valid")
+    }
+}
+
+impl std::fmt::Display for FilenameTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn task() -> Task {
+        let mut task = Task::from_str(include_str!("../examples/add_task.json").trim())
+            .expect("fixture parses");
+        task.description = String::from("Fix the Büro printer, again!");
+        task
+    }
+
+    #[test]
+    fn slugify_descriptions() {
+        assert_eq!(
+            slugify("Fix the Büro printer, again!"),
+            "fix-the-büro-printer-again"
+        );
+        assert_eq!(
+            slugify("  --leading and trailing--  "),
+            "leading-and-trailing"
+        );
+        assert_eq!(slugify("../../etc/passwd"), "etc-passwd");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn render_default_template() {
+        let task = task();
+        let path = FilenameTemplate::default().render(&task, "md", 100);
+        let expected = format!(
+            "{}-fix-the-büro-printer-again-{}.md",
+            task.entry.format("%Y-%m-%d"),
+            short_uuid(&task)
+        );
+        assert_eq!(path, PathBuf::from(expected));
+    }
+
+    #[test]
+    fn render_project_as_directories() {
+        let mut task = task();
+        let template: FilenameTemplate = "{project}/{slug(description)}".parse().unwrap();
+
+        task.project = Some(String::from("Home.Garden"));
+        assert_eq!(
+            template.render(&task, "wiki", 100),
+            Path::new("Home/Garden/fix-the-büro-printer-again.wiki")
+        );
+
+        task.project = None;
+        assert_eq!(
+            template.render(&task, "wiki", 100),
+            Path::new("fix-the-büro-printer-again.wiki")
+        );
+
+        task.project = Some(String::from(".."));
+        assert_eq!(
+            template.render(&task, "wiki", 100),
+            Path::new("fix-the-büro-printer-again.wiki")
+        );
+    }
+
+    #[test]
+    fn keep_dots_in_file_names() {
+        let mut task = task();
+        task.description = String::from("Release v1.2 prep");
+        let template: FilenameTemplate = "{short_uuid}-{description}".parse().unwrap();
+        assert_eq!(
+            template.render(&task, "md", 100),
+            PathBuf::from(format!("{}-Release v1.2 prep.md", short_uuid(&task)))
+        );
+    }
+
+    #[test]
+    fn shorten_description_to_max_length() {
+        let task = task();
+        let template: FilenameTemplate = "{slug(description)}-{short_uuid}".parse().unwrap();
+        let path = template.render(&task, "md", 16);
+        assert_eq!(
+            path,
+            PathBuf::from(format!("fix-the-{}.md", short_uuid(&task)))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_templates() {
+        assert!("{title}".parse::<FilenameTemplate>().is_err());
+        assert!("{slug(uuid}".parse::<FilenameTemplate>().is_err());
+        assert!("{description".parse::<FilenameTemplate>().is_err());
+        assert!("".parse::<FilenameTemplate>().is_err());
+        assert!("notes/{slug(project)}-{uuid}"
+            .parse::<FilenameTemplate>()
+            .is_ok());
+    }
+}
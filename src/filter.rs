@@ -0,0 +1,871 @@
+use crate::dates::DateContext;
+use crate::{datetime_format, Status, Task};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Attributes that may be abbreviated in filters, e.g. `pro:work` for `project:work`
+const ATTRIBUTES: [&str; 20] = [
+    "description",
+    "project",
+    "status",
+    "uuid",
+    "id",
+    "priority",
+    "recur",
+    "parent",
+    "tags",
+    "depends",
+    "urgency",
+    "entry",
+    "modified",
+    "due",
+    "scheduled",
+    "wait",
+    "until",
+    "start",
+    "end",
+    "imask",
+];
+
+/// Attributes holding dates
+const DATE_ATTRIBUTES: [&str; 8] = [
+    "entry",
+    "modified",
+    "due",
+    "scheduled",
+    "wait",
+    "until",
+    "start",
+    "end",
+];
+
+/// Shortest abbreviation of an attribute name taskwarrior accepts
+const ABBREVIATION_MINIMUM: usize = 2;
+
+/// A taskwarrior filter expression like `project:work +wiki due.before:2022-03-01`.
+///
+/// Supported are attribute terms with modifiers (`before`, `after`, `by`, `is`, `isnt`, `has`,
+/// `hasnt`, `startswith`, `endswith`, `word`, `noword`, `none`, `any` and their synonyms),
+/// `+tag` and `-tag` including virtual tags like `+OVERDUE`, comparisons like `due < today`
+/// and `description ~ 'regex'`, `/regex/` and plain words searching the description and
+/// annotations, ids and UUIDs, combined with `and`, `or`, `xor`, `not` and parentheses. Terms
+/// without an operator in between are and-ed. Values containing spaces or parentheses must be
+/// quoted.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Tag {
+        name: String,
+        present: bool,
+    },
+    Attribute {
+        name: String,
+        op: Op,
+        value: String,
+    },
+    /// Matches the description or an annotation
+    Regex(Regex),
+    /// Contained in the description or an annotation
+    Word(String),
+    /// Inclusive ranges of ids
+    Ids(Vec<(u64, u64)>),
+    /// The beginning of a UUID
+    Uuid(String),
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// `attribute:value`, a partial match for strings
+    Equal,
+    NotEqual,
+    Is,
+    Isnt,
+    Before,
+    After,
+    By,
+    AtLeast,
+    None,
+    Any,
+    Has,
+    Hasnt,
+    StartsWith,
+    EndsWith,
+    Word,
+    NoWord,
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+/// The value of a task attribute, typed for comparison
+enum Attribute {
+    None,
+    Text(String),
+    Date(DateTime<Utc>),
+    Number(f64),
+    List(Vec<String>),
+}
+
+impl Filter {
+    /// Whether `task` passes the filter, relative dates being relative to the current time in
+    /// the local time zone
+    pub fn matches(&self, task: &Task) -> bool {
+        self.matches_in(task, &DateContext::new())
+    }
+
+    /// Whether `task` passes the filter, dates like `today` or `eow` being evaluated in `dates`
+    pub fn matches_in(&self, task: &Task, dates: &DateContext) -> bool {
+        self.expr.eval(task, dates)
+    }
+
+    /// Whether the filter has a term on the attribute `name`, like `status:completed` for
+    /// `status`
+    pub fn mentions(&self, name: &str) -> bool {
+        self.expr.mentions(name)
+    }
+
+    /// Whether the filter has a `+name` term on the tag `name`
+    pub fn mentions_tag(&self, name: &str) -> bool {
+        self.expr.mentions_tag(name)
+    }
+
+    /// Give `task` the project and tags asked for by `project:<name>` and `+tag` terms, so a
+    /// new task shows up among the tasks of the filter. Terms joined by `or`, `xor` or under a
+    /// `not` are left alone, as are virtual tags.
+    pub fn fill_in(&self, task: &mut Task) {
+        self.expr.fill_in(task)
+    }
+}
+
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(String::from("empty filter"));
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected '{}'", token));
+        }
+        Ok(Self {
+            source: s.trim().to_string(),
+            expr,
+        })
+    }
+}
+
+impl TryFrom<String> for Filter {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Filter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Split a filter into words, keeping quoted strings together and parentheses apart
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quote = None;
+    let flush = |token: &mut String, tokens: &mut Vec<String>| {
+        if !token.is_empty() {
+            tokens.push(std::mem::take(token));
+        }
+    };
+
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => token.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => flush(&mut token, &mut tokens),
+            // parentheses within values must be quoted, except in `/regex/` terms
+            (None, '(' | ')') if !token.starts_with('/') => {
+                flush(&mut token, &mut tokens);
+                tokens.push(c.to_string());
+            }
+            (None, c) => token.push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(String::from("unterminated quote"));
+    }
+    flush(&mut token, &mut tokens);
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.xor()?;
+        while self.peek() == Some("or") {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.xor()?));
+        }
+        Ok(expr)
+    }
+
+    fn xor(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("xor") {
+            self.next();
+            expr = Expr::Xor(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            match self.peek() {
+                Some("and") => {
+                    self.next();
+                }
+                // terms without an operator in between are and-ed
+                Some(token) if !matches!(token, "or" | "xor" | ")") => {}
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if matches!(self.peek(), Some("not" | "!")) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .next()
+            .ok_or_else(|| String::from("unexpected end of filter"))?;
+        match token.as_str() {
+            "(" => {
+                let expr = self.or()?;
+                match self.next().as_deref() {
+                    Some(")") => Ok(expr),
+                    _ => Err(String::from("missing ')'")),
+                }
+            }
+            ")" | "and" | "or" | "xor" => Err(format!("unexpected '{}'", token)),
+            _ => match self.peek().and_then(infix_op) {
+                Some(op) => {
+                    self.next();
+                    let value = self
+                        .next()
+                        .ok_or_else(|| format!("missing value after '{} {}'", token, op))?;
+                    attribute_term(&token, op, &value)
+                }
+                None => term(&token),
+            },
+        }
+    }
+}
+
+fn infix_op(token: &str) -> Option<&'static str> {
+    ["<", "<=", ">", ">=", "=", "==", "!=", "~", "!~"]
+        .into_iter()
+        .find(|op| *op == token)
+}
+
+/// Parse a single term like `+tag`, `due.before:eow` or `/regex/`
+fn term(token: &str) -> Result<Expr, String> {
+    if let Some(tag) = token.strip_prefix('+') {
+        return tag_term(tag, true);
+    }
+    if let Some(tag) = token.strip_prefix('-') {
+        if !tag.is_empty() && !tag.starts_with(char::is_numeric) {
+            return tag_term(tag, false);
+        }
+    }
+    if let Some(pattern) = token
+        .strip_prefix('/')
+        .and_then(|token| token.strip_suffix('/'))
+    {
+        return Ok(Expr::Term(Term::Regex(regex(pattern)?)));
+    }
+
+    if let Some((name, value)) = token.split_once(':') {
+        let (name, modifier) = match name.split_once('.') {
+            Some((name, modifier)) => (name, Some(modifier)),
+            None => (name, None),
+        };
+        if is_identifier(name) && modifier.is_none_or(is_identifier) {
+            let op = match modifier {
+                None => Op::Equal,
+                Some(modifier) => modifier_op(modifier)?,
+            };
+            return attribute(name, op, value);
+        }
+    }
+
+    // like in taskwarrior, eight or more digits are a UUID prefix rather than an id
+    if is_uuid_prefix(token) {
+        return Ok(Expr::Term(Term::Uuid(token.to_lowercase())));
+    }
+    if let Some(ids) = parse_ids(token) {
+        return Ok(Expr::Term(Term::Ids(ids)));
+    }
+    Ok(Expr::Term(Term::Word(token.to_string())))
+}
+
+fn tag_term(tag: &str, present: bool) -> Result<Expr, String> {
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return Err(format!("invalid tag '{}'", tag));
+    }
+    Ok(Expr::Term(Term::Tag {
+        name: tag.to_string(),
+        present,
+    }))
+}
+
+/// A comparison like `due < today` or `description ~ 'regex'`
+fn attribute_term(name: &str, op: &str, value: &str) -> Result<Expr, String> {
+    if !is_identifier(name) {
+        return Err(format!("invalid attribute '{}'", name));
+    }
+    let op = match op {
+        "<" => Op::Before,
+        "<=" => Op::By,
+        ">" => Op::After,
+        ">=" => Op::AtLeast,
+        "=" | "==" => Op::Equal,
+        "!=" => Op::NotEqual,
+        "~" => Op::Matches(regex(value)?),
+        "!~" => Op::NotMatches(regex(value)?),
+        _ => unreachable!("only infix operators are passed"),
+    };
+    attribute(name, op, value)
+}
+
+fn attribute(name: &str, op: Op, value: &str) -> Result<Expr, String> {
+    let name = resolve_attribute(name);
+    let comparative = matches!(op, Op::Before | Op::After | Op::By | Op::AtLeast);
+    if DATE_ATTRIBUTES.contains(&name.as_str())
+        && (comparative || !value.is_empty() && matches!(op, Op::Equal | Op::Is))
+        && DateContext::new().parse_date(value).is_err()
+    {
+        return Err(format!("invalid date '{}' for '{}'", value, name));
+    }
+    Ok(Expr::Term(Term::Attribute {
+        name,
+        op,
+        value: value.to_string(),
+    }))
+}
+
+fn modifier_op(modifier: &str) -> Result<Op, String> {
+    Ok(match modifier {
+        "before" | "under" | "below" => Op::Before,
+        "after" | "over" | "above" => Op::After,
+        "by" => Op::By,
+        "none" => Op::None,
+        "any" => Op::Any,
+        "is" | "equals" => Op::Is,
+        "isnt" | "not" => Op::Isnt,
+        "has" | "contains" => Op::Has,
+        "hasnt" => Op::Hasnt,
+        "startswith" | "left" => Op::StartsWith,
+        "endswith" | "right" => Op::EndsWith,
+        "word" => Op::Word,
+        "noword" => Op::NoWord,
+        _ => return Err(format!("unknown modifier '{}'", modifier)),
+    })
+}
+
+fn regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| format!("invalid regex '{}': {}", pattern, err))
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The attribute `name` abbreviates, or `name` itself, e.g. a UDA
+fn resolve_attribute(name: &str) -> String {
+    if ATTRIBUTES.contains(&name) || name.len() < ABBREVIATION_MINIMUM {
+        return name.to_string();
+    }
+    let mut candidates = ATTRIBUTES.iter().filter(|attr| attr.starts_with(name));
+    match (candidates.next(), candidates.next()) {
+        (Some(attr), None) => attr.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Ids like `1`, `1,3` or `2-5`
+fn parse_ids(token: &str) -> Option<Vec<(u64, u64)>> {
+    token
+        .split(',')
+        .map(|range| match range.split_once('-') {
+            Some((from, to)) => Some((from.parse().ok()?, to.parse().ok()?)),
+            None => {
+                let id = range.parse().ok()?;
+                Some((id, id))
+            }
+        })
+        .collect()
+}
+
+fn is_uuid_prefix(token: &str) -> bool {
+    token.len() >= 8
+        && token.len() <= 36
+        && token[..8].chars().all(|c| c.is_ascii_hexdigit())
+        && token.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+impl Expr {
+    fn fill_in(&self, task: &mut Task) {
+        match self {
+            Expr::And(left, right) => {
+                left.fill_in(task);
+                right.fill_in(task);
+            }
+            Expr::Term(Term::Tag {
+                name,
+                present: true,
+            }) if !name.chars().all(|c| c.is_ascii_uppercase()) => {
+                task.tags.insert(name.clone());
+            }
+            Expr::Term(Term::Attribute {
+                name,
+                op: Op::Equal | Op::Is,
+                value,
+            }) if name == "project" && !value.is_empty() => {
+                task.project = Some(value.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn mentions(&self, attribute: &str) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) | Expr::Xor(left, right) => {
+                left.mentions(attribute) || right.mentions(attribute)
+            }
+            Expr::Not(expr) => expr.mentions(attribute),
+            Expr::Term(Term::Attribute { name, .. }) => name == attribute,
+            Expr::Term(_) => false,
+        }
+    }
+
+    fn mentions_tag(&self, tag: &str) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) | Expr::Xor(left, right) => {
+                left.mentions_tag(tag) || right.mentions_tag(tag)
+            }
+            Expr::Not(expr) => expr.mentions_tag(tag),
+            Expr::Term(Term::Tag {
+                name,
+                present: true,
+            }) => name == tag,
+            Expr::Term(_) => false,
+        }
+    }
+
+    fn eval(&self, task: &Task, dates: &DateContext) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(task, dates) && b.eval(task, dates),
+            Expr::Or(a, b) => a.eval(task, dates) || b.eval(task, dates),
+            Expr::Xor(a, b) => a.eval(task, dates) != b.eval(task, dates),
+            Expr::Not(expr) => !expr.eval(task, dates),
+            Expr::Term(term) => term.eval(task, dates),
+        }
+    }
+}
+
+impl Term {
+    fn eval(&self, task: &Task, dates: &DateContext) -> bool {
+        match self {
+            Term::Tag { name, present } => has_tag(task, name, dates) == *present,
+            Term::Attribute { name, op, value } => {
+                compare(&attribute_value(task, name), op, value, dates)
+            }
+            Term::Regex(regex) => searchable_text(task).any(|text| regex.is_match(text)),
+            Term::Word(word) => searchable_text(task).any(|text| text.contains(word.as_str())),
+            Term::Ids(ids) => task.id.is_some_and(|id| {
+                ids.iter()
+                    .any(|(from, to)| (*from..=*to).contains(&id) && id != 0)
+            }),
+            Term::Uuid(prefix) => task.uuid.to_string().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+fn searchable_text(task: &Task) -> impl Iterator<Item = &str> {
+    std::iter::once(task.description.as_str()).chain(
+        task.annotations
+            .iter()
+            .map(|annotation| annotation.description.as_str()),
+    )
+}
+
+/// Whether `task` has the tag `name`, or the virtual tag if `name` is one
+fn has_tag(task: &Task, name: &str, dates: &DateContext) -> bool {
+    let now = dates.now();
+    let today = dates.local_date(now);
+    let due_date = task.due.map(|due| dates.local_date(due));
+    let pending = matches!(task.status, Status::Pending | Status::Waiting);
+    match name {
+        "PENDING" => task.status == Status::Pending,
+        "COMPLETED" => task.status == Status::Completed,
+        "DELETED" => task.status == Status::Deleted,
+        "WAITING" => task.status == Status::Waiting,
+        "RECURRING" => task.status == Status::Recurring,
+        "ACTIVE" => pending && task.start.is_some(),
+        "TAGGED" => !task.tags.is_empty(),
+        "ANNOTATED" => !task.annotations.is_empty(),
+        "PROJECT" => task.project.is_some(),
+        "PRIORITY" => task.priority.is_some(),
+        "SCHEDULED" => task.scheduled.is_some(),
+        "UNTIL" => task.until.is_some(),
+        "BLOCKED" => !task.depends.is_empty(),
+        "OVERDUE" => pending && task.due.is_some_and(|due| due < now),
+        "TODAY" => pending && due_date == Some(today),
+        "TOMORROW" => pending && due_date == Some(today + Duration::days(1)),
+        "YESTERDAY" => pending && due_date == Some(today - Duration::days(1)),
+        "DUE" => pending && task.due.is_some_and(|due| due < now + Duration::days(7)),
+        _ => task.has_tag(name),
+    }
+}
+
+fn attribute_value(task: &Task, name: &str) -> Attribute {
+    let text = |value: &Option<String>| match value {
+        Some(value) => Attribute::Text(value.clone()),
+        None => Attribute::None,
+    };
+    let date = |value: Option<DateTime<Utc>>| match value {
+        Some(value) => Attribute::Date(value),
+        None => Attribute::None,
+    };
+    match name {
+        "description" => Attribute::Text(task.description.clone()),
+        "project" => text(&task.project),
+        "status" => Attribute::Text(task.status.as_str().to_string()),
+        "uuid" => Attribute::Text(task.uuid.to_string()),
+        "priority" => text(&task.priority.as_ref().map(|p| p.as_str().to_string())),
+        "recur" => text(&task.recur),
+        "id" => task
+            .id
+            .map_or(Attribute::None, |id| Attribute::Number(id as f64)),
+        "urgency" => task.urgency.map_or(Attribute::None, Attribute::Number),
+        "tags" => Attribute::List(task.tags.iter().cloned().collect()),
+        "depends" => Attribute::List(task.depends.iter().map(|uuid| uuid.to_string()).collect()),
+        "entry" => Attribute::Date(task.entry),
+        "modified" => Attribute::Date(task.modified),
+        "due" => date(task.due),
+        "scheduled" => date(task.scheduled),
+        "wait" => date(task.wait),
+        "until" => date(task.until),
+        "start" => date(task.start),
+        "end" => date(task.end),
+        _ => match task.unknown_fields.get(name) {
+            Some(Value::String(s)) => match datetime_format::parse(s) {
+                Ok(date) => Attribute::Date(date),
+                Err(_) => Attribute::Text(s.clone()),
+            },
+            Some(Value::Number(n)) => n.as_f64().map_or(Attribute::None, Attribute::Number),
+            Some(Value::Bool(b)) => Attribute::Text(b.to_string()),
+            _ => Attribute::None,
+        },
+    }
+}
+
+fn compare(attribute: &Attribute, op: &Op, value: &str, dates: &DateContext) -> bool {
+    let is_set = match attribute {
+        Attribute::None => false,
+        Attribute::Text(text) => !text.is_empty(),
+        Attribute::List(list) => !list.is_empty(),
+        Attribute::Date(_) | Attribute::Number(_) => true,
+    };
+    match op {
+        Op::None => return !is_set,
+        Op::Any => return is_set,
+        Op::Equal | Op::Is if value.is_empty() => return !is_set,
+        Op::NotEqual | Op::Isnt if value.is_empty() => return is_set,
+        Op::NotEqual => return !compare(attribute, &Op::Equal, value, dates),
+        Op::Isnt => return !compare(attribute, &Op::Is, value, dates),
+        Op::Hasnt => return !compare(attribute, &Op::Has, value, dates),
+        Op::NoWord => return !compare(attribute, &Op::Word, value, dates),
+        Op::NotMatches(regex) => {
+            return !compare(attribute, &Op::Matches(regex.clone()), value, dates)
+        }
+        _ => {}
+    }
+
+    match attribute {
+        Attribute::None => false,
+        Attribute::Text(text) => compare_text(text, op, value),
+        Attribute::List(list) => match op {
+            Op::Matches(regex) => list.iter().any(|item| regex.is_match(item)),
+            Op::Equal | Op::Is | Op::Has | Op::Word => list.iter().any(|item| item == value),
+            _ => false,
+        },
+        Attribute::Date(date) => match dates.parse_date(value) {
+            Ok(other) => match op {
+                // a date without time of day matches the whole day
+                Op::Equal => dates.local_date(*date) == dates.local_date(other),
+                Op::Is => *date == other,
+                Op::Before => *date < other,
+                Op::After => *date > other,
+                Op::By => *date <= other,
+                Op::AtLeast => *date >= other,
+                _ => false,
+            },
+            Err(_) => match op {
+                Op::Matches(regex) => regex.is_match(&datetime_format::format(date)),
+                _ => false,
+            },
+        },
+        Attribute::Number(number) => match value.parse::<f64>() {
+            Ok(other) => match op {
+                Op::Equal | Op::Is => *number == other,
+                Op::Before => *number < other,
+                Op::After => *number > other,
+                Op::By => *number <= other,
+                Op::AtLeast => *number >= other,
+                _ => compare_text(&number.to_string(), op, value),
+            },
+            Err(_) => compare_text(&number.to_string(), op, value),
+        },
+    }
+}
+
+fn compare_text(text: &str, op: &Op, value: &str) -> bool {
+    match op {
+        // like taskwarrior, `project:work` also matches `work.notes`
+        Op::Equal => text.starts_with(value),
+        Op::Is => text == value,
+        Op::Has => text.contains(value),
+        Op::StartsWith => text.starts_with(value),
+        Op::EndsWith => text.ends_with(value),
+        Op::Word => text
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == value),
+        Op::Before => text < value,
+        Op::After => text > value,
+        Op::By => text <= value,
+        Op::AtLeast => text >= value,
+        Op::Matches(regex) => regex.is_match(text),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Annotation;
+    use chrono::TimeZone;
+
+    /// Tuesday, March 1st 2022, noon in UTC
+    fn dates() -> DateContext {
+        let now = Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap();
+        DateContext::at(now).with_zone("UTC".parse().unwrap())
+    }
+
+    fn at(date: &str) -> DateTime<Utc> {
+        dates().parse_date(date).expect("valid date")
+    }
+
+    fn matches(filter: &str, task: &Task) -> bool {
+        Filter::from_str(filter)
+            .expect("valid filter")
+            .matches_in(task, &dates())
+    }
+
+    fn work_task() -> Task {
+        let mut task = Task::new("Write the quarterly report")
+            .with_tag("wiki")
+            .with_tag("office");
+        task.id = Some(3);
+        task.project = Some(String::from("work.reports"));
+        task.due = Some(at("2022-03-02T09:00"));
+        task.annotations
+            .push(Annotation::new("ask about the budget"));
+        task.unknown_fields
+            .insert(String::from("estimate"), Value::from(4));
+        task
+    }
+
+    #[test]
+    fn match_attributes_and_tags() {
+        let task = work_task();
+        assert!(matches(
+            "project:work +wiki due.before:2022-03-05 status:pending",
+            &task
+        ));
+        assert!(matches("pro:work.reports -home", &task));
+        assert!(!matches("project:home", &task));
+        assert!(!matches("project.is:work", &task));
+        assert!(matches("project.isnt:work", &task));
+        assert!(!matches("project:", &task));
+        assert!(matches("priority: recur.none:", &task));
+        assert!(matches("tags.has:office tags.hasnt:home", &task));
+        assert!(matches("description.word:quarterly", &task));
+        assert!(!matches("description.word:quarter", &task));
+        assert!(matches(
+            "description.startswith:Write description.endswith:report",
+            &task
+        ));
+        assert!(matches("estimate.over:3 estimate.under:5", &task));
+        assert!(!matches("-wiki", &task));
+    }
+
+    #[test]
+    fn compare_dates() {
+        let task = work_task();
+        assert!(matches("due:tomorrow", &task));
+        assert!(!matches("due.after:today due.by:tomorrow", &task));
+        assert!(matches("due.after:today due.before:2022-03-03", &task));
+        assert!(matches("due > now and due <= 2022-03-02T09:00", &task));
+        assert!(!matches("due.is:2022-03-02", &task));
+        assert!(matches("+TOMORROW +DUE -OVERDUE", &task));
+        assert!(matches("entry.any: end.none:", &task));
+        assert!(matches("due.before:eow due.after:now+18h", &task));
+        assert!(matches("due.by:tomorrow+9h due.after:socw-P1W", &task));
+        assert!(!matches("due.before:tomorrow+8h", &task));
+        assert!(Filter::from_str("due.before:sometime").is_err());
+    }
+
+    #[test]
+    fn combine_with_operators() {
+        let task = work_task();
+        assert!(matches("+home or +office", &task));
+        assert!(!matches("+home or +garden", &task));
+        assert!(matches("+wiki xor +home", &task));
+        assert!(!matches("+wiki xor +office", &task));
+        assert!(matches("not +home", &task));
+        assert!(matches("! project:home", &task));
+        assert!(matches("(project:home or project:work) and +wiki", &task));
+        assert!(!matches("project:home or project:work and +home", &task));
+        assert!(matches("(+home or +wiki) (+office)", &task));
+    }
+
+    #[test]
+    fn search_text_with_regexes_and_words() {
+        let task = work_task();
+        assert!(matches("/quarter(ly)?/", &task));
+        assert!(matches("budget", &task));
+        assert!(matches("'the quarterly'", &task));
+        assert!(!matches("/^report/", &task));
+        assert!(matches("description ~ '^Write.*report$'", &task));
+        assert!(matches("project !~ home", &task));
+        assert!(matches("tags ~ ^off", &task));
+    }
+
+    #[test]
+    fn match_ids_and_uuids() {
+        let task = work_task();
+        assert!(matches("3", &task));
+        assert!(matches("1,2-4", &task));
+        assert!(!matches("1,2", &task));
+        let uuid = task.uuid.to_string();
+        assert!(matches(&uuid[..8], &task));
+        assert!(matches(&uuid, &task));
+        let mut task = task;
+        task.uuid = "12345678-9abc-4def-8123-456789abcdef".parse().unwrap();
+        assert!(matches("12345678", &task));
+    }
+
+    #[test]
+    fn find_mentioned_attributes() {
+        let filter = Filter::from_str("+wiki (stat:completed or not due.before:today)").unwrap();
+        assert!(filter.mentions("status"));
+        assert!(filter.mentions("due"));
+        assert!(!filter.mentions("project"));
+        assert!(filter.mentions_tag("wiki"));
+        assert!(!filter.mentions_tag("next"));
+        assert!(!Filter::from_str("-wiki").unwrap().mentions_tag("wiki"));
+    }
+
+    #[test]
+    fn fill_in_project_and_tags() {
+        let filter =
+            Filter::from_str("pro:work.reports +wiki +OVERDUE (+home or +office) -next").unwrap();
+        let mut task = Task::new("Draft the summary");
+        filter.fill_in(&mut task);
+        assert_eq!(task.project.as_deref(), Some("work.reports"));
+        assert_eq!(task.tags.len(), 1);
+        assert!(task.has_tag("wiki"));
+    }
+
+    #[test]
+    fn reject_malformed_filters() {
+        for filter in [
+            "",
+            "(+wiki",
+            "+wiki)",
+            "project:work or",
+            "/[/",
+            "due.sometime:today",
+            "'unterminated",
+            "+",
+            "due <",
+        ] {
+            assert!(Filter::from_str(filter).is_err(), "{} is invalid", filter);
+        }
+    }
+
+    #[test]
+    fn display_the_expression() {
+        let filter = Filter::from_str(" project:work +wiki ").unwrap();
+        assert_eq!(filter.to_string(), "project:work +wiki");
+        assert_eq!(filter, Filter::from_str("project:work +wiki").unwrap());
+    }
+}
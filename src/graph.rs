@@ -0,0 +1,588 @@
+use crate::config::Config;
+use crate::filename::short_uuid;
+use crate::filter::Filter;
+use crate::notes::{with_section, NotesFile};
+use crate::storage::Storage;
+use crate::{Error, Result, Status, Task};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const SECTION_HEADING: &str = "## Dependencies";
+
+/// The dependencies among a set of tasks as given by their `depends` attribute. Dependencies
+/// on tasks outside the set are ignored.
+#[derive(Clone, Debug)]
+pub struct TaskGraph {
+    tasks: Vec<Task>,
+    /// For each task the indices of the tasks it depends on
+    dependencies: Vec<Vec<usize>>,
+    /// For each task the indices of the tasks depending on it
+    dependents: Vec<Vec<usize>>,
+    index: HashMap<Uuid, usize>,
+}
+
+impl TaskGraph {
+    pub fn new(tasks: Vec<Task>) -> Self {
+        let index: HashMap<_, _> = tasks
+            .iter()
+            .enumerate()
+            .map(|(idx, task)| (task.uuid, idx))
+            .collect();
+        let mut dependencies = vec![vec![]; tasks.len()];
+        let mut dependents = vec![vec![]; tasks.len()];
+        for (idx, task) in tasks.iter().enumerate() {
+            for dependency in task.depends.iter().filter_map(|uuid| index.get(uuid)) {
+                if !dependencies[idx].contains(dependency) {
+                    dependencies[idx].push(*dependency);
+                    dependents[*dependency].push(idx);
+                }
+            }
+        }
+        Self {
+            tasks,
+            dependencies,
+            dependents,
+            index,
+        }
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    pub fn get(&self, uuid: Uuid) -> Option<&Task> {
+        self.index.get(&uuid).map(|idx| &self.tasks[*idx])
+    }
+
+    /// The tasks `uuid` depends on
+    pub fn dependencies(&self, uuid: Uuid) -> Vec<&Task> {
+        self.related(uuid, &self.dependencies)
+    }
+
+    /// The tasks depending on `uuid`
+    pub fn dependents(&self, uuid: Uuid) -> Vec<&Task> {
+        self.related(uuid, &self.dependents)
+    }
+
+    fn related(&self, uuid: Uuid, edges: &[Vec<usize>]) -> Vec<&Task> {
+        match self.index.get(&uuid) {
+            Some(idx) => edges[*idx].iter().map(|idx| &self.tasks[*idx]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Whether `uuid` is neither completed nor deleted and depends on a task that is neither
+    pub fn is_blocked(&self, uuid: Uuid) -> bool {
+        self.index
+            .get(&uuid)
+            .is_some_and(|idx| self.has_open_edge(*idx, &self.dependencies))
+    }
+
+    /// Whether `uuid` is neither completed nor deleted and a task that is neither depends on it
+    pub fn is_blocking(&self, uuid: Uuid) -> bool {
+        self.index
+            .get(&uuid)
+            .is_some_and(|idx| self.has_open_edge(*idx, &self.dependents))
+    }
+
+    fn has_open_edge(&self, idx: usize, edges: &[Vec<usize>]) -> bool {
+        is_open(&self.tasks[idx]) && edges[idx].iter().any(|idx| is_open(&self.tasks[*idx]))
+    }
+
+    /// All blocked tasks, see [`is_blocked`](Self::is_blocked)
+    pub fn blocked(&self) -> Vec<&Task> {
+        self.tasks
+            .iter()
+            .filter(|task| self.is_blocked(task.uuid))
+            .collect()
+    }
+
+    /// All blocking tasks, see [`is_blocking`](Self::is_blocking)
+    pub fn blocking(&self) -> Vec<&Task> {
+        self.tasks
+            .iter()
+            .filter(|task| self.is_blocking(task.uuid))
+            .collect()
+    }
+
+    /// Groups of tasks that depend on each other in a circle, directly or indirectly
+    pub fn cycles(&self) -> Vec<Vec<&Task>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            next: 0,
+            indices: vec![None; self.tasks.len()],
+            lowlinks: vec![0; self.tasks.len()],
+            stack: vec![],
+            on_stack: vec![false; self.tasks.len()],
+            components: vec![],
+        };
+        for idx in 0..self.tasks.len() {
+            if tarjan.indices[idx].is_none() {
+                tarjan.visit(idx);
+            }
+        }
+
+        let mut cycles: Vec<Vec<usize>> = tarjan
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.dependencies[component[0]].contains(&component[0])
+            })
+            .collect();
+        for cycle in cycles.iter_mut() {
+            cycle.sort_unstable();
+        }
+        cycles.sort_unstable();
+        cycles
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|idx| &self.tasks[idx]).collect())
+            .collect()
+    }
+
+    /// All tasks, each after the tasks it depends on, preferring tasks that come first in the
+    /// original order. Fails if tasks depend on each other in a circle.
+    pub fn topological_order(&self) -> Result<Vec<&Task>> {
+        let mut remaining: Vec<usize> = self.dependencies.iter().map(Vec::len).collect();
+        let mut ready: Vec<usize> = (0..self.tasks.len())
+            .filter(|idx| remaining[*idx] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.tasks.len());
+
+        while let Some(idx) = ready.iter().copied().min() {
+            ready.retain(|other| *other != idx);
+            order.push(idx);
+            for dependent in &self.dependents[idx] {
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+
+        if order.len() < self.tasks.len() {
+            let cycle = self.cycles().into_iter().next().unwrap_or_default();
+            let tasks: Vec<_> = cycle.iter().map(|task| short_uuid(task)).collect();
+            return Err(Error::Graph(format!(
+                "tasks {} depend on each other in a circle",
+                tasks.join(", ")
+            )));
+        }
+        Ok(order.into_iter().map(|idx| &self.tasks[idx]).collect())
+    }
+
+    /// The longest chain of tasks that are neither completed nor deleted, each depending on the
+    /// one before, i.e. the work that determines when the last of them can be done
+    pub fn critical_path(&self) -> Result<Vec<&Task>> {
+        let order = self.topological_order()?;
+        // length of the longest chain ending in each task and the task before it
+        let mut chains: HashMap<Uuid, (usize, Option<Uuid>)> = HashMap::new();
+        for task in order.iter().filter(|task| is_open(task)) {
+            let previous = self
+                .dependencies(task.uuid)
+                .into_iter()
+                .filter_map(|dependency| Some((chains.get(&dependency.uuid)?.0, dependency.uuid)))
+                .fold(
+                    None,
+                    |longest: Option<(usize, Uuid)>, (len, uuid)| match longest {
+                        Some((longest_len, _)) if longest_len >= len => longest,
+                        _ => Some((len, uuid)),
+                    },
+                );
+            let len = previous.map_or(1, |(len, _)| len + 1);
+            chains.insert(task.uuid, (len, previous.map(|(_, uuid)| uuid)));
+        }
+
+        let mut end = None;
+        for task in order.iter().filter(|task| is_open(task)) {
+            let len = chains[&task.uuid].0;
+            if end.is_none_or(|(longest, _)| len > longest) {
+                end = Some((len, task.uuid));
+            }
+        }
+        let mut path = vec![];
+        let mut next = end.map(|(_, uuid)| uuid);
+        while let Some(uuid) = next {
+            path.push(
+                self.get(uuid)
+                    .expect("chains only contain tasks of the graph"),
+            );
+            next = chains[&uuid].1;
+        }
+        path.reverse();
+        Ok(path)
+    }
+
+    /// The graph in the Graphviz DOT language, an arrow pointing from each task to the tasks
+    /// depending on it
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph tasks {\n    rankdir=LR;\n    node [shape=box];\n");
+        for task in &self.tasks {
+            let style = match is_open(task) {
+                true => "",
+                false => ", style=dashed, fontcolor=gray",
+            };
+            dot.push_str(&format!(
+                "    {} [label=\"{}\"{}];\n",
+                node_id(task),
+                label(task).replace('\\', "\\\\").replace('"', "\\\""),
+                style
+            ));
+        }
+        for (task, dependents) in self.tasks.iter().zip(&self.dependents) {
+            for dependent in dependents {
+                dot.push_str(&format!(
+                    "    {} -> {};\n",
+                    node_id(task),
+                    node_id(&self.tasks[*dependent])
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph as a Mermaid flowchart, an arrow pointing from each task to the tasks
+    /// depending on it
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart LR\n");
+        for task in &self.tasks {
+            let class = match is_open(task) {
+                true => "",
+                false => ":::done",
+            };
+            mermaid.push_str(&format!(
+                "    {}[\"{}\"]{}\n",
+                node_id(task),
+                label(task).replace('"', "#quot;"),
+                class
+            ));
+        }
+        for (task, dependents) in self.tasks.iter().zip(&self.dependents) {
+            for dependent in dependents {
+                mermaid.push_str(&format!(
+                    "    {} --> {}\n",
+                    node_id(task),
+                    node_id(&self.tasks[*dependent])
+                ));
+            }
+        }
+        if self.tasks.iter().any(|task| !is_open(task)) {
+            mermaid.push_str("    classDef done stroke-dasharray: 5 5, color: gray\n");
+        }
+        mermaid
+    }
+}
+
+impl From<Vec<Task>> for TaskGraph {
+    fn from(tasks: Vec<Task>) -> Self {
+        Self::new(tasks)
+    }
+}
+
+/// Tarjan's algorithm for the strongly connected components of a graph
+struct Tarjan<'a> {
+    graph: &'a TaskGraph,
+    next: usize,
+    indices: Vec<Option<usize>>,
+    lowlinks: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, idx: usize) {
+        self.indices[idx] = Some(self.next);
+        self.lowlinks[idx] = self.next;
+        self.next += 1;
+        self.stack.push(idx);
+        self.on_stack[idx] = true;
+
+        for &dependency in &self.graph.dependencies[idx] {
+            match self.indices[dependency] {
+                None => {
+                    self.visit(dependency);
+                    self.lowlinks[idx] = self.lowlinks[idx].min(self.lowlinks[dependency]);
+                }
+                Some(index) if self.on_stack[dependency] => {
+                    self.lowlinks[idx] = self.lowlinks[idx].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.lowlinks[idx]) == self.indices[idx] {
+            let mut component = vec![];
+            while let Some(member) = self.stack.pop() {
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == idx {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+fn is_open(task: &Task) -> bool {
+    !matches!(task.status, Status::Completed | Status::Deleted)
+}
+
+fn node_id(task: &Task) -> String {
+    format!("t{}", task.uuid.to_simple())
+}
+
+/// The description, preceded by the id of pending tasks
+fn label(task: &Task) -> String {
+    match task.id {
+        Some(id) if id > 0 => format!("{}: {}", id, task.description),
+        _ => task.description.clone(),
+    }
+}
+
+/// The diagram language a [`TaskGraph`] is rendered in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// Mermaid flowcharts, rendered by many markdown viewers
+    #[default]
+    Mermaid,
+    /// The Graphviz DOT language
+    Dot,
+}
+
+impl GraphFormat {
+    pub fn render(&self, graph: &TaskGraph) -> String {
+        match self {
+            GraphFormat::Mermaid => graph.to_mermaid(),
+            GraphFormat::Dot => graph.to_dot(),
+        }
+    }
+
+    /// The info string of fenced code blocks in this language
+    fn code_block_language(&self) -> &'static str {
+        match self {
+            GraphFormat::Mermaid => "mermaid",
+            GraphFormat::Dot => "dot",
+        }
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            _ => Err(format!("unknown graph format '{}', use mermaid or dot", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code_block_language())
+    }
+}
+
+/// Draw the dependencies among the tasks in `storage` passing `filter`, or among all tasks
+/// neither completed nor deleted without one. The diagram is written to `output`, or with
+/// `file` to the dependencies section of that notes file, relative to the notes directory.
+pub fn run<W: Write>(
+    config: &Config,
+    storage: &dyn Storage,
+    filter: Option<&Filter>,
+    format: GraphFormat,
+    file: Option<&Path>,
+    output: &mut W,
+) -> Result<()> {
+    let dates = config.date_context();
+    let mut tasks = storage.load()?;
+    tasks.retain(|task| match filter {
+        Some(filter) => filter.matches_in(task, &dates),
+        None => is_open(task),
+    });
+    tasks.sort_by_key(|task| (task.id.filter(|id| *id > 0).is_none(), task.id, task.entry));
+    let diagram = format.render(&TaskGraph::new(tasks));
+
+    let path = match file {
+        Some(file) => config.notes_dir.join(file),
+        None => {
+            return write!(output, "{}", diagram).map_err(Error::io("cannot write graph"));
+        }
+    };
+    let path = match path.extension() {
+        Some(_) => path,
+        None => path.with_extension(&config.notes_ext),
+    };
+    let notes_file = match path.exists() {
+        true => NotesFile::read(&path)?,
+        false => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(Error::io(format!(
+                    "cannot create notes directory {}",
+                    dir.display()
+                )))?;
+            }
+            NotesFile::new(&path)
+        }
+    };
+    let section = format!(
+        "{}\n\n```{}\n{}```\n",
+        SECTION_HEADING,
+        format.code_block_language(),
+        diagram
+    );
+    let content = with_section(notes_file.content(), SECTION_HEADING, &section);
+    notes_file.with_content(&content).write()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ff4::TaskData;
+    use tempfile::tempdir;
+
+    /// Design blocks Build, which blocks Ship; Docs is independent and Cleanup is done already
+    fn project() -> Vec<Task> {
+        let design = Task::new("Design");
+        let mut build = Task::new("Build");
+        build.depends = vec![design.uuid];
+        let mut ship = Task::new("Ship \"v1\"");
+        let mut cleanup = Task::new("Cleanup");
+        cleanup.status = Status::Completed;
+        ship.depends = vec![build.uuid, cleanup.uuid];
+        let docs = Task::new("Docs");
+        let mut tasks = vec![ship, docs, build, design, cleanup];
+        for (idx, task) in tasks.iter_mut().enumerate() {
+            task.id = Some(idx as u64 + 1);
+        }
+        tasks
+    }
+
+    fn descriptions(tasks: &[&Task]) -> Vec<String> {
+        tasks.iter().map(|task| task.description.clone()).collect()
+    }
+
+    #[test]
+    fn answer_blocked_and_blocking_queries() {
+        let graph = TaskGraph::new(project());
+        let ship = graph.tasks()[0].uuid;
+        let cleanup = graph.tasks()[4].uuid;
+
+        assert_eq!(descriptions(&graph.blocked()), ["Ship \"v1\"", "Build"]);
+        assert_eq!(descriptions(&graph.blocking()), ["Build", "Design"]);
+        assert!(!graph.is_blocking(cleanup));
+        assert_eq!(
+            descriptions(&graph.dependencies(ship)),
+            ["Build", "Cleanup"]
+        );
+        assert_eq!(descriptions(&graph.dependents(cleanup)), ["Ship \"v1\""]);
+        assert!(graph.dependents(Uuid::nil()).is_empty());
+    }
+
+    #[test]
+    fn order_tasks_and_find_the_critical_path() {
+        let graph = TaskGraph::new(project());
+        assert!(graph.cycles().is_empty());
+        assert_eq!(
+            descriptions(&graph.topological_order().unwrap()),
+            ["Docs", "Design", "Build", "Cleanup", "Ship \"v1\""]
+        );
+        assert_eq!(
+            descriptions(&graph.critical_path().unwrap()),
+            ["Design", "Build", "Ship \"v1\""]
+        );
+        assert!(TaskGraph::new(vec![]).critical_path().unwrap().is_empty());
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let mut tasks = project();
+        let ship = tasks[0].uuid;
+        tasks[3].depends.push(ship);
+        let mut selfish = Task::new("Selfish");
+        selfish.depends = vec![selfish.uuid];
+        tasks.push(selfish);
+        let graph = TaskGraph::new(tasks);
+
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 2);
+        assert_eq!(descriptions(&cycles[0]), ["Ship \"v1\"", "Build", "Design"]);
+        assert_eq!(descriptions(&cycles[1]), ["Selfish"]);
+        let err = graph.topological_order().expect_err("cycle");
+        assert!(err.to_string().contains("in a circle"));
+        assert!(graph.critical_path().is_err());
+    }
+
+    #[test]
+    fn render_diagrams() {
+        let graph = TaskGraph::new(project());
+        let id = |idx: usize| node_id(&graph.tasks()[idx]);
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains(&format!("    {}[\"1: Ship #quot;v1#quot;\"]\n", id(0))));
+        assert!(mermaid.contains(&format!("    {}[\"5: Cleanup\"]:::done\n", id(4))));
+        assert!(mermaid.contains(&format!("    {} --> {}\n", id(3), id(2))));
+        assert!(mermaid.contains(&format!("    {} --> {}\n", id(4), id(0))));
+        assert!(mermaid.ends_with("classDef done stroke-dasharray: 5 5, color: gray\n"));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph tasks {\n"));
+        assert!(dot.contains(&format!("    {} [label=\"1: Ship \\\"v1\\\"\"];\n", id(0))));
+        assert!(dot.contains(&format!(
+            "    {} [label=\"5: Cleanup\", style=dashed, fontcolor=gray];\n",
+            id(4)
+        )));
+        assert!(dot.contains(&format!("    {} -> {};\n", id(2), id(0))));
+        assert!(dot.ends_with("}\n"));
+
+        assert_eq!("dot".parse(), Ok(GraphFormat::Dot));
+        assert!("png".parse::<GraphFormat>().is_err());
+    }
+
+    #[test]
+    fn write_the_diagram_into_a_notes_file() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let mut config = Config::default();
+        config.notes_dir = dir.path().join("notes");
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        let storage = TaskData::open(&dir.path().join("data")).writable();
+        let mut tasks = project();
+        tasks[1].project = Some(String::from("docs"));
+        storage.save(&tasks).expect("saving succeeds");
+
+        let path = config.notes_dir.join("projects/launch.md");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "# Launch\n\n## Dependencies\n\nold\n\n## Links\n").unwrap();
+        let filter = Filter::from_str("project.not:docs status:pending").unwrap();
+        run(
+            &config,
+            &storage,
+            Some(&filter),
+            GraphFormat::Mermaid,
+            Some(Path::new("projects/launch")),
+            &mut vec![],
+        )
+        .expect("writing the graph succeeds");
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# Launch\n\n## Dependencies\n\n```mermaid\nflowchart LR\n"));
+        assert!(content.contains("Design"));
+        assert!(!content.contains("Docs"));
+        assert!(!content.contains("Cleanup"));
+        assert!(content.ends_with("```\n\n## Links\n"));
+
+        let mut output = vec![];
+        run(&config, &storage, None, GraphFormat::Dot, None, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("digraph tasks {\n"));
+        assert!(output.contains("Docs"));
+        assert!(!output.contains("Cleanup"));
+    }
+}
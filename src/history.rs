@@ -0,0 +1,465 @@
+use crate::config::Config;
+use crate::ff4::parse_line;
+use crate::hooks::Hooks;
+use crate::notes::{with_section, NotesFile};
+use crate::uda::UdaRegistry;
+use crate::{datetime_format, Error, Result, Task};
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Heading of the history section appended to notes files
+const SECTION_HEADING: &str = "## History";
+
+/// Attributes left out of diffs, as they change with every modification
+const IGNORED_ATTRIBUTES: [&str; 3] = ["id", "urgency", "modified"];
+
+/// A single change recorded in taskwarrior's `undo.data`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub time: DateTime<Utc>,
+    /// The task before the change, `None` if it has been added
+    pub old: Option<Task>,
+    /// The task after the change
+    pub new: Task,
+}
+
+/// Parse the content of an `undo.data` file, a sequence of blocks like
+///
+/// ```text
+/// time 1641886872
+/// old [description:"Dummy Task" ...]
+/// new [description:"Dummy Task" tags:"wiki" ...]
+/// ---
+/// ```
+///
+/// where `old` is missing for added tasks.
+pub fn parse_undo_data(content: &str, udas: &UdaRegistry) -> Result<Vec<Transaction>> {
+    let mut transactions = vec![];
+    let mut time = None;
+    let mut old = None;
+    let mut new = None;
+
+    for (idx, line) in content.lines().enumerate() {
+        let malformed =
+            |message: String| Error::TaskData(format!("undo.data:{}: {}", idx + 1, message));
+        let task = |line: &str| {
+            parse_line(line, udas).map_err(|err| match err {
+                Error::TaskData(message) => malformed(message),
+                err => malformed(err.chain()),
+            })
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        } else if line == "---" {
+            match (time.take(), new.take()) {
+                (Some(time), Some(new)) => transactions.push(Transaction {
+                    time,
+                    old: old.take(),
+                    new,
+                }),
+                _ => return Err(malformed(String::from("incomplete transaction"))),
+            }
+        } else if let Some(epoch) = line.strip_prefix("time ") {
+            let parsed = epoch
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single());
+            time = Some(parsed.ok_or_else(|| malformed(format!("invalid time '{}'", epoch)))?);
+        } else if let Some(line) = line.strip_prefix("old ") {
+            old = Some(task(line)?);
+        } else if let Some(line) = line.strip_prefix("new ") {
+            new = Some(task(line)?);
+        } else {
+            return Err(malformed(format!("unexpected line '{}'", line)));
+        }
+    }
+
+    if time.is_some() || new.is_some() {
+        return Err(Error::TaskData(String::from(
+            "undo.data: last transaction is not terminated by '---'",
+        )));
+    }
+    Ok(transactions)
+}
+
+/// The transactions in `undo.data` in the data location `dir`, none if there is no such file
+pub fn read_undo_data(dir: &Path, udas: &UdaRegistry) -> Result<Vec<Transaction>> {
+    let path = dir.join("undo.data");
+    match std::fs::read_to_string(&path) {
+        Ok(content) => parse_undo_data(&content, udas),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(Error::io(format!("cannot read {}", path.display()))(err)),
+    }
+}
+
+/// A changed task attribute, values formatted for display
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => String::from("(none)"),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+/// The changes of the attributes of `new` compared to `old`, all set attributes if there is
+/// no `old` task
+pub fn task_diff(old: Option<&Task>, new: &Task) -> Vec<FieldChange> {
+    let mut old = old.map(attributes).unwrap_or_default();
+    let new = attributes(new);
+
+    let mut changes = vec![];
+    for (field, value) in new {
+        let old = old.remove(&field);
+        if old.as_ref() != Some(&value) {
+            changes.push(FieldChange {
+                field,
+                old,
+                new: Some(value),
+            });
+        }
+    }
+    changes.extend(old.into_iter().map(|(field, value)| FieldChange {
+        field,
+        old: Some(value),
+        new: None,
+    }));
+    changes.sort_by(|a, b| a.field.cmp(&b.field));
+    changes
+}
+
+/// The attributes of `task` by name, formatted for display
+fn attributes(task: &Task) -> BTreeMap<String, String> {
+    let attributes = match serde_json::to_value(task) {
+        Ok(Value::Object(attributes)) => attributes,
+        _ => return BTreeMap::new(),
+    };
+    attributes
+        .into_iter()
+        .filter(|(name, _)| !IGNORED_ATTRIBUTES.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name, display_value(&value)?)))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+fn display_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => match datetime_format::parse(s) {
+            Ok(date) => Some(format_time(&date)),
+            Err(_) => Some(s.clone()),
+        },
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(values) => {
+            let values: Vec<_> = values
+                .iter()
+                .filter_map(|value| match value {
+                    // annotations
+                    Value::Object(object) => object.get("description").and_then(display_value),
+                    value => display_value(value),
+                })
+                .collect();
+            Some(values.join(", "))
+        }
+        Value::Object(_) | Value::Null => None,
+    }
+}
+
+fn format_time(date: &DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// A task as it was after a change, together with what has been changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub time: DateTime<Utc>,
+    pub task: Task,
+    /// Empty if nothing but the modification time has changed
+    pub changes: Vec<FieldChange>,
+    /// Whether the task has been added by this change
+    pub created: bool,
+}
+
+/// The evolution of a single task, oldest change first
+#[derive(Clone, Debug, PartialEq)]
+pub struct History {
+    pub uuid: Uuid,
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// The task as it is after the last recorded change
+    pub fn current(&self) -> Option<&Task> {
+        self.entries.last().map(|entry| &entry.task)
+    }
+
+    /// The history as a markdown section for notes files
+    pub fn to_markdown(&self) -> String {
+        let mut section = format!("{}\n", SECTION_HEADING);
+        for entry in &self.entries {
+            section.push_str(&format!(
+                "\n- {}: {}",
+                format_time(&entry.time),
+                entry.summary()
+            ));
+            for change in &entry.changes {
+                section.push_str(&format!("\n    - {}", change));
+            }
+        }
+        section.push('\n');
+        section
+    }
+}
+
+impl HistoryEntry {
+    fn summary(&self) -> &'static str {
+        if self.created {
+            "created"
+        } else {
+            "modified"
+        }
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task {}", self.uuid)?;
+        for entry in &self.entries {
+            write!(f, "\n{} {}", format_time(&entry.time), entry.summary())?;
+            for change in &entry.changes {
+                write!(f, "\n  {}", change)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The histories of all tasks changed in `transactions`, which are expected oldest first
+pub fn histories(transactions: Vec<Transaction>) -> BTreeMap<Uuid, History> {
+    let mut histories = BTreeMap::new();
+    for transaction in transactions {
+        let uuid = transaction.new.uuid;
+        let history = histories.entry(uuid).or_insert_with(|| History {
+            uuid,
+            entries: vec![],
+        });
+        history.entries.push(HistoryEntry {
+            time: transaction.time,
+            changes: task_diff(transaction.old.as_ref(), &transaction.new),
+            created: transaction.old.is_none(),
+            task: transaction.new,
+        });
+    }
+    histories
+}
+
+/// The history of the task whose UUID is or starts with `uuid`
+fn find_history(mut histories: BTreeMap<Uuid, History>, uuid: &str) -> Result<History> {
+    let uuid = uuid.to_lowercase();
+    let matching: Vec<Uuid> = histories
+        .keys()
+        .filter(|candidate| candidate.to_string().starts_with(&uuid))
+        .copied()
+        .collect();
+    match matching.as_slice() {
+        [found] => Ok(histories.remove(found).expect("key exists")),
+        [] => Err(Error::History(format!("no history of task {}", uuid))),
+        _ => Err(Error::History(format!(
+            "'{}' matches {} tasks",
+            uuid,
+            matching.len()
+        ))),
+    }
+}
+
+/// Replace the history section of `content`, or append one if there is none
+fn with_history_section(content: &str, section: &str) -> String {
+    with_section(content, SECTION_HEADING, section)
+}
+
+/// Show the history of the task `uuid`, which may be abbreviated, recorded in the data
+/// location `data_location`. With `append` it is also written to the task's notes file.
+pub fn run<W: Write>(
+    config: &'static Config,
+    data_location: &Path,
+    uuid: &str,
+    append: bool,
+    output: &mut W,
+) -> Result<()> {
+    let transactions = read_undo_data(data_location, &config.udas)?;
+    let history = find_history(histories(transactions), uuid)?;
+    writeln!(output, "{}", history).map_err(Error::io("cannot write history"))?;
+    if !append {
+        return Ok(());
+    }
+
+    let task = history.current().expect("histories are never empty");
+    let hooks = Hooks::with_config(config);
+    let path = hooks
+        .locate_notes_file(task)
+        .ok_or_else(|| Error::History(format!("task {} has no notes file", history.uuid)))?;
+    let notes_file = NotesFile::read(&path)?;
+    let content = with_history_section(notes_file.content(), &history.to_markdown());
+    notes_file.with_content(&content).write()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::{HeaderFields, YamlMeta};
+    use tempfile::tempdir;
+
+    const UNDO_DATA: &str = concat!(
+        "time 1641834979\n",
+        r#"new [description:"Dummy Task" entry:"1641834979" modified:"1641834979" status:"pending" uuid:"dde3720b-003f-4776-8e15-61e5d90376af"]"#,
+        "\n---\n",
+        "time 1641835000\n",
+        r#"new [description:"Other Task" entry:"1641835000" modified:"1641835000" status:"pending" uuid:"0cfe4c2b-b2d0-4bc8-9c49-b81ef9c1af5b"]"#,
+        "\n---\n",
+        "time 1641886872\n",
+        r#"old [description:"Dummy Task" entry:"1641834979" modified:"1641834979" status:"pending" uuid:"dde3720b-003f-4776-8e15-61e5d90376af"]"#,
+        "\n",
+        r#"new [description:"Dummy Task" entry:"1641834979" modified:"1641886872" project:"dummy" status:"pending" tags:"wiki" uuid:"dde3720b-003f-4776-8e15-61e5d90376af"]"#,
+        "\n---\n",
+    );
+
+    fn dummy_history() -> History {
+        let transactions = parse_undo_data(UNDO_DATA, &UdaRegistry::default()).unwrap();
+        find_history(histories(transactions), "dde3720b").expect("history exists")
+    }
+
+    #[test]
+    fn parse_transactions() {
+        let transactions =
+            parse_undo_data(UNDO_DATA, &UdaRegistry::default()).expect("parsing succeeds");
+        assert_eq!(transactions.len(), 3);
+        assert!(transactions[0].old.is_none());
+        assert_eq!(
+            transactions[2].time,
+            Utc.timestamp_opt(1641886872, 0).unwrap()
+        );
+        assert_eq!(transactions[2].old.as_ref(), Some(&transactions[0].new));
+        assert!(transactions[2].new.has_tag("wiki"));
+    }
+
+    #[test]
+    fn reject_malformed_undo_data() {
+        let udas = UdaRegistry::default();
+        assert!(parse_undo_data("time now\n---\n", &udas).is_err());
+        assert!(parse_undo_data("time 1641834979\n---\n", &udas).is_err());
+        assert!(
+            parse_undo_data("time 1641834979\nnew [status:\"pending\"]\n---\n", &udas).is_err()
+        );
+        let err = parse_undo_data(&UNDO_DATA.replace("---\n", ""), &udas)
+            .expect_err("blocks are not terminated");
+        assert!(matches!(err, Error::TaskData(_)));
+    }
+
+    #[test]
+    fn diff_snapshots_per_task() {
+        let history = dummy_history();
+        assert_eq!(history.entries.len(), 2);
+        assert!(history.entries[0].created);
+        assert_eq!(
+            history.to_string(),
+            "task dde3720b-003f-4776-8e15-61e5d90376af\n\
+             2022-01-10 17:16:19 created\n  \
+             description: (none) -> \"Dummy Task\"\n  \
+             entry: (none) -> \"2022-01-10 17:16:19\"\n  \
+             status: (none) -> \"pending\"\n  \
+             uuid: (none) -> \"dde3720b-003f-4776-8e15-61e5d90376af\"\n\
+             2022-01-11 07:41:12 modified\n  \
+             project: (none) -> \"dummy\"\n  \
+             tags: (none) -> \"wiki\""
+        );
+        assert_eq!(history.current().unwrap().project.as_deref(), Some("dummy"));
+    }
+
+    #[test]
+    fn find_history_by_uuid_prefix() {
+        let transactions = parse_undo_data(UNDO_DATA, &UdaRegistry::default()).unwrap();
+        let histories = histories(transactions);
+        assert_eq!(histories.len(), 2);
+        assert!(find_history(histories.clone(), "0CFE4C2B").is_ok());
+        assert!(find_history(histories.clone(), "ffff").is_err());
+        assert!(find_history(histories, "").is_err());
+    }
+
+    #[test]
+    fn replace_existing_history_section() {
+        let section = dummy_history().to_markdown();
+        assert!(section.starts_with("## History\n\n- 2022-01-10 17:16:19: created\n    - "));
+
+        let appended = with_history_section("# Notes\n\nSome text\n", &section);
+        assert_eq!(appended, format!("# Notes\n\nSome text\n\n{}", section));
+        assert_eq!(with_history_section(&appended, &section), appended);
+
+        let followed = "# Notes\n\n## History\n\n- old entry\n\n## Links\n\nnone";
+        assert_eq!(
+            with_history_section(followed, &section),
+            format!("# Notes\n\n{}\n## Links\n\nnone", section)
+        );
+        assert_eq!(
+            with_history_section(&format!("{}\n", followed), &section),
+            format!("# Notes\n\n{}\n## Links\n\nnone\n", section)
+        );
+        assert_eq!(with_history_section("", &section), section);
+    }
+
+    #[test]
+    fn append_history_to_notes_file() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let mut config = Config::default();
+        config.notes_dir = dir.path().join("notes");
+        let config = config.to_static();
+        std::fs::write(dir.path().join("undo.data"), UNDO_DATA).unwrap();
+
+        let history = dummy_history();
+        let task = history.current().unwrap();
+        let path = Hooks::with_config(config).note_file_path(task);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        NotesFile::new(&path)
+            .with_header(YamlMeta::from_task(task, &HeaderFields::default()))
+            .with_content("# Notes")
+            .write()
+            .unwrap();
+
+        let mut output = vec![];
+        run(config, dir.path(), "dde3720b", true, &mut output).expect("history succeeds");
+        assert_eq!(String::from_utf8(output).unwrap(), format!("{}\n", history));
+        let notes_file = NotesFile::read(&path).unwrap();
+        assert_eq!(
+            notes_file.content(),
+            format!("# Notes\n\n{}", history.to_markdown())
+        );
+        assert!(notes_file.header().is_some());
+
+        let err =
+            run(config, dir.path(), "0cfe4c2b", true, &mut vec![]).expect_err("no notes file");
+        assert_eq!(
+            err.to_string(),
+            "task 0cfe4c2b-b2d0-4bc8-9c49-b81ef9c1af5b has no notes file"
+        );
+    }
+}
@@ -1,11 +1,18 @@
 use crate::config::Config;
-use crate::notes::{NotesFile, YamlMeta};
-use crate::{Annotation, Task};
+use crate::notes::{find_notes_file, with_section, NotesFile, YamlMeta};
+use crate::template::NotesTemplate;
+use crate::{Annotation, Error, Result, Task};
 use log::debug;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
 mod on_add;
+mod on_exit;
+mod on_launch;
 mod on_modify;
+mod runner;
+
+pub use runner::{HookKind, HookRunner};
 
 pub type Feedback = String;
 
@@ -18,40 +25,207 @@ impl Hooks {
         Self { config: cfg }
     }
 
+    /// Path for a new notes file of `task`, rendered from the `notes_name` template
     pub fn note_file_path(&self, task: &Task) -> PathBuf {
-        PathBuf::from(&self.config.notes_dir)
+        let name =
+            self.config
+                .notes_name
+                .render(task, &self.config.notes_ext, self.config.notes_name_max);
+        self.config.notes_dir.join(name)
+    }
+
+    /// Path notes files were created at before file names were configurable
+    fn legacy_note_file_path(&self, task: &Task) -> PathBuf {
+        self.config
+            .notes_dir
             .join(task.uuid.to_string())
             .with_extension(&self.config.notes_ext)
     }
 
-    fn create_notes_file(&self, task: &Task) -> Result<PathBuf, &'static str> {
-        let path = self.note_file_path(task);
+    fn create_notes_file(&self, task: &Task) -> Result<PathBuf> {
+        if let Some(parent) = self.series_parent(task) {
+            return self.add_series_section(task, parent);
+        }
+        let path = self.available_path(task, &self.note_file_path(task));
+        let content = match self.notes_template(task) {
+            Some(template) => NotesTemplate::read(&template)?.render_in(
+                task,
+                &self.config.udas,
+                &self.config.date_context(),
+            ),
+            None => String::from("%% Add your notes here"),
+        };
 
         let notes_file = NotesFile::new(&path)
-            .with_header(YamlMeta::new(
-                &task.description,
-                task.entry.naive_local().date(),
-            ))
-            .with_content("%% Add your notes here");
+            .with_header(YamlMeta::from_task(task, &self.config.notes_fields))
+            .with_content(&content);
 
         debug!("Creating note at {:?}", path);
 
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(Error::io(format!(
+                "cannot create notes directory {}",
+                dir.display()
+            )))?;
+        }
         notes_file.write()?;
         Ok(path)
     }
 
-    fn remove_notes_file(&self, task: &Task) -> Result<(), &'static str> {
-        let path = self.note_file_path(task);
-        std::fs::remove_file(&path).map_err(|_| "Cannot remove notes file")
+    /// The parent of `task` if it is an instance of a recurring task sharing the notes file of
+    /// its series
+    fn series_parent(&self, task: &Task) -> Option<Uuid> {
+        task.parent.filter(|_| self.config.notes_series)
+    }
+
+    /// Add a section for the instance `task` to the notes file of its recurring `parent`,
+    /// headed by the due date of the instance, creating the file if there is none yet
+    fn add_series_section(&self, task: &Task, parent: Uuid) -> Result<PathBuf> {
+        let path = match find_notes_file(&self.config.notes_dir, &self.config.notes_ext, parent) {
+            Some(path) => path,
+            None => {
+                let mut series = task.clone();
+                series.uuid = parent;
+                series.parent = None;
+                series.imask = None;
+                series.due = None;
+                series.annotations.clear();
+                self.create_notes_file(&series)?
+            }
+        };
+
+        let date = task.due.unwrap_or(task.entry);
+        let heading = format!("## {}", self.config.date_context().format(date, "%Y-%m-%d"));
+        let notes_file = NotesFile::read(&path)?;
+        if notes_file
+            .content()
+            .lines()
+            .any(|line| line.trim_end() == heading)
+        {
+            return Ok(path);
+        }
+
+        debug!("Adding section {:?} to {:?}", heading, path);
+        let section = format!("{}\n\n%% Add your notes on this occurrence here\n", heading);
+        let content = with_section(notes_file.content(), &heading, &section);
+        notes_file.with_content(&content).write()?;
+        Ok(path)
+    }
+
+    /// The template for new notes files of `task` from the `templates/` directory in the notes
+    /// directory. A template for the task's project (`project/<project>.<ext>`, parent projects
+    /// included) is preferred over one for any of its tags (`tag/<tag>.<ext>`), which is
+    /// preferred over `default.<ext>`.
+    fn notes_template(&self, task: &Task) -> Option<PathBuf> {
+        let dir = self.config.notes_dir.join("templates");
+        let file_name = |name: &str| format!("{}.{}", name, self.config.notes_ext);
+
+        let mut candidates = vec![];
+        if let Some(project) = &task.project {
+            let mut project = project.as_str();
+            loop {
+                candidates.push(dir.join("project").join(file_name(project)));
+                match project.rsplit_once('.') {
+                    Some((parent, _)) => project = parent,
+                    None => break,
+                }
+            }
+        }
+        let mut tags: Vec<_> = task.tags.iter().collect();
+        tags.sort_unstable();
+        candidates.extend(tags.iter().map(|tag| dir.join("tag").join(file_name(tag))));
+        candidates.push(dir.join(file_name("default")));
+
+        candidates.into_iter().find(|path| path.is_file())
+    }
+
+    /// `path` if it is free or already taken by the notes file of `task`, otherwise the first
+    /// free path with a numeric suffix like `-2` appended to its file name
+    fn available_path(&self, task: &Task, path: &Path) -> PathBuf {
+        let is_available = |path: &Path| !path.exists() || self.is_linked(path, task);
+        if is_available(path) {
+            return path.to_path_buf();
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let ext = format!(".{}", self.config.notes_ext);
+        let stem = name.strip_suffix(&ext).unwrap_or(&name);
+        (2..)
+            .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, ext)))
+            .find(|path| is_available(path))
+            .expect("some suffix is free")
+    }
+
+    /// Whether the header of the notes file at `path` links it to `task`
+    fn is_linked(&self, path: &Path, task: &Task) -> bool {
+        NotesFile::read(path)
+            .ok()
+            .and_then(|notes_file| notes_file.header()?.uuid())
+            == Some(task.uuid)
+    }
+
+    /// The location a notes file at `path` is moved to when archived
+    fn archived_path(&self, path: &Path) -> PathBuf {
+        let file_name = path.file_name().unwrap_or_default();
+        self.archive_dir().join(file_name)
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.config.notes_dir.join("archive")
+    }
+
+    fn is_archived(&self, path: &Path) -> bool {
+        path.starts_with(self.archive_dir())
     }
 
-    fn create_path_annotation(&self, task: &mut Task) {
+    /// The existing notes file of `task`, be it archived, renamed or not. The path annotation
+    /// is tried first, then the shared file of a recurring series, then the path the file would
+    /// be created at and the legacy `<uuid>` path, before the notes directory is searched for a
+    /// header linking it to `task`.
+    pub fn locate_notes_file(&self, task: &Task) -> Option<PathBuf> {
+        if let Some(path) = annotated_path(task).filter(|path| path.is_file()) {
+            return Some(path);
+        }
+        if let Some(parent) = self.series_parent(task) {
+            return find_notes_file(&self.config.notes_dir, &self.config.notes_ext, parent);
+        }
         let path = self.note_file_path(task);
-        let path_annotation = Annotation::new(&format!(
-            "taskw:note {}",
-            path.to_str().unwrap_or("<invalid path>")
-        ));
-        task.annotations.push(path_annotation);
+        if path.is_file() && self.is_linked(&path, task) {
+            return Some(path);
+        }
+        let path = self.legacy_note_file_path(task);
+        if path.is_file() {
+            return Some(path);
+        }
+        find_notes_file(&self.config.notes_dir, &self.config.notes_ext, task.uuid)
+    }
+
+    fn move_notes_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let context = format!(
+            "cannot move notes file {} to {}",
+            from.display(),
+            to.display()
+        );
+        if let Some(dir) = to.parent() {
+            std::fs::create_dir_all(dir).map_err(Error::io(context.clone()))?;
+        }
+        std::fs::rename(from, to).map_err(Error::io(context))
+    }
+
+    fn remove_notes_file(&self, task: &Task) -> Result<()> {
+        let path = self.locate_notes_file(task).ok_or_else(|| Error::Io {
+            context: format!("cannot find notes file of task {}", task.uuid),
+            source: std::io::ErrorKind::NotFound.into(),
+        })?;
+        std::fs::remove_file(&path).map_err(Error::io(format!(
+            "cannot remove notes file {}",
+            path.display()
+        )))
+    }
+
+    /// Replace any existing path annotation by one pointing to `path`
+    fn update_path_annotation(&self, task: &mut Task, path: &Path) {
+        self.remove_path_annotation(task);
+        task.annotations.push(path_annotation(path));
     }
 
     fn remove_path_annotation(&self, task: &mut Task) {
@@ -60,10 +234,27 @@ impl Hooks {
     }
 }
 
+/// The path of the notes file according to the annotation of `task`, if any
+fn annotated_path(task: &Task) -> Option<PathBuf> {
+    task.annotations
+        .iter()
+        .find_map(|annotation| annotation.description.strip_prefix("taskw:note "))
+        .map(PathBuf::from)
+}
+
+fn path_annotation(path: &Path) -> Annotation {
+    Annotation::new(&format!(
+        "taskw:note {}",
+        path.to_str().unwrap_or("<invalid path>")
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filename::short_uuid;
     use tempfile::{tempdir, TempDir};
+    use uuid::Uuid;
 
     pub fn test_config() -> (&'static Config, TempDir) {
         let temp_dir = tempdir().expect("tempdir creation succeeds");
@@ -76,18 +267,83 @@ mod tests {
     fn create_note_file_path_for_task() {
         let (cfg, _tmp_dir) = test_config();
         let task = Task::new("Dummy Task");
-        let path = Hooks::with_config(&cfg).note_file_path(&task);
+        let path = Hooks::with_config(cfg).note_file_path(&task);
 
         let path_str = path.to_str().expect("valid path");
         assert!(path_str.contains(cfg.notes_dir.to_str().expect("valid path")));
-        assert!(path_str.contains(&task.uuid.to_string()));
+        assert!(path_str.contains(&short_uuid(&task)));
+        assert!(path_str.contains("dummy-task"));
         assert!(path_str.contains(&cfg.notes_ext));
     }
 
+    #[test]
+    fn avoid_collisions_with_notes_of_other_tasks() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let task = Task::new("Dummy Task");
+        let path = hooks
+            .create_notes_file(&task)
+            .expect("file creation succeeds");
+
+        let mut other = Task::new("Dummy Task");
+        other.uuid = Uuid::from_u128(task.uuid.as_u128() ^ 1);
+        other.entry = task.entry;
+        assert_eq!(hooks.note_file_path(&other), path);
+
+        let other_path = hooks
+            .create_notes_file(&other)
+            .expect("file creation succeeds");
+        assert_ne!(other_path, path);
+        assert!(other_path.to_str().unwrap().ends_with("-2.md"));
+        assert_eq!(hooks.available_path(&task, &path), path);
+
+        let dotted = cfg.notes_dir.join("Release v1.2 prep.md");
+        std::fs::write(&dotted, "taken").unwrap();
+        assert_eq!(
+            hooks.available_path(&task, &dotted),
+            cfg.notes_dir.join("Release v1.2 prep-2.md")
+        );
+    }
+
+    #[test]
+    fn locate_notes_file_after_task_changed() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let mut task = Task::new("Dummy Task");
+        let path = hooks
+            .create_notes_file(&task)
+            .expect("file creation succeeds");
+
+        task.description = String::from("Renamed Task");
+        task.project = Some(String::from("elsewhere"));
+        assert_ne!(hooks.note_file_path(&task), path);
+        assert_eq!(hooks.locate_notes_file(&task), Some(path.clone()));
+
+        let moved = cfg.notes_dir.join("moved").join("by-hand.md");
+        hooks
+            .move_notes_file(&path, &moved)
+            .expect("moving succeeds");
+        assert_eq!(hooks.locate_notes_file(&task), Some(moved.clone()));
+
+        hooks.update_path_annotation(&mut task, &moved);
+        assert_eq!(annotated_path(&task), Some(moved));
+    }
+
+    #[test]
+    fn locate_legacy_notes_file() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let task = Task::new("Dummy Task");
+        let legacy = hooks.legacy_note_file_path(&task);
+        NotesFile::new(&legacy).write().expect("writing succeeds");
+
+        assert_eq!(hooks.locate_notes_file(&task), Some(legacy));
+    }
+
     #[test]
     fn create_and_remove_notes_file() {
         let (cfg, _tmp_dir) = test_config();
-        let hooks = Hooks::with_config(&cfg);
+        let hooks = Hooks::with_config(cfg);
         let task = Task::new("Dummy Task");
 
         let path = hooks
@@ -104,11 +360,12 @@ mod tests {
     #[test]
     fn create_and_remove_path_annotation() {
         let (cfg, tmp_dir) = test_config();
-        let hooks = Hooks::with_config(&cfg);
+        let hooks = Hooks::with_config(cfg);
         let mut task = Task::new("Dummy Task");
 
         assert_eq!(task.annotations.len(), 0);
-        hooks.create_path_annotation(&mut task);
+        let path = hooks.note_file_path(&task);
+        hooks.update_path_annotation(&mut task, &path);
         assert_eq!(task.annotations.len(), 1);
 
         let path_in_annotation = PathBuf::from(&task.annotations[0].description)
@@ -116,10 +373,55 @@ mod tests {
             .expect("valid path")
             .to_owned();
         assert!(path_in_annotation.contains(tmp_dir.path().to_str().expect("valid path")));
-        assert!(path_in_annotation.contains(&task.uuid.to_string()));
+        assert!(path_in_annotation.contains(&short_uuid(&task)));
 
         assert_eq!(task.annotations.len(), 1);
         hooks.remove_path_annotation(&mut task);
         assert_eq!(task.annotations.len(), 0);
     }
+
+    #[test]
+    fn create_notes_file_from_template() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let templates = cfg.notes_dir.join("templates");
+        std::fs::create_dir_all(templates.join("project")).unwrap();
+        std::fs::create_dir_all(templates.join("tag")).unwrap();
+        std::fs::write(templates.join("default.md"), "default").unwrap();
+        std::fs::write(templates.join("tag").join("bug.md"), "bug {{short_uuid}}").unwrap();
+        std::fs::write(
+            templates.join("project").join("Work.md"),
+            "{{#tag.meeting}}## Attendees{{/tag.meeting}}",
+        )
+        .unwrap();
+
+        let read = |task: &Task| {
+            let path = hooks
+                .create_notes_file(task)
+                .expect("file creation succeeds");
+            NotesFile::read(&path).unwrap().content().to_string()
+        };
+
+        assert_eq!(read(&Task::new("Plain")), "default");
+        let bug = Task::new("Crash").with_tag("bug");
+        assert_eq!(read(&bug), format!("bug {}", short_uuid(&bug)));
+
+        let mut meeting = Task::new("Sync").with_tag("meeting").with_tag("bug");
+        meeting.project = Some(String::from("Work.Team"));
+        assert_eq!(read(&meeting), "## Attendees");
+    }
+
+    #[test]
+    fn fail_on_malformed_template() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let templates = cfg.notes_dir.join("templates");
+        std::fs::create_dir_all(&templates).unwrap();
+        std::fs::write(templates.join("default.md"), "{{#due}}").unwrap();
+
+        let err = hooks
+            .create_notes_file(&Task::new("Dummy Task"))
+            .expect_err("template is malformed");
+        assert!(matches!(err, Error::Template(_)));
+    }
 }
@@ -1,18 +1,24 @@
-use crate::Task;
+use crate::{Result, Task};
 use log::debug;
 
 use super::{Feedback, Hooks};
 
 impl Hooks {
-    pub fn on_add(&self, mut task: Task) -> Result<(Task, Feedback), &'static str> {
+    pub fn on_add(&self, mut task: Task) -> Result<(Task, Feedback)> {
         debug!("added = {:#?}", task);
 
-        if !task.has_tag(&self.config.notes_tag) {
+        task.validate_udas(&self.config.udas)?;
+        let defaults = task.apply_uda_defaults(&self.config.udas, &self.config.date_context())?;
+        if !defaults.is_empty() {
+            debug!("applied UDA defaults for {:?}", defaults);
+        }
+
+        if !self.config.wants_notes(&task) {
             return Ok((task, Feedback::new()));
         }
 
         let path = self.create_notes_file(&task)?;
-        self.create_path_annotation(&mut task);
+        self.update_path_annotation(&mut task, &path);
 
         Ok((task, format!("Created notes file at {}", path.display())))
     }
@@ -21,7 +27,12 @@ impl Hooks {
 #[cfg(test)]
 mod tests {
     use super::{super::tests::test_config, *};
+    use crate::config::Config;
+    use crate::notes::{NotesFile, YamlMeta};
+    use crate::uda::{Uda, UdaType};
     use crate::Task;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
 
     #[test]
     fn on_add_passes_irrelevant_task() {
@@ -48,4 +59,62 @@ mod tests {
         assert!(hooks.note_file_path(&task).exists());
         assert!(feedback.contains("notes"));
     }
+
+    #[test]
+    fn on_add_shares_notes_file_of_recurring_series() {
+        let (cfg, _tmp_dir) = test_config();
+        let mut series_cfg = Config::default();
+        series_cfg.notes_dir = cfg.notes_dir.clone();
+        series_cfg.notes_series = true;
+        let hooks = Hooks::with_config(series_cfg.to_static());
+
+        let parent = Uuid::new_v4();
+        let instance = |day: u32| {
+            let mut task = Task::new("Water the plants").with_tag(&cfg.notes_tag);
+            task.parent = Some(parent);
+            task.due = Some(Utc.with_ymd_and_hms(2022, 3, day, 12, 0, 0).unwrap());
+            task
+        };
+        let (first, _) = hooks.on_add(instance(1)).expect("succeeds");
+        let (second, _) = hooks.on_add(instance(8)).expect("succeeds");
+        hooks.on_add(instance(8)).expect("succeeds");
+
+        let path = hooks.locate_notes_file(&first).expect("notes file exists");
+        assert_eq!(hooks.locate_notes_file(&second), Some(path.clone()));
+        let notes_file = NotesFile::read(&path).expect("valid notes file");
+        assert_eq!(notes_file.header().and_then(YamlMeta::uuid), Some(parent));
+        assert_eq!(notes_file.content().matches("## 2022-03-01").count(), 1);
+        assert_eq!(notes_file.content().matches("## 2022-03-08").count(), 1);
+    }
+
+    #[test]
+    fn on_add_fills_in_uda_defaults() {
+        let (cfg, _tmp_dir) = test_config();
+        let mut cfg_with_udas = Config::default();
+        cfg_with_udas.notes_dir = cfg.notes_dir.clone();
+        cfg_with_udas
+            .udas
+            .insert(Uda::new("client", UdaType::String).with_default("acme"));
+        let hooks = Hooks::with_config(cfg_with_udas.to_static());
+
+        let (task, _) = hooks.on_add(Task::new("Dummy Task")).expect("succeeds");
+        assert_eq!(task.unknown_fields["client"], "acme");
+    }
+
+    #[test]
+    fn on_add_rejects_disallowed_uda_values() {
+        let (cfg, _tmp_dir) = test_config();
+        let mut cfg_with_udas = Config::default();
+        cfg_with_udas.notes_dir = cfg.notes_dir.clone();
+        cfg_with_udas
+            .udas
+            .insert(Uda::new("client", UdaType::String).with_values(&["acme", "globex"]));
+        let hooks = Hooks::with_config(cfg_with_udas.to_static());
+
+        let mut task = Task::new("Dummy Task");
+        task.unknown_fields
+            .insert(String::from("client"), "initech".into());
+        let err = hooks.on_add(task).expect_err("not an allowed value");
+        assert!(matches!(err, crate::Error::Uda(_)));
+    }
 }
@@ -0,0 +1,60 @@
+use crate::config::NotesPolicy;
+use crate::{Result, Status, Task};
+use log::debug;
+
+use super::{Feedback, Hooks};
+
+impl Hooks {
+    /// Called after taskwarrior processed a command with all tasks that have been added or
+    /// modified. The tasks cannot be changed anymore, only feedback can be given. Closed tasks
+    /// whose notes file is removed by the policy of their status are not expected to have one.
+    pub fn on_exit(&self, tasks: Vec<Task>) -> Result<Feedback> {
+        debug!("exit, {} changed tasks", tasks.len());
+
+        let policy = |task: &Task| match task.status {
+            Status::Completed => Some(self.config.notes_on_complete),
+            Status::Deleted => Some(self.config.notes_on_delete),
+            _ => None,
+        };
+        let missing: Vec<String> = tasks
+            .iter()
+            .filter(|task| self.config.wants_notes(task))
+            .filter(|task| policy(task) != Some(NotesPolicy::Delete))
+            .filter(|task| self.locate_notes_file(task).is_none())
+            .map(|task| format!("Notes file missing for task {}", task.uuid))
+            .collect();
+
+        Ok(missing.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::test_config, *};
+
+    #[test]
+    fn on_exit_reports_missing_notes_files() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+
+        let with_notes = Task::new("With Notes").with_tag(&cfg.notes_tag);
+        let (with_notes, _) = hooks.on_add(with_notes).expect("succeeds");
+        let without_notes = Task::new("Without Notes").with_tag(&cfg.notes_tag);
+        let untagged = Task::new("Untagged");
+
+        let feedback = hooks
+            .on_exit(vec![with_notes, without_notes.clone(), untagged])
+            .expect("succeeds");
+        assert_eq!(
+            feedback,
+            format!("Notes file missing for task {}", without_notes.uuid)
+        );
+    }
+
+    #[test]
+    fn on_exit_without_tasks_gives_no_feedback() {
+        let (cfg, _tmp_dir) = test_config();
+        let feedback = Hooks::with_config(cfg).on_exit(vec![]).expect("succeeds");
+        assert!(feedback.is_empty());
+    }
+}
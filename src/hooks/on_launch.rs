@@ -0,0 +1,46 @@
+use crate::config::ConfigError;
+use crate::Result;
+use log::debug;
+
+use super::{Feedback, Hooks};
+
+impl Hooks {
+    /// Called before taskwarrior processes a command. Returning an error aborts the command.
+    pub fn on_launch(&self) -> Result<Feedback> {
+        debug!("launch, notes_dir = {:?}", self.config.notes_dir);
+
+        if !self.config.notes_dir.is_dir() {
+            return Err(ConfigError::NotesDirMissing {
+                path: self.config.notes_dir.clone(),
+                source: self.config.source_of("notes_dir").clone(),
+            }
+            .into());
+        }
+        Ok(Feedback::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::test_config, *};
+    use crate::Error;
+
+    #[test]
+    fn on_launch_passes_with_existing_notes_dir() {
+        let (cfg, _tmp_dir) = test_config();
+        let feedback = Hooks::with_config(cfg).on_launch().expect("succeeds");
+        assert_eq!(feedback, String::new());
+    }
+
+    #[test]
+    fn on_launch_aborts_without_notes_dir() {
+        let (cfg, tmp_dir) = test_config();
+        tmp_dir.close().expect("removing notes dir");
+
+        let err = Hooks::with_config(cfg).on_launch().expect_err("fails");
+        assert!(matches!(
+            err,
+            Error::Config(ConfigError::NotesDirMissing { .. })
+        ));
+    }
+}
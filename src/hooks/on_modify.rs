@@ -1,49 +1,247 @@
-use crate::Task;
+use crate::config::NotesPolicy;
+use crate::notes::NotesFile;
+use crate::{Result, Status, Task};
 use log::debug;
+use std::path::Path;
 
 use super::{Feedback, Hooks};
 
 impl Hooks {
-    pub fn on_modify(
-        &self,
-        original: Task,
-        mut modified: Task,
-    ) -> Result<(Task, Feedback), &'static str> {
+    pub fn on_modify(&self, original: Task, mut modified: Task) -> Result<(Task, Feedback)> {
         debug!("original = {:#?}", original);
         debug!("modified = {:#?}", modified);
 
-        let notes_tag = &self.config.notes_tag;
+        // completing or deleting tasks is up to the notes policies, so a notes filter checking
+        // the status sees the tasks as pending
+        let as_pending = |task: &Task| {
+            let mut task = task.clone();
+            task.status = Status::Pending;
+            task.end = None;
+            task
+        };
+        let had_notes = self.config.wants_notes(&as_pending(&original));
+        let has_notes = self.config.wants_notes(&as_pending(&modified));
 
-        match (original.has_tag(notes_tag), modified.has_tag(notes_tag)) {
-            // notes tag added
+        // the notes file of a recurring series outlives its instances, which only add sections
+        if had_notes && self.series_parent(&modified).is_some() {
+            if !has_notes {
+                self.remove_path_annotation(&mut modified);
+            }
+            return Ok((modified, Feedback::new()));
+        }
+
+        match (had_notes, has_notes) {
+            // notes tag added, or the task passes the notes filter (again)
             (false, true) => {
-                let path = self.create_notes_file(&modified)?;
-                self.create_path_annotation(&mut modified);
-                Ok((
-                    modified,
-                    format!("Created notes file at {}", path.display()),
-                ))
+                // notes kept when the task stopped passing the notes filter are linked again
+                let existing = self
+                    .locate_notes_file(&modified)
+                    .filter(|_| self.series_parent(&modified).is_none());
+                let (path, feedback) = match existing {
+                    Some(path) => (path, "Linked notes file at"),
+                    None => (self.create_notes_file(&modified)?, "Created notes file at"),
+                };
+                self.update_path_annotation(&mut modified, &path);
+                Ok((modified, format!("{} {}", feedback, path.display())))
             }
 
-            // notes tag removed
-            (true, false) => {
-                self.remove_path_annotation(&mut modified);
+            // notes tag removed, if the notes depend on it
+            (true, false) if self.removed_notes_tag(&original, &modified) => {
                 let feedback = match self.remove_notes_file(&modified) {
                     Ok(_) => String::from("Removed notes file"),
                     _ => String::from("No notes found"),
                 };
+                self.remove_path_annotation(&mut modified);
                 Ok((modified, feedback))
             }
+
+            // the task no longer passes the notes filter, which is no reason to lose the notes
+            (true, false) => {
+                let feedback = match self.locate_notes_file(&modified) {
+                    Some(path) => format!(
+                        "Kept notes file at {}, the task no longer passes the notes filter",
+                        path.display()
+                    ),
+                    None => String::from("No notes found"),
+                };
+                self.remove_path_annotation(&mut modified);
+                Ok((modified, feedback))
+            }
+
+            // task with notes changed
+            (true, true) => {
+                let mut feedback = vec![];
+                if original.description != modified.description
+                    || original.project != modified.project
+                {
+                    feedback.push(self.follow_notes_file(&mut modified)?);
+                }
+                if original.status != modified.status {
+                    feedback.push(self.change_notes_status(&original.status, &mut modified)?);
+                }
+                self.sync_notes_header(&modified)?;
+                Ok((modified, feedback.join("\n")))
+            }
             _ => Ok((modified, String::new())),
         }
-        // TODO: take status::Deleted into account
+    }
+
+    /// Rewrite the header of the notes file of `task` if it no longer mirrors the task
+    fn sync_notes_header(&self, task: &Task) -> Result<()> {
+        let path = match self.locate_notes_file(task) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut notes_file = NotesFile::read(&path)?;
+        if let Some(header) = notes_file.header_mut() {
+            let original = header.clone();
+            header.update(task, &self.config.notes_fields);
+            if *header != original {
+                debug!("updating header of {:?}", path);
+                notes_file.write()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the notes file of a task whose description or project changed to the path it
+    /// would be created at now, if configured to
+    fn follow_notes_file(&self, task: &mut Task) -> Result<Feedback> {
+        let mut path = match self.locate_notes_file(task) {
+            Some(path) => path,
+            None => return Ok(String::from("No notes found")),
+        };
+
+        let mut feedback = Feedback::new();
+        if self.config.notes_rename && !self.is_archived(&path) {
+            let new_path = self.available_path(task, &self.note_file_path(task));
+            if new_path != path {
+                self.move_notes_file(&path, &new_path)?;
+                self.remove_empty_dir(path.parent());
+                feedback = format!("Moved notes file to {}", new_path.display());
+                path = new_path;
+            }
+        }
+        self.update_path_annotation(task, &path);
+        Ok(feedback)
+    }
+
+    /// Whether `notes_tag` has been removed from `original`, while the notes depend on it: there
+    /// is no notes filter, or the filter has a `+notes_tag` term
+    fn removed_notes_tag(&self, original: &Task, modified: &Task) -> bool {
+        let tag = &self.config.notes_tag;
+        original.has_tag(tag)
+            && !modified.has_tag(tag)
+            && self
+                .config
+                .notes_filter
+                .as_ref()
+                .is_none_or(|filter| filter.mentions_tag(tag))
+    }
+
+    /// Remove `dir` if it is an empty subdirectory of the notes directory
+    fn remove_empty_dir(&self, dir: Option<&Path>) {
+        if let Some(dir) = dir.filter(|dir| *dir != self.config.notes_dir) {
+            // fails if the directory still contains files, which is fine
+            if std::fs::remove_dir(dir).is_ok() {
+                debug!("removed empty directory {:?}", dir);
+            }
+        }
+    }
+
+    /// Handle the notes file of a task whose status changed from `original`
+    fn change_notes_status(&self, original: &Status, task: &mut Task) -> Result<Feedback> {
+        match (is_closed(original), &task.status) {
+            (_, Status::Completed) => self.apply_notes_policy(self.config.notes_on_complete, task),
+            (_, Status::Deleted) => self.apply_notes_policy(self.config.notes_on_delete, task),
+            (true, _) => self.restore_notes_file(task),
+            _ => Ok(Feedback::new()),
+        }
+    }
+
+    /// Handle the notes file of a task that has been completed or deleted
+    fn apply_notes_policy(&self, policy: NotesPolicy, task: &mut Task) -> Result<Feedback> {
+        let path = match self.locate_notes_file(task) {
+            Some(path) => path,
+            None => return Ok(String::from("No notes found")),
+        };
+
+        match policy {
+            NotesPolicy::Keep => Ok(Feedback::new()),
+            NotesPolicy::Archive => {
+                if self.is_archived(&path) {
+                    self.update_path_annotation(task, &path);
+                    return Ok(Feedback::new());
+                }
+                let archived = self.available_path(task, &self.archived_path(&path));
+                self.move_notes_file(&path, &archived)?;
+                self.update_path_annotation(task, &archived);
+                Ok(format!("Archived notes file at {}", archived.display()))
+            }
+            NotesPolicy::Delete => {
+                std::fs::remove_file(&path).map_err(crate::Error::io(format!(
+                    "cannot remove notes file {}",
+                    path.display()
+                )))?;
+                self.remove_path_annotation(task);
+                Ok(String::from("Removed notes file"))
+            }
+            NotesPolicy::Stamp => {
+                let mut notes_file = NotesFile::read(&path)?;
+                let header = match notes_file.header_mut() {
+                    Some(header) => header,
+                    None => return Ok(String::from("No notes header to stamp")),
+                };
+                header.set_status(Some(task.status.as_str()));
+                header.set_end(task.end);
+                notes_file.write()?;
+                Ok(format!("Marked notes file as {}", task.status.as_str()))
+            }
+        }
+    }
+
+    /// Bring back the notes file of a task that has been reopened or undeleted
+    fn restore_notes_file(&self, task: &mut Task) -> Result<Feedback> {
+        let (path, feedback) = match self.locate_notes_file(task) {
+            None => {
+                let path = self.create_notes_file(task)?;
+                let feedback = format!("Recreated notes file at {}", path.display());
+                (path, feedback)
+            }
+            Some(archived) if self.is_archived(&archived) => {
+                let path = self.available_path(task, &self.note_file_path(task));
+                self.move_notes_file(&archived, &path)?;
+                let feedback = format!("Restored notes file at {}", path.display());
+                (path, feedback)
+            }
+            Some(path) => (path, Feedback::new()),
+        };
+
+        let mut notes_file = NotesFile::read(&path)?;
+        if let Some(header) = notes_file.header_mut() {
+            if header.status().is_some() || header.end().is_some() {
+                header.set_status(None);
+                header.set_end(None);
+                notes_file.write()?;
+            }
+        }
+
+        self.update_path_annotation(task, &path);
+        Ok(feedback)
     }
 }
 
+/// Whether tasks of this status are done with, i.e. completed or deleted
+fn is_closed(status: &Status) -> bool {
+    matches!(status, Status::Completed | Status::Deleted)
+}
+
 #[cfg(test)]
 mod tests {
     use super::{super::tests::test_config, *};
+    use crate::config::Config;
     use crate::Task;
+    use std::str::FromStr;
 
     #[test]
     fn add_and_remove_note_tag() {
@@ -66,4 +264,341 @@ mod tests {
         assert!(!hooks.note_file_path(&task_final).exists());
         assert!(feedback.contains("notes"));
     }
+
+    #[test]
+    fn follow_the_notes_filter() {
+        let (test_cfg, _tmp_dir) = test_config();
+        let mut cfg = Config::default();
+        cfg.notes_dir = test_cfg.notes_dir.clone();
+        cfg.notes_filter = Some("project:work status:pending".parse().unwrap());
+        cfg.notes_on_complete = NotesPolicy::Keep;
+        let hooks = Hooks::with_config(cfg.to_static());
+
+        let old_task = Task::new("Dummy Task");
+        let mut new_task = old_task.clone();
+        new_task.project = Some(String::from("work"));
+        let (task_with, _) = hooks.on_modify(old_task, new_task).expect("succeeds");
+        let path = hooks.note_file_path(&task_with);
+        assert!(path.exists());
+
+        // completing the task is up to the notes policy, although the filter does not match
+        let mut completed = task_with.clone();
+        completed.status = Status::Completed;
+        completed.end = Some(chrono::Utc::now());
+        let (completed, _) = hooks.on_modify(task_with, completed).expect("succeeds");
+        assert!(path.exists());
+
+        let mut moved = completed.clone();
+        moved.project = Some(String::from("home"));
+        let (moved, feedback) = hooks.on_modify(completed, moved).expect("succeeds");
+        assert!(moved.annotations.is_empty());
+        assert!(path.exists());
+        assert!(feedback.contains("Kept notes file"));
+    }
+
+    #[test]
+    fn keep_notes_when_filter_no_longer_matches() {
+        let (test_cfg, _tmp_dir) = test_config();
+        let mut cfg = Config::default();
+        cfg.notes_dir = test_cfg.notes_dir.clone();
+        cfg.notes_filter = Some("project:work +wiki due.before:eow".parse().unwrap());
+        let hooks = Hooks::with_config(cfg.to_static());
+
+        let mut task = Task::new("Dummy Task").with_tag("wiki");
+        task.project = Some(String::from("work"));
+        task.due = Some(chrono::Utc::now());
+        let (task, _) = hooks.on_add(task).expect("succeeds");
+        let path = hooks.locate_notes_file(&task).expect("notes file created");
+        let notes = std::fs::read_to_string(&path).unwrap() + "\nmy precious notes\n";
+        std::fs::write(&path, &notes).unwrap();
+
+        let mut postponed = task.clone();
+        postponed.due = Some(chrono::Utc::now() + chrono::Duration::days(40));
+        let (postponed, _) = hooks.on_modify(task, postponed).expect("succeeds");
+        assert!(postponed.annotations.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), notes);
+
+        // back within the filter, the notes are linked again rather than recreated
+        let mut due_again = postponed.clone();
+        due_again.due = Some(chrono::Utc::now());
+        let (due_again, feedback) = hooks.on_modify(postponed, due_again).expect("succeeds");
+        assert_eq!(hooks.locate_notes_file(&due_again), Some(path.clone()));
+        assert_eq!(due_again.annotations.len(), 1);
+        assert!(feedback.contains("Linked"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), notes);
+
+        // removing the tag still removes the notes
+        let mut untagged = due_again.clone();
+        untagged.tags.remove("wiki");
+        hooks.on_modify(due_again, untagged).expect("succeeds");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn keep_notes_when_removing_a_tag_the_filter_ignores() {
+        let (test_cfg, _tmp_dir) = test_config();
+        let mut cfg = Config::default();
+        cfg.notes_dir = test_cfg.notes_dir.clone();
+        cfg.notes_filter = Some("project:work".parse().unwrap());
+        let cfg = cfg.to_static();
+        let hooks = Hooks::with_config(cfg);
+
+        let mut task = Task::new("Dummy Task").with_tag(&cfg.notes_tag);
+        task.project = Some(String::from("work"));
+        let (task, _) = hooks.on_add(task).expect("succeeds");
+        let path = hooks.locate_notes_file(&task).expect("notes file created");
+
+        // one command moves the task out of the filter and removes the default notes tag
+        let mut moved = task.clone();
+        moved.project = Some(String::from("home"));
+        moved.tags.remove(&cfg.notes_tag);
+        let (moved, feedback) = hooks.on_modify(task, moved).expect("succeeds");
+        assert!(path.exists());
+        assert!(moved.annotations.is_empty());
+        assert!(feedback.contains("Kept notes file"));
+    }
+
+    /// Hooks configured with the given policies together with the tagged original and
+    /// modified task of `fixture`, the notes file of the original already created
+    fn setup(
+        fixture: &str,
+        on_complete: NotesPolicy,
+        on_delete: NotesPolicy,
+    ) -> (Hooks, Task, Task, tempfile::TempDir) {
+        let (test_cfg, tmp_dir) = test_config();
+        let mut cfg = Config::default();
+        cfg.notes_dir = test_cfg.notes_dir.clone();
+        cfg.notes_on_complete = on_complete;
+        cfg.notes_on_delete = on_delete;
+        let hooks = Hooks::with_config(cfg.to_static());
+
+        let mut lines = fixture.lines();
+        let original = Task::from_str(lines.next().unwrap()).expect("fixture parses");
+        let modified = Task::from_str(lines.next().unwrap()).expect("fixture parses");
+        let (original, _) = hooks
+            .on_add(original.with_tag("wiki"))
+            .expect("notes file created");
+        let mut modified = modified.with_tag("wiki");
+        modified.annotations = original.annotations.clone();
+
+        (hooks, original, modified, tmp_dir)
+    }
+
+    #[test]
+    fn archive_notes_of_deleted_task() {
+        let (hooks, original, deleted, _tmp_dir) = setup(
+            include_str!("../../examples/task_deleted.json"),
+            NotesPolicy::Keep,
+            NotesPolicy::Archive,
+        );
+
+        let (deleted, feedback) = hooks.on_modify(original, deleted).expect("succeeds");
+        let archived = hooks.locate_notes_file(&deleted).expect("notes file kept");
+        assert!(!hooks.note_file_path(&deleted).exists());
+        assert!(hooks.is_archived(&archived));
+        assert!(feedback.contains("Archived"));
+        assert_eq!(deleted.annotations.len(), 1);
+        assert!(deleted.annotations[0]
+            .description
+            .ends_with(archived.to_str().unwrap()));
+    }
+
+    #[test]
+    fn delete_notes_of_deleted_task() {
+        let (hooks, original, deleted, _tmp_dir) = setup(
+            include_str!("../../examples/task_deleted.json"),
+            NotesPolicy::Keep,
+            NotesPolicy::Delete,
+        );
+
+        let (deleted, feedback) = hooks.on_modify(original, deleted).expect("succeeds");
+        assert!(hooks.locate_notes_file(&deleted).is_none());
+        assert!(deleted.annotations.is_empty());
+        assert_eq!(feedback, "Removed notes file");
+    }
+
+    #[test]
+    fn no_missing_notes_reported_after_removing_them() {
+        let (hooks, original, completed, _tmp_dir) = setup(
+            include_str!("../../examples/task_completed.json"),
+            NotesPolicy::Delete,
+            NotesPolicy::Keep,
+        );
+
+        let (completed, _) = hooks.on_modify(original, completed).expect("succeeds");
+        assert!(hooks.locate_notes_file(&completed).is_none());
+        assert_eq!(hooks.on_exit(vec![completed]).expect("succeeds"), "");
+    }
+
+    #[test]
+    fn stamp_notes_of_completed_task() {
+        let (hooks, original, completed, _tmp_dir) = setup(
+            include_str!("../../examples/task_completed.json"),
+            NotesPolicy::Stamp,
+            NotesPolicy::Keep,
+        );
+
+        let (completed, _) = hooks.on_modify(original, completed).expect("succeeds");
+        let notes_file =
+            NotesFile::read(&hooks.note_file_path(&completed)).expect("notes file kept");
+        let header = notes_file.header().expect("header exists");
+        assert_eq!(header.status(), Some("completed"));
+        assert_eq!(header.end(), completed.end);
+        assert!(header.end().is_some());
+    }
+
+    #[test]
+    fn keep_notes_of_completed_task() {
+        let (hooks, original, completed, _tmp_dir) = setup(
+            include_str!("../../examples/task_completed.json"),
+            NotesPolicy::Keep,
+            NotesPolicy::Keep,
+        );
+
+        let (completed, feedback) = hooks.on_modify(original, completed).expect("succeeds");
+        let notes_file =
+            NotesFile::read(&hooks.note_file_path(&completed)).expect("notes file kept");
+        assert!(notes_file.header().unwrap().end().is_none());
+        assert!(feedback.is_empty());
+    }
+
+    #[test]
+    fn restore_archived_notes_when_undeleting() {
+        let (hooks, original, deleted, _tmp_dir) = setup(
+            include_str!("../../examples/task_deleted.json"),
+            NotesPolicy::Keep,
+            NotesPolicy::Archive,
+        );
+        let (deleted, _) = hooks
+            .on_modify(original.clone(), deleted)
+            .expect("succeeds");
+
+        let mut undeleted = deleted.clone();
+        undeleted.status = Status::Pending;
+        undeleted.end = None;
+        let (undeleted, feedback) = hooks.on_modify(deleted, undeleted).expect("succeeds");
+
+        assert!(hooks.note_file_path(&undeleted).exists());
+        assert!(!hooks.is_archived(&hooks.locate_notes_file(&undeleted).unwrap()));
+        assert!(feedback.starts_with("Restored"));
+        assert_eq!(undeleted.annotations.len(), 1);
+    }
+
+    #[test]
+    fn unstamp_notes_when_reopening() {
+        let (hooks, original, completed, _tmp_dir) = setup(
+            include_str!("../../examples/task_completed.json"),
+            NotesPolicy::Stamp,
+            NotesPolicy::Keep,
+        );
+        let (completed, _) = hooks.on_modify(original, completed).expect("succeeds");
+
+        let mut reopened = completed.clone();
+        reopened.status = Status::Pending;
+        reopened.end = None;
+        let (reopened, _) = hooks.on_modify(completed, reopened).expect("succeeds");
+
+        let notes_file = NotesFile::read(&hooks.note_file_path(&reopened)).expect("exists");
+        let header = notes_file.header().expect("header exists");
+        assert_eq!(header.status(), Some("pending"));
+        assert!(header.end().is_none());
+    }
+
+    #[test]
+    fn recreate_deleted_notes_when_undeleting() {
+        let (hooks, original, deleted, _tmp_dir) = setup(
+            include_str!("../../examples/task_deleted.json"),
+            NotesPolicy::Keep,
+            NotesPolicy::Delete,
+        );
+        let (deleted, _) = hooks.on_modify(original, deleted).expect("succeeds");
+
+        let mut undeleted = deleted.clone();
+        undeleted.status = Status::Pending;
+        let (undeleted, feedback) = hooks.on_modify(deleted, undeleted).expect("succeeds");
+
+        assert!(hooks.note_file_path(&undeleted).exists());
+        assert!(feedback.starts_with("Recreated"));
+        assert_eq!(undeleted.annotations.len(), 1);
+    }
+
+    #[test]
+    fn follow_description_and_project_changes() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let (original, _) = hooks
+            .on_add(Task::new("Dummy Task").with_tag(&cfg.notes_tag))
+            .expect("succeeds");
+        let old_path = hooks
+            .locate_notes_file(&original)
+            .expect("notes file created");
+
+        let mut modified = original.clone();
+        modified.description = String::from("Renamed Task");
+        modified.project = Some(String::from("Home"));
+        let (modified, feedback) = hooks.on_modify(original, modified).expect("succeeds");
+
+        let new_path = hooks.note_file_path(&modified);
+        assert!(new_path.to_str().unwrap().contains("renamed-task"));
+        assert!(new_path.exists());
+        assert!(!old_path.exists());
+        assert!(feedback.starts_with("Moved notes file"));
+        assert_eq!(modified.annotations.len(), 1);
+        assert_eq!(
+            modified.annotations[0].description,
+            format!("taskw:note {}", new_path.display())
+        );
+
+        let notes_file = NotesFile::read(&new_path).expect("notes file moved");
+        assert_eq!(notes_file.header().unwrap().title(), "Renamed Task");
+    }
+
+    #[test]
+    fn keep_notes_path_unless_configured_to_rename() {
+        let (test_cfg, _tmp_dir) = test_config();
+        let mut cfg = Config::default();
+        cfg.notes_dir = test_cfg.notes_dir.clone();
+        cfg.notes_name = "{project}/{slug(description)}".parse().unwrap();
+        cfg.notes_rename = false;
+        let hooks = Hooks::with_config(cfg.to_static());
+
+        let mut task = Task::new("Dummy Task").with_tag("wiki");
+        task.project = Some(String::from("Home"));
+        let (original, _) = hooks.on_add(task).expect("succeeds");
+        let path = hooks
+            .locate_notes_file(&original)
+            .expect("notes file created");
+        assert!(path.ends_with("Home/dummy-task.md"));
+
+        let mut modified = original.clone();
+        modified.description = String::from("Renamed Task");
+        modified.project = None;
+        let (modified, feedback) = hooks.on_modify(original, modified).expect("succeeds");
+
+        assert!(feedback.is_empty());
+        assert_eq!(hooks.locate_notes_file(&modified), Some(path.clone()));
+        let notes_file = NotesFile::read(&path).expect("notes file kept");
+        assert_eq!(notes_file.header().unwrap().title(), "Renamed Task");
+    }
+
+    #[test]
+    fn mirror_task_changes_into_header() {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let (original, _) = hooks
+            .on_add(Task::new("Dummy Task").with_tag(&cfg.notes_tag))
+            .expect("succeeds");
+
+        let mut modified = original.clone().with_tag("urgent");
+        modified.due = Some(original.entry);
+        let (modified, _) = hooks.on_modify(original, modified).expect("succeeds");
+
+        let path = hooks
+            .locate_notes_file(&modified)
+            .expect("notes file exists");
+        let notes_file = NotesFile::read(&path).expect("notes file readable");
+        let header = notes_file.header().expect("header exists");
+        assert_eq!(header.keywords(), ["urgent", "wiki"]);
+        assert_eq!(header.due(), modified.due);
+    }
 }
@@ -0,0 +1,225 @@
+use crate::{Error, Result, Task};
+use log::{debug, warn};
+use std::io::{BufRead, Write};
+
+use super::{Feedback, Hooks};
+
+/// The taskwarrior hook events taskwiki can be called for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookKind {
+    /// No input, feedback only, a non-zero exit status aborts the command
+    Launch,
+    /// One task as input, exactly one task and feedback as output
+    Add,
+    /// Original and modified task as input, exactly one task and feedback as output
+    Modify,
+    /// All added and modified tasks as input, feedback only
+    Exit,
+}
+
+/// Runs a hook following taskwarrior's hook protocol.
+///
+/// On success, `on-add` and `on-modify` write exactly one JSON line followed by the feedback
+/// to stdout, while `on-launch` and `on-exit` write feedback only. On failure, nothing but the
+/// error message is written to stdout (which taskwarrior shows to the user) and a non-zero
+/// exit status is returned. Empty feedback lines are never written, as taskwarrior would try
+/// to parse them as tasks. Diagnostics go to stderr via the logger.
+pub struct HookRunner<'a> {
+    hooks: &'a Hooks,
+}
+
+impl<'a> HookRunner<'a> {
+    pub fn new(hooks: &'a Hooks) -> Self {
+        Self { hooks }
+    }
+
+    /// Run the hook of the given `kind`, returning the exit status for taskwarrior
+    pub fn run<R: BufRead, W: Write>(&self, kind: HookKind, mut input: R, output: &mut W) -> i32 {
+        let result = self.dispatch(kind, &mut input);
+        respond(result, output)
+    }
+
+    fn dispatch<R: BufRead>(
+        &self,
+        kind: HookKind,
+        input: &mut R,
+    ) -> Result<(Option<Task>, Feedback)> {
+        debug!("running {:?} hook", kind);
+        match kind {
+            HookKind::Launch => Ok((None, self.hooks.on_launch()?)),
+            HookKind::Add => {
+                let added = read_task(input)?;
+                let (task, feedback) = self.hooks.on_add(added)?;
+                Ok((Some(task), feedback))
+            }
+            HookKind::Modify => {
+                let original = read_task(input)?;
+                let modified = read_task(input)?;
+                let (task, feedback) = self.hooks.on_modify(original, modified)?;
+                Ok((Some(task), feedback))
+            }
+            HookKind::Exit => Ok((None, self.hooks.on_exit(read_tasks(input)?)?)),
+        }
+    }
+}
+
+/// Write the outcome of a hook to `output` and return the corresponding exit status
+fn respond<W: Write>(result: Result<(Option<Task>, Feedback)>, output: &mut W) -> i32 {
+    let (status, task, feedback) = match result {
+        Ok((task, feedback)) => (0, task, feedback),
+        Err(Error::Rejected(message)) => (1, None, message),
+        Err(err) => {
+            debug!("{:?}", err);
+            (1, None, format!("taskwiki: {}", err.chain()))
+        }
+    };
+
+    let written = write_response(task.as_ref(), &feedback, output);
+    match written {
+        Ok(()) => status,
+        Err(err) => {
+            warn!("cannot write hook output: {}", err);
+            1
+        }
+    }
+}
+
+fn write_response<W: Write>(
+    task: Option<&Task>,
+    feedback: &str,
+    output: &mut W,
+) -> std::io::Result<()> {
+    if let Some(task) = task {
+        writeln!(output, "{}", task)?;
+    }
+    for line in feedback.lines().filter(|line| !line.trim().is_empty()) {
+        writeln!(output, "{}", line)?;
+    }
+    output.flush()
+}
+
+/// Read a single task from the next line of `input`
+fn read_task<R: BufRead>(input: &mut R) -> Result<Task> {
+    let mut json = String::new();
+    let bytes_read = input
+        .read_line(&mut json)
+        .map_err(Error::io("cannot read task from stdin"))?;
+    if bytes_read == 0 {
+        return Err(Error::Hook(String::from(
+            "expected a task on stdin, got end of input",
+        )));
+    }
+    parse_task(&json)
+}
+
+/// Read tasks from `input` until it is closed, one task per line
+fn read_tasks<R: BufRead>(input: &mut R) -> Result<Vec<Task>> {
+    input
+        .lines()
+        .map(|line| line.map_err(Error::io("cannot read tasks from stdin")))
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| parse_task(&line?))
+        .collect()
+}
+
+fn parse_task(json: &str) -> Result<Task> {
+    let (task, warnings) = Task::from_str_lenient(json.trim())?;
+    for warning in warnings {
+        warn!("task {}: {}", task.uuid, warning);
+    }
+    Ok(task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::test_config, *};
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    fn run(kind: HookKind, input: &str) -> (i32, Vec<String>) {
+        let (cfg, _tmp_dir) = test_config();
+        let hooks = Hooks::with_config(cfg);
+        let mut output = vec![];
+        let status = HookRunner::new(&hooks).run(kind, Cursor::new(input), &mut output);
+        let output = String::from_utf8(output).expect("valid utf-8");
+        (status, output.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn add_writes_exactly_one_task_and_no_empty_feedback() {
+        let input = Task::new("Dummy Task").to_string();
+        let (status, lines) = run(HookKind::Add, &input);
+
+        assert_eq!(status, 0);
+        assert_eq!(lines.len(), 1);
+        assert!(Task::from_str(&lines[0]).is_ok());
+    }
+
+    #[test]
+    fn add_writes_feedback_after_the_task() {
+        let input = include_str!("../../examples/add_task.json");
+        let (status, lines) = run(HookKind::Add, input);
+
+        assert_eq!(status, 0);
+        assert_eq!(lines.len(), 2);
+        assert!(Task::from_str(&lines[0]).is_ok());
+        assert!(lines[1].starts_with("Created notes file"));
+    }
+
+    #[test]
+    fn modify_reads_two_tasks() {
+        let input = include_str!("../../examples/add_tag.json");
+        let (status, lines) = run(HookKind::Modify, input);
+
+        assert_eq!(status, 0);
+        let task = Task::from_str(&lines[0]).expect("first line is a task");
+        assert!(task.has_tag("wiki"));
+    }
+
+    #[test]
+    fn missing_input_fails_without_writing_a_task() {
+        let input = include_str!("../../examples/add_task.json");
+        let (status, lines) = run(HookKind::Modify, input);
+
+        assert_eq!(status, 1);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("taskwiki: hook protocol violation"));
+    }
+
+    #[test]
+    fn malformed_input_fails_with_message() {
+        let (status, lines) = run(HookKind::Add, "{\"description\": \n");
+        assert_eq!(status, 1);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("cannot parse task JSON"));
+    }
+
+    #[test]
+    fn launch_and_exit_write_no_tasks() {
+        let (status, lines) = run(HookKind::Launch, "");
+        assert_eq!(status, 0);
+        assert!(lines.is_empty());
+
+        let input = include_str!("../../examples/task_deleted.json");
+        let (status, lines) = run(HookKind::Exit, input);
+        assert_eq!(status, 0);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn rejection_writes_only_the_message() {
+        let task = Task::new("Dummy Task");
+        let mut output = vec![];
+        let status = respond(
+            Err(Error::Rejected(String::from("Not allowed\n\nat all"))),
+            &mut output,
+        );
+        assert_eq!(status, 1);
+        assert_eq!(String::from_utf8(output).unwrap(), "Not allowed\nat all\n");
+
+        let mut output = vec![];
+        let status = respond(Ok((Some(task.clone()), Feedback::new())), &mut output);
+        assert_eq!(status, 0);
+        assert_eq!(String::from_utf8(output).unwrap(), format!("{}\n", task));
+    }
+}
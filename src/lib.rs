@@ -1,9 +1,28 @@
 mod datetime_format;
+mod depends_format;
+mod error;
 mod task;
 
 pub mod cli;
 pub mod config;
+pub mod dates;
+pub mod ff4;
+pub mod filename;
+pub mod filter;
+pub mod graph;
+pub mod history;
 pub mod hooks;
 pub mod notes;
+pub mod recurrence;
+pub mod storage;
+pub mod sync;
+pub mod taskchampion;
+pub mod taskrc;
+pub mod taskwarrior;
+pub mod template;
+pub mod uda;
+pub mod urgency;
+pub mod wiki;
 
-pub use task::{Annotation, Status, Task};
+pub use error::{Error, Result};
+pub use task::{Annotation, Priority, Status, Task};
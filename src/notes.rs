@@ -1,10 +1,67 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::{Error, Result, Task};
+
+/// Replace the section of `content` starting with the line `heading`, up to the next heading of
+/// the same or a higher level outside fenced code, by `section`, or append `section` if there
+/// is none
+pub fn with_section(content: &str, heading: &str, section: &str) -> String {
+    let newline = if content.ends_with('\n') { "\n" } else { "" };
+    let content = content.trim_end();
+    let level = heading_level(heading).unwrap_or(1);
+
+    let mut in_code = false;
+    let mut start = None;
+    let mut end = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let idx = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        match start {
+            None if line.trim_end() == heading => start = Some(idx),
+            Some(_) if heading_level(line).is_some_and(|other| other <= level) => {
+                end = Some(idx);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    match (start, end) {
+        (Some(start), Some(end)) => format!(
+            "{}{}\n{}{}",
+            &content[..start],
+            section,
+            &content[end..],
+            newline
+        ),
+        (Some(start), None) => format!("{}{}", &content[..start], section),
+        (None, _) if content.is_empty() => section.to_string(),
+        (None, _) => format!("{}\n\n{}", content, section),
+    }
+}
+
+/// The level of the markdown heading `line`, e.g. 2 for `## Links`
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.trim().is_empty() || rest.starts_with(' '))).then_some(level)
+}
 
 /// A notes file associated with a taskwarrior task
 pub struct NotesFile {
@@ -32,25 +89,45 @@ impl NotesFile {
         self
     }
 
-    pub fn write(&self) -> Result<(), &'static str> {
-        let mut file =
-            std::fs::File::create(&self.path).map_err(|_| "Cannot open file for writing")?;
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn header(&self) -> Option<&YamlMeta> {
+        self.header.as_ref()
+    }
+
+    pub fn header_mut(&mut self) -> Option<&mut YamlMeta> {
+        self.header.as_mut()
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let context = || format!("cannot write notes file {}", self.path.display());
+        let mut file = std::fs::File::create(&self.path).map_err(Error::io(context()))?;
         if let Some(header) = &self.header {
-            write!(file, "{}---\n\n", header).map_err(|_| "Cannot write header to file")?;
+            let yaml_str = serde_yaml::to_string(header).map_err(Error::yaml(context()))?;
+            write!(file, "{}---\n\n", yaml_str).map_err(Error::io(context()))?;
         }
-        write!(file, "{}", self.content).map_err(|_| "Cannot write content to file")?;
+        write!(file, "{}", self.content).map_err(Error::io(context()))?;
         Ok(())
     }
 
-    pub fn read(path: &Path) -> Result<Self, &'static str> {
-        let document = std::fs::read_to_string(path).map_err(|_| "Cannot read notes file")?;
+    pub fn read(path: &Path) -> Result<Self> {
+        let document = std::fs::read_to_string(path).map_err(Error::io(format!(
+            "cannot read notes file {}",
+            path.display()
+        )))?;
 
         let (header, content) = match split_yaml_header(&document) {
             Some((yaml_str, content_str)) => (
                 YamlMeta::from_str(yaml_str).ok(),
-                content_str.trim().to_string(),
+                content_str.trim_start().to_string(),
             ),
-            None => (None, document.trim().to_string()),
+            None => (None, document.trim_start().to_string()),
         };
 
         Ok(Self {
@@ -61,11 +138,27 @@ impl NotesFile {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct YamlMeta {
     title: String,
     date: NaiveDate,
     keywords: Vec<String>,
+    /// The task this notes file belongs to, linking it independently of its file name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+    /// When the task was completed or deleted, recorded by the `stamp` notes policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<DateTime<Utc>>,
 
     #[serde(flatten)]
     unknown_fields: HashMap<String, Value>,
@@ -77,16 +170,121 @@ impl YamlMeta {
             title: title.to_string(),
             date,
             keywords: vec![],
+            uuid: None,
+            project: None,
+            status: None,
+            due: None,
+            priority: None,
+            modified: None,
+            end: None,
             unknown_fields: HashMap::new(),
         }
     }
+
+    /// A header for the notes file of `task`, mirroring the given `fields`
+    pub fn from_task(task: &Task, fields: &HeaderFields) -> Self {
+        let mut header = Self::new(&task.description, task.entry.naive_local().date());
+        header.update(task, fields);
+        header
+    }
+
+    /// Mirror the title, the UUID linking the notes file to `task` and the given `fields` of
+    /// `task`. All other fields, including those added by the user, are left untouched.
+    pub fn update(&mut self, task: &Task, fields: &HeaderFields) {
+        self.title = task.description.clone();
+        self.uuid = Some(task.uuid);
+        for field in fields.iter() {
+            match field {
+                HeaderField::Project => self.project = task.project.clone(),
+                HeaderField::Tags => {
+                    let mut keywords: Vec<_> = task.tags.iter().cloned().collect();
+                    keywords.sort_unstable();
+                    self.keywords = keywords;
+                }
+                HeaderField::Status => self.status = Some(task.status.as_str().to_string()),
+                HeaderField::Due => self.due = task.due,
+                HeaderField::Priority => {
+                    self.priority = task.priority.as_ref().map(|p| p.as_str().to_string())
+                }
+                HeaderField::Modified => self.modified = Some(task.modified),
+            }
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    pub fn with_uuid(mut self, uuid: Uuid) -> Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.uuid
+    }
+
+    pub fn project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    pub fn set_status(&mut self, status: Option<&str>) {
+        self.status = status.map(String::from);
+    }
+
+    pub fn due(&self) -> Option<DateTime<Utc>> {
+        self.due
+    }
+
+    pub fn priority(&self) -> Option<&str> {
+        self.priority.as_deref()
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    pub fn end(&self) -> Option<DateTime<Utc>> {
+        self.end
+    }
+
+    pub fn set_end(&mut self, end: Option<DateTime<Utc>>) {
+        self.end = end;
+    }
+
+    /// The value of a header field not captured by any other field
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.unknown_fields.get(key)
+    }
+
+    /// Set a header field not captured by any other field
+    pub fn set_field(&mut self, key: &str, value: Value) {
+        self.unknown_fields.insert(key.to_string(), value);
+    }
+
+    /// Remove a header field not captured by any other field, returning its value
+    pub fn remove_field(&mut self, key: &str) -> Option<Value> {
+        self.unknown_fields.remove(key)
+    }
 }
 
 impl FromStr for YamlMeta {
-    type Err = &'static str;
+    type Err = Error;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_yaml::from_str(s).map_err(|_| "Deserialization succeeds")
+    fn from_str(s: &str) -> Result<Self> {
+        serde_yaml::from_str(s).map_err(Error::yaml("cannot deserialize notes header"))
     }
 }
 
@@ -97,9 +295,185 @@ impl std::fmt::Display for YamlMeta {
     }
 }
 
+/// Task attributes that can be mirrored into the YAML header of notes files, besides the
+/// title and the UUID, which are always written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderField {
+    Project,
+    /// The task's tags, written as `keywords`
+    Tags,
+    Status,
+    Due,
+    Priority,
+    Modified,
+}
+
+impl HeaderField {
+    const ALL: [HeaderField; 6] = [
+        HeaderField::Project,
+        HeaderField::Tags,
+        HeaderField::Status,
+        HeaderField::Due,
+        HeaderField::Priority,
+        HeaderField::Modified,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderField::Project => "project",
+            HeaderField::Tags => "tags",
+            HeaderField::Status => "status",
+            HeaderField::Due => "due",
+            HeaderField::Priority => "priority",
+            HeaderField::Modified => "modified",
+        }
+    }
+}
+
+impl FromStr for HeaderField {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.as_str() == s)
+            .ok_or_else(|| format!("'{}' is not a header field", s))
+    }
+}
+
+/// The set of task attributes mirrored into notes headers, given as a comma separated list
+/// like `project,tags` or, in YAML, as a list. By default all fields are mirrored. The UUID
+/// linking notes files to their tasks cannot be left out, so `uuid` is accepted but ignored,
+/// like `none`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "FieldList")]
+pub struct HeaderFields(Vec<HeaderField>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldList {
+    List(Vec<String>),
+    String(String),
+}
+
+impl HeaderFields {
+    pub fn new(fields: &[HeaderField]) -> Self {
+        let mut unique = vec![];
+        for field in fields {
+            if !unique.contains(field) {
+                unique.push(*field);
+            }
+        }
+        Self(unique)
+    }
+
+    /// The fields named `names`, skipping `uuid` and `none`
+    fn from_names<'a>(names: impl Iterator<Item = &'a str>) -> std::result::Result<Self, String> {
+        let fields = names
+            .map(str::trim)
+            .filter(|name| !["", "none", "uuid"].contains(name))
+            .map(HeaderField::from_str)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Self::new(&fields))
+    }
+
+    pub fn contains(&self, field: HeaderField) -> bool {
+        self.0.contains(&field)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = HeaderField> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl Default for HeaderFields {
+    fn default() -> Self {
+        Self(HeaderField::ALL.to_vec())
+    }
+}
+
+impl FromStr for HeaderFields {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::from_names(s.split(','))
+    }
+}
+
+impl TryFrom<FieldList> for HeaderFields {
+    type Error = String;
+
+    fn try_from(list: FieldList) -> std::result::Result<Self, Self::Error> {
+        match list {
+            FieldList::List(fields) => Self::from_names(fields.iter().map(String::as_str)),
+            FieldList::String(s) => s.parse(),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderFields {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "none");
+        }
+        let names: Vec<_> = self.iter().map(|field| field.as_str()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// Search `dir` and its subdirectories for the notes file whose header links it to the task
+/// `uuid`. Only files with extension `ext` are considered.
+pub fn find_notes_file(dir: &Path, ext: &str, uuid: Uuid) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let mut subdirs = vec![];
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            subdirs.push(path);
+        } else if path.extension().is_some_and(|e| e == ext) {
+            let linked = NotesFile::read(&path)
+                .ok()
+                .and_then(|notes_file| notes_file.header?.uuid);
+            if linked == Some(uuid) {
+                return Some(path);
+            }
+        }
+    }
+    subdirs
+        .iter()
+        .find_map(|subdir| find_notes_file(subdir, ext, uuid))
+}
+
+/// All notes files in `dir` and its subdirectories whose header links them to a task. Only
+/// files with extension `ext` are considered.
+pub fn linked_notes_files(dir: &Path, ext: &str) -> Vec<NotesFile> {
+    let mut notes_files = vec![];
+    collect_linked_notes_files(dir, ext, &mut notes_files);
+    notes_files
+}
+
+fn collect_linked_notes_files(dir: &Path, ext: &str, notes_files: &mut Vec<NotesFile>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            collect_linked_notes_files(&path, ext, notes_files);
+        } else if path.extension().is_some_and(|e| e == ext) {
+            match NotesFile::read(&path) {
+                Ok(notes_file) if notes_file.header().and_then(YamlMeta::uuid).is_some() => {
+                    notes_files.push(notes_file)
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Split `s` into its YAML header and the content following it, keeping trailing whitespace
 fn split_yaml_header(s: &str) -> Option<(&str, &str)> {
-    let mut tokens = s.trim().splitn(3, "---").skip(1);
-    Some((tokens.next()?.trim(), tokens.next()?.trim()))
+    let mut tokens = s.trim_start().splitn(3, "---").skip(1);
+    Some((tokens.next()?.trim(), tokens.next()?.trim_start()))
 }
 
 #[cfg(test)]
@@ -119,7 +493,7 @@ mod tests {
     fn deserialize_simple_yaml_meta() {
         let yaml = YamlMeta::from_str(YAML_STR).expect("Deserialization succeeds");
         assert_eq!(yaml.title, "Complex note title");
-        assert_eq!(yaml.date, NaiveDate::from_ymd(2022, 2, 18));
+        assert_eq!(yaml.date, NaiveDate::from_ymd_opt(2022, 2, 18).unwrap());
         assert_eq!(
             yaml.keywords,
             vec!["projectX".to_string(), "withQuotes".to_string()]
@@ -127,6 +501,28 @@ mod tests {
         assert_eq!(yaml.unknown_fields["author"], "That's me");
     }
 
+    #[test]
+    fn replace_section_with_subsections() {
+        let content = "# Notes\n\n\
+                       ## History\n\n- old\n\n### Detail\n\nold detail\n\n\
+                       ```sh\n# not a heading\n```\n\n\
+                       ## Links\n\n### More\n";
+        assert_eq!(
+            with_section(content, "## History", "## History\n\n- new\n"),
+            "# Notes\n\n## History\n\n- new\n\n## Links\n\n### More\n"
+        );
+        assert_eq!(
+            with_section(content, "### More", "### More\n\nnew\n"),
+            content.replace("### More\n", "### More\n\nnew\n")
+        );
+        assert_eq!(
+            with_section("```\n## History\n```", "## History", "## History\n"),
+            "```\n## History\n```\n\n## History\n"
+        );
+        assert_eq!(heading_level("## Links"), Some(2));
+        assert_eq!(heading_level("#hashtag"), None);
+    }
+
     #[test]
     fn split_yaml_header_with_valid_yaml_header() {
         let content_str = "## Document Headline\n\nand some content";
@@ -170,6 +566,20 @@ mod tests {
         assert!(notes_file.header.is_some());
     }
 
+    #[test]
+    fn keep_final_newline_when_reading_and_writing() {
+        let document_str = format!("---\n{}\n---\n\n## Headline\n\nbody\n", YAML_STR);
+        let mut test_notes_file = NamedTempFile::new().expect("created tempfile");
+        write!(test_notes_file, "{}", document_str).expect("writing tempfile");
+
+        let notes_file = NotesFile::read(test_notes_file.path()).expect("reading notes files");
+        assert!(notes_file.header.is_some());
+        assert_eq!(notes_file.content, "## Headline\n\nbody\n");
+        notes_file.write().expect("writing notes file");
+        let written = std::fs::read_to_string(test_notes_file.path()).unwrap();
+        assert!(written.ends_with("\n---\n\n## Headline\n\nbody\n"));
+    }
+
     #[test]
     fn read_notes_files_without_header_from_filesystem() {
         let document_str = "---\n\n## Document Headline\n\nand some content";
@@ -215,4 +625,92 @@ mod tests {
             format!("---\n{}\n---\n\n{}", YAML_STR, content_str)
         );
     }
+
+    #[test]
+    fn find_notes_file_by_uuid_in_subdirectories() {
+        let temp_dir = tempdir().expect("create temporary directory");
+        let uuid = Uuid::new_v4();
+        let date = NaiveDate::from_ymd_opt(2022, 2, 18).unwrap();
+
+        std::fs::create_dir(temp_dir.path().join("project")).expect("create subdirectory");
+        let path = temp_dir.path().join("project").join("renamed.md");
+        NotesFile::new(&path)
+            .with_header(YamlMeta::new("Linked", date).with_uuid(uuid))
+            .write()
+            .expect("writing notes file succeeds");
+        NotesFile::new(&temp_dir.path().join("other.md"))
+            .with_header(YamlMeta::new("Other", date).with_uuid(Uuid::new_v4()))
+            .write()
+            .expect("writing notes file succeeds");
+
+        assert_eq!(find_notes_file(temp_dir.path(), "md", uuid), Some(path));
+        assert_eq!(find_notes_file(temp_dir.path(), "wiki", uuid), None);
+        assert_eq!(find_notes_file(temp_dir.path(), "md", Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn mirror_task_into_header() {
+        let mut task = Task::new("Weekly sync")
+            .with_tag("meeting")
+            .with_tag("team");
+        task.project = Some(String::from("Work"));
+        task.priority = Some(crate::Priority::High);
+
+        let header = YamlMeta::from_task(&task, &HeaderFields::default());
+        assert_eq!(header.title(), "Weekly sync");
+        assert_eq!(header.uuid(), Some(task.uuid));
+        assert_eq!(header.keywords(), ["meeting", "team"]);
+        assert_eq!(header.project(), Some("Work"));
+        assert_eq!(header.status(), Some("pending"));
+        assert_eq!(header.priority(), Some("H"));
+        assert_eq!(header.modified(), Some(task.modified));
+        assert_eq!(header.due(), None);
+
+        let fields = HeaderFields::from_str("uuid, tags").expect("valid fields");
+        let header = YamlMeta::from_task(&task, &fields);
+        assert_eq!(header.uuid(), Some(task.uuid));
+        assert_eq!(header.project(), None);
+        assert_eq!(header.status(), None);
+        assert!(!header.to_string().contains("project"));
+    }
+
+    #[test]
+    fn preserve_user_fields_when_updating_header() {
+        let mut header = YamlMeta::from_str(YAML_STR).expect("Deserialization succeeds");
+        let mut task = Task::new("New title");
+        task.project = Some(String::from("projectY"));
+        header.update(&task, &HeaderFields::default());
+
+        let reparsed = YamlMeta::from_str(&header.to_string()).expect("roundtrip succeeds");
+        assert_eq!(reparsed, header);
+        assert_eq!(reparsed.title(), "New title");
+        assert_eq!(reparsed.project(), Some("projectY"));
+        assert_eq!(reparsed.date, NaiveDate::from_ymd_opt(2022, 2, 18).unwrap());
+        assert_eq!(reparsed.field("author"), Some(&Value::from("That's me")));
+    }
+
+    #[test]
+    fn parse_header_fields() {
+        let fields: HeaderFields = "uuid,due,due".parse().expect("valid fields");
+        assert_eq!(fields.to_string(), "due");
+        assert_eq!(HeaderFields::from_str("none").unwrap().to_string(), "none");
+        assert!(HeaderFields::from_str("due,title").is_err());
+
+        let fields: HeaderFields =
+            serde_yaml::from_str("[project, uuid, tags]").expect("valid list");
+        assert!(fields.contains(HeaderField::Tags));
+        assert_eq!(fields.to_string(), "project,tags");
+        assert!(serde_yaml::from_str::<HeaderFields>("[project, title]").is_err());
+    }
+
+    #[test]
+    fn always_link_header_to_task() {
+        let task = Task::new("Weekly sync");
+        let header = YamlMeta::from_task(&task, &HeaderFields::from_str("none").unwrap());
+        assert_eq!(header.uuid(), Some(task.uuid));
+
+        let mut header = YamlMeta::new("Unlinked", task.entry.date_naive());
+        header.update(&task, &HeaderFields::from_str("project").unwrap());
+        assert_eq!(header.uuid(), Some(task.uuid));
+    }
 }
@@ -0,0 +1,319 @@
+use crate::dates::{DateContext, Duration, Zone};
+use crate::filename::short_uuid;
+use crate::{Error, Result, Status, Task};
+use chrono::{DateTime, Datelike, Utc, Weekday};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// How often a recurring task recurs, as given by its `recur` attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recurrence {
+    /// Every Monday to Friday
+    Weekdays,
+    /// A fixed period like `daily`, `weekly`, `monthly`, `quarterly`, `annual`, `3d` or `2w`,
+    /// months and years being calendar months and years
+    Every(Duration),
+}
+
+impl Recurrence {
+    /// The due date of the instance after the one due at `date`, in the time zone `zone`
+    pub fn next(&self, date: DateTime<Utc>, zone: Zone) -> DateTime<Utc> {
+        let period = match self {
+            Recurrence::Weekdays => match zone.to_naive(date).weekday() {
+                Weekday::Fri => Duration::days(3),
+                Weekday::Sat => Duration::days(2),
+                _ => Duration::days(1),
+            },
+            Recurrence::Every(period) => *period,
+        };
+        period.add_to(date, zone)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("weekdays") {
+            return Ok(Recurrence::Weekdays);
+        }
+        let period = Duration::from_str(s)?;
+        if period.approximate() <= chrono::Duration::zero() {
+            return Err(format!("'{}' is not a positive period", s));
+        }
+        Ok(Recurrence::Every(period))
+    }
+}
+
+/// The character the `mask` of a recurring parent records an instance of `status` with
+fn mask_char(status: &Status) -> char {
+    match status {
+        Status::Completed => '+',
+        Status::Deleted => 'X',
+        Status::Waiting => 'W',
+        Status::Pending | Status::Recurring => '-',
+    }
+}
+
+/// The due dates of all instances of the recurring `parent` that should exist at
+/// `dates.now()`: starting with the parent's due date, every instance due by now and the next
+/// `limit` instances, like taskwarrior's `recurrence.limit`, but none after the parent's
+/// `until`. Empty once all instances have been created and none is pending anymore.
+pub fn due_dates(parent: &Task, dates: &DateContext, limit: usize) -> Result<Vec<DateTime<Utc>>> {
+    let recurrence = recurrence(parent)?;
+    let mut due = parent.due.ok_or_else(|| {
+        Error::Recurrence(format!(
+            "recurring task {} has no due date",
+            short_uuid(parent)
+        ))
+    })?;
+
+    let mut due_dates = vec![];
+    let mut upcoming = 0;
+    loop {
+        if parent.until.is_some_and(|until| due > until) {
+            let mask = parent.mask.as_deref().unwrap_or_default();
+            if mask.chars().count() == due_dates.len() && !mask.contains(['-', 'W']) {
+                return Ok(vec![]);
+            }
+            return Ok(due_dates);
+        }
+        due_dates.push(due);
+        if due > dates.now() {
+            upcoming += 1;
+        }
+        if upcoming >= limit.max(1) {
+            return Ok(due_dates);
+        }
+        due = recurrence.next(due, dates.zone());
+    }
+}
+
+fn recurrence(parent: &Task) -> Result<Recurrence> {
+    let recur = parent
+        .recur
+        .as_deref()
+        .ok_or_else(|| Error::Recurrence(format!("task {} does not recur", short_uuid(parent))))?;
+    recur.parse().map_err(|message| {
+        Error::Recurrence(format!(
+            "task {} has an invalid recurrence: {}",
+            short_uuid(parent),
+            message
+        ))
+    })
+}
+
+/// Create the instances of the recurring `parent` that are missing at `dates.now()`, see
+/// [`due_dates`], and record them in the parent's `mask`
+pub fn expand(parent: &mut Task, dates: &DateContext, limit: usize) -> Result<Vec<Task>> {
+    let mut mask = parent.mask.clone().unwrap_or_default();
+    let mut instances = vec![];
+    for (imask, due) in due_dates(parent, dates, limit)?
+        .into_iter()
+        .enumerate()
+        .skip(mask.chars().count())
+    {
+        let instance = instance(parent, imask as u64, due, dates.now());
+        mask.push(mask_char(&instance.status));
+        instances.push(instance);
+    }
+    if !instances.is_empty() {
+        parent.mask = Some(mask);
+        parent.modified = dates.now();
+    }
+    Ok(instances)
+}
+
+/// The instance `imask` of `parent` due at `due`, created at `now`. Like in taskwarrior, a
+/// wait date keeps its distance to the due date and makes the instance wait.
+pub fn instance(parent: &Task, imask: u64, due: DateTime<Utc>, now: DateTime<Utc>) -> Task {
+    let mut instance = parent.clone();
+    instance.id = None;
+    instance.uuid = Uuid::new_v4();
+    instance.status = Status::Pending;
+    instance.entry = now;
+    instance.modified = now;
+    instance.due = Some(due);
+    instance.parent = Some(parent.uuid);
+    instance.imask = Some(imask);
+    instance.mask = None;
+    instance.urgency = None;
+    if let (Some(wait), Some(parent_due)) = (parent.wait, parent.due) {
+        instance.wait = Some(due + (wait - parent_due));
+        instance.status = Status::Waiting;
+    }
+    instance
+}
+
+/// Record the status of `instances` of `parent` in its `mask`, returning whether it changed.
+/// Tasks that are no instances of `parent` are ignored.
+pub fn update_mask(parent: &mut Task, instances: &[Task]) -> bool {
+    let mut mask: Vec<char> = parent.mask.as_deref().unwrap_or_default().chars().collect();
+    let mut changed = false;
+    for instance in instances
+        .iter()
+        .filter(|task| task.parent == Some(parent.uuid))
+    {
+        let slot = match instance
+            .imask
+            .and_then(|imask| mask.get_mut(imask as usize))
+        {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let status = mask_char(&instance.status);
+        if *slot != status {
+            *slot = status;
+            changed = true;
+        }
+    }
+    if changed {
+        parent.mask = Some(mask.into_iter().collect());
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 9, 0, 0).unwrap()
+    }
+
+    /// Tuesday, March 1st 2022, noon in UTC
+    fn dates() -> DateContext {
+        DateContext::at(Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap())
+            .with_zone("UTC".parse().unwrap())
+    }
+
+    fn parent(recur: &str, due: DateTime<Utc>) -> Task {
+        let mut parent = Task::new("Water the plants");
+        parent.status = Status::Recurring;
+        parent.recur = Some(String::from(recur));
+        parent.due = Some(due);
+        parent
+    }
+
+    fn days(due_dates: &[DateTime<Utc>]) -> Vec<String> {
+        due_dates
+            .iter()
+            .map(|date| date.format("%m-%d").to_string())
+            .collect()
+    }
+
+    fn due_days(recur: &str, due: DateTime<Utc>) -> Vec<String> {
+        days(&due_dates(&parent(recur, due), &dates(), 1).expect("valid recurrence"))
+    }
+
+    #[test]
+    fn compute_due_dates() {
+        assert_eq!(
+            due_days("daily", date(2022, 2, 27)),
+            ["02-27", "02-28", "03-01", "03-02"]
+        );
+        assert_eq!(
+            due_days("weekly", date(2022, 2, 20)),
+            ["02-20", "02-27", "03-06"]
+        );
+        assert_eq!(
+            due_days("2w", date(2022, 2, 1)),
+            ["02-01", "02-15", "03-01", "03-15"]
+        );
+        assert_eq!(
+            due_days("3d", date(2022, 2, 25)),
+            ["02-25", "02-28", "03-03"]
+        );
+        // the day of the month is clamped, and stays so like in taskwarrior
+        assert_eq!(
+            due_days("monthly", date(2021, 12, 31)),
+            ["12-31", "01-31", "02-28", "03-28"]
+        );
+        assert_eq!(
+            due_days("quarterly", date(2021, 11, 30)),
+            ["11-30", "02-28", "05-28"]
+        );
+        assert_eq!(
+            due_days("annual", date(2020, 2, 29)),
+            ["02-29", "02-28", "02-28", "02-28"]
+        );
+        // Friday, Saturday, Monday
+        assert_eq!(
+            due_days("weekdays", date(2022, 2, 25)),
+            ["02-25", "02-28", "03-01", "03-02"]
+        );
+        assert_eq!(
+            due_days("weekdays", date(2022, 2, 26)),
+            ["02-26", "02-28", "03-01", "03-02"]
+        );
+
+        let mut parent = parent("daily", date(2022, 2, 27));
+        let three = due_dates(&parent, &dates(), 3).unwrap();
+        assert_eq!(
+            days(&three),
+            ["02-27", "02-28", "03-01", "03-02", "03-03", "03-04"]
+        );
+        parent.until = Some(date(2022, 2, 28));
+        assert_eq!(
+            days(&due_dates(&parent, &dates(), 1).unwrap()),
+            ["02-27", "02-28"]
+        );
+        parent.mask = Some(String::from("+X"));
+        assert!(due_dates(&parent, &dates(), 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_recurrences() {
+        for recur in ["sometimes", "-1d", "0d", "P"] {
+            assert!(Recurrence::from_str(recur).is_err(), "{} is invalid", recur);
+        }
+        let err = due_dates(&parent("often", date(2022, 2, 1)), &dates(), 1).unwrap_err();
+        assert!(err.to_string().contains("invalid recurrence"));
+        let mut parent = parent("daily", date(2022, 2, 1));
+        parent.due = None;
+        assert!(due_dates(&parent, &dates(), 1).is_err());
+    }
+
+    #[test]
+    fn expand_parents_into_instances() {
+        let mut parent = parent("weekly", date(2022, 2, 20)).with_tag("garden");
+        parent.mask = Some(String::from("+"));
+        let instances = expand(&mut parent, &dates(), 1).expect("valid recurrence");
+
+        assert_eq!(parent.mask.as_deref(), Some("+--"));
+        assert_eq!(instances.len(), 2);
+        for (instance, imask) in instances.iter().zip(1..) {
+            assert_eq!(instance.parent, Some(parent.uuid));
+            assert_eq!(instance.imask, Some(imask));
+            assert_eq!(instance.status, Status::Pending);
+            assert_eq!(instance.recur, parent.recur);
+            assert_eq!(instance.mask, None);
+            assert!(instance.has_tag("garden"));
+            assert_ne!(instance.uuid, parent.uuid);
+        }
+        assert_eq!(instances[1].due, Some(date(2022, 3, 6)));
+        assert!(expand(&mut parent, &dates(), 1).unwrap().is_empty());
+
+        let mut waiting = self::parent("monthly", date(2022, 3, 10));
+        waiting.wait = Some(date(2022, 3, 7));
+        let instances = expand(&mut waiting, &dates(), 1).unwrap();
+        assert_eq!(waiting.mask.as_deref(), Some("W"));
+        assert_eq!(instances[0].status, Status::Waiting);
+        assert_eq!(instances[0].wait, Some(date(2022, 3, 7)));
+    }
+
+    #[test]
+    fn record_instance_status_in_mask() {
+        let mut parent = parent("daily", date(2022, 2, 27));
+        let mut instances = expand(&mut parent, &dates(), 1).unwrap();
+        assert_eq!(parent.mask.as_deref(), Some("----"));
+
+        instances[0].status = Status::Completed;
+        instances[1].status = Status::Deleted;
+        instances.push(Task::new("Unrelated"));
+        assert!(update_mask(&mut parent, &instances));
+        assert_eq!(parent.mask.as_deref(), Some("+X--"));
+        assert!(!update_mask(&mut parent, &instances));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::ff4::TaskData;
+use crate::taskwarrior::TaskWarrior;
+use crate::{Result, Status, Task};
+use uuid::Uuid;
+
+/// A task database tasks are loaded from and saved to, be it through the `task` binary, the
+/// FF4 files of taskwarrior 2.x or the TaskChampion replica of taskwarrior 3
+pub trait Storage {
+    /// All tasks in the database
+    fn load(&self) -> Result<Vec<Task>>;
+
+    /// Add `tasks`, or replace the tasks with the same UUIDs
+    fn save(&self, tasks: &[Task]) -> Result<()>;
+
+    /// The task `uuid`, if it exists
+    fn load_task(&self, uuid: Uuid) -> Result<Option<Task>> {
+        Ok(self.load()?.into_iter().find(|task| task.uuid == uuid))
+    }
+}
+
+impl Storage for TaskWarrior {
+    fn load(&self) -> Result<Vec<Task>> {
+        self.export(&[])
+    }
+
+    fn save(&self, tasks: &[Task]) -> Result<()> {
+        self.import(tasks)
+    }
+
+    fn load_task(&self, uuid: Uuid) -> Result<Option<Task>> {
+        Ok(self.export(&[&uuid.to_string()])?.into_iter().next())
+    }
+}
+
+impl Storage for TaskData {
+    fn load(&self) -> Result<Vec<Task>> {
+        self.tasks()
+    }
+
+    /// Tasks are replaced where they are, new ones are added to `pending.data` unless they are
+    /// completed or deleted
+    fn save(&self, tasks: &[Task]) -> Result<()> {
+        let mut pending = self.pending()?;
+        let mut completed = self.completed()?;
+        let (mut pending_changed, mut completed_changed) = (false, false);
+
+        for task in tasks {
+            if let Some(existing) = pending.iter_mut().find(|t| t.uuid == task.uuid) {
+                *existing = task.clone();
+                pending_changed = true;
+            } else if let Some(existing) = completed.iter_mut().find(|t| t.uuid == task.uuid) {
+                *existing = task.clone();
+                completed_changed = true;
+            } else if matches!(task.status, Status::Completed | Status::Deleted) {
+                completed.push(task.clone());
+                completed_changed = true;
+            } else {
+                pending.push(task.clone());
+                pending_changed = true;
+            }
+        }
+
+        if pending_changed {
+            self.write_pending(&pending)?;
+        }
+        if completed_changed {
+            self.write_completed(&completed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn save_tasks_in_task_data() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let storage = TaskData::open(dir.path()).writable();
+        let mut task = Task::new("Dummy Task");
+        storage.save(&[task.clone()]).expect("saving succeeds");
+
+        task.status = Status::Completed;
+        let mut done = Task::new("Done already");
+        done.status = Status::Completed;
+        storage
+            .save(&[task.clone(), done])
+            .expect("saving succeeds");
+
+        // completed tasks stay in pending.data until taskwarrior garbage collects them
+        let pending = storage.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].status, Status::Completed);
+        assert_eq!(storage.completed().unwrap().len(), 1);
+
+        let loaded = storage.load_task(task.uuid).unwrap().expect("task exists");
+        assert_eq!(loaded.description, "Dummy Task");
+        assert_eq!(storage.load().unwrap().len(), 2);
+    }
+}
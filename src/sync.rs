@@ -0,0 +1,366 @@
+use crate::config::Config;
+use crate::notes::{linked_notes_files, HeaderField, NotesFile, YamlMeta};
+use crate::storage::Storage;
+use crate::{Error, Priority, Result, Status, Task};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// The side of a task and its notes file that has been edited last and therefore wins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winner {
+    /// The task has been modified after the notes file, its header is rewritten
+    Task,
+    /// The notes file has been modified after the task, the task is changed accordingly
+    Notes,
+}
+
+/// A task and its notes file that are out of sync, together with the resolution
+pub struct SyncChange {
+    pub winner: Winner,
+    /// The notes file, its header already updated to mirror the (resulting) task
+    pub notes_file: NotesFile,
+    /// The changed task to be imported into taskwarrior, if the notes file won
+    pub task: Option<Task>,
+    /// Human readable list of changed attributes
+    pub diff: Vec<String>,
+}
+
+impl std::fmt::Display for SyncChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.winner {
+            Winner::Task => "task -> notes",
+            Winner::Notes => "notes -> task",
+        };
+        write!(f, "{} ({})", self.notes_file.path().display(), direction)?;
+        for line in &self.diff {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Synchronizes notes headers and tasks in both directions, the last writer wins
+pub struct Sync<'a> {
+    config: &'a Config,
+}
+
+impl<'a> Sync<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self { config }
+    }
+
+    /// Compare every notes file in the notes directory with its task in `tasks`
+    pub fn plan(&self, tasks: Vec<Task>, notes_files: Vec<NotesFile>) -> Vec<SyncChange> {
+        let mut tasks: HashMap<_, _> = tasks.into_iter().map(|task| (task.uuid, task)).collect();
+
+        notes_files
+            .into_iter()
+            .filter_map(|notes_file| {
+                let uuid = notes_file.header()?.uuid()?;
+                match tasks.remove(&uuid) {
+                    Some(task) => self.compare(task, notes_file),
+                    None => {
+                        warn!("no task {} for {:?}", uuid, notes_file.path());
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn compare(&self, task: Task, mut notes_file: NotesFile) -> Option<SyncChange> {
+        let fields = &self.config.notes_fields;
+        let header = notes_file.header()?.clone();
+        let mut mirrored = header.clone();
+        mirrored.update(&task, fields);
+        if mirrored == header {
+            return None;
+        }
+
+        let edited = std::fs::metadata(notes_file.path())
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from)
+            .ok();
+        if edited.is_some_and(|edited| edited > task.modified) {
+            let changed = self.apply_header(&task, &header);
+            let diff = task_diff(&task, &changed);
+            if !diff.is_empty() {
+                notes_file.header_mut()?.update(&changed, fields);
+                return Some(SyncChange {
+                    winner: Winner::Notes,
+                    notes_file,
+                    task: Some(changed),
+                    diff,
+                });
+            }
+        }
+
+        let diff = header_diff(&header, &mirrored);
+        *notes_file.header_mut()? = mirrored;
+        Some(SyncChange {
+            winner: Winner::Task,
+            notes_file,
+            task: None,
+            diff,
+        })
+    }
+
+    /// `task` changed to match the title and the mirrored fields of `header`
+    fn apply_header(&self, task: &Task, header: &YamlMeta) -> Task {
+        let mut changed = task.clone();
+        changed.description = header.title().to_string();
+
+        for field in self.config.notes_fields.iter() {
+            match field {
+                HeaderField::Project => changed.project = header.project().map(String::from),
+                HeaderField::Tags => {
+                    changed.tags = header.keywords().iter().cloned().collect();
+                    // removing the notes tag would remove the notes file as well
+                    if task.has_tag(&self.config.notes_tag) {
+                        changed.tags.insert(self.config.notes_tag.clone());
+                    }
+                }
+                HeaderField::Status => match header.status().map(str::parse::<Status>) {
+                    Some(Ok(status)) => changed.status = status,
+                    Some(Err(err)) => warn!("task {}: {}", task.uuid, err),
+                    None => {}
+                },
+                HeaderField::Due => changed.due = header.due(),
+                HeaderField::Priority => {
+                    changed.priority = header.priority().map(|p| Priority::from(p.to_string()))
+                }
+                HeaderField::Modified => {}
+            }
+        }
+
+        if changed.status != task.status {
+            changed.end = match changed.status {
+                Status::Completed | Status::Deleted => Some(Utc::now()),
+                _ => None,
+            };
+        }
+        changed.modified = Utc::now();
+        changed
+    }
+
+    /// Write the updated notes files and return the tasks to be imported into taskwarrior
+    pub fn apply(&self, changes: Vec<SyncChange>) -> Result<Vec<Task>> {
+        let mut tasks = vec![];
+        for change in changes {
+            debug!("applying {}", change);
+            change.notes_file.write()?;
+            tasks.extend(change.task);
+        }
+        Ok(tasks)
+    }
+}
+
+/// Human readable list of the attributes changed from `old` to `new`
+pub(crate) fn task_diff(old: &Task, new: &Task) -> Vec<String> {
+    let tags = |task: &Task| {
+        let mut tags: Vec<_> = task.tags.iter().cloned().collect();
+        tags.sort_unstable();
+        Some(tags.join(", "))
+    };
+    let priority = |task: &Task| task.priority.as_ref().map(|p| p.as_str().to_string());
+
+    let mut diff = vec![];
+    let description = |task: &Task| Some(task.description.clone());
+    push_diff(&mut diff, "description", description(old), description(new));
+    push_diff(
+        &mut diff,
+        "project",
+        old.project.clone(),
+        new.project.clone(),
+    );
+    push_diff(&mut diff, "tags", tags(old), tags(new));
+    let status = |task: &Task| Some(task.status.as_str().to_string());
+    push_diff(&mut diff, "status", status(old), status(new));
+    push_diff(&mut diff, "start", rfc3339(old.start), rfc3339(new.start));
+    push_diff(&mut diff, "due", rfc3339(old.due), rfc3339(new.due));
+    push_diff(&mut diff, "priority", priority(old), priority(new));
+    diff
+}
+
+fn header_diff(old: &YamlMeta, new: &YamlMeta) -> Vec<String> {
+    let owned = |value: Option<&str>| value.map(String::from);
+    let keywords = |header: &YamlMeta| Some(header.keywords().join(", "));
+
+    let mut diff = vec![];
+    push_diff(
+        &mut diff,
+        "title",
+        owned(Some(old.title())),
+        owned(Some(new.title())),
+    );
+    push_diff(
+        &mut diff,
+        "project",
+        owned(old.project()),
+        owned(new.project()),
+    );
+    push_diff(&mut diff, "keywords", keywords(old), keywords(new));
+    push_diff(
+        &mut diff,
+        "status",
+        owned(old.status()),
+        owned(new.status()),
+    );
+    push_diff(&mut diff, "due", rfc3339(old.due()), rfc3339(new.due()));
+    push_diff(
+        &mut diff,
+        "priority",
+        owned(old.priority()),
+        owned(new.priority()),
+    );
+    diff
+}
+
+fn rfc3339(date: Option<DateTime<Utc>>) -> Option<String> {
+    date.map(|date| date.to_rfc3339())
+}
+
+fn push_diff(diff: &mut Vec<String>, name: &str, old: Option<String>, new: Option<String>) {
+    if old != new {
+        let show = |value: Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => String::from("(none)"),
+        };
+        diff.push(format!("{}: {} -> {}", name, show(old), show(new)));
+    }
+}
+
+/// Synchronize all notes files with their tasks in `storage`, writing a report to `output`.
+/// With `dry_run` nothing is changed and the report shows what would be.
+pub fn run<W: Write>(
+    config: &Config,
+    storage: &dyn Storage,
+    dry_run: bool,
+    output: &mut W,
+) -> Result<()> {
+    let mut tasks = storage.load()?;
+    tasks.retain(|task| config.wants_notes(task));
+    let notes_files = linked_notes_files(&config.notes_dir, &config.notes_ext);
+    let sync = Sync::new(config);
+    let changes = sync.plan(tasks, notes_files);
+
+    let context = || "cannot write sync report";
+    for change in changes.iter().filter(|change| !change.diff.is_empty()) {
+        writeln!(output, "{}", change).map_err(Error::io(context()))?;
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let tasks = sync.apply(changes)?;
+    storage.save(&tasks)?;
+    debug!("imported {} changed tasks", tasks.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::HeaderFields;
+    use chrono::Duration;
+    use tempfile::{tempdir, TempDir};
+
+    fn setup(task: &Task) -> (Config, NotesFile, TempDir) {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let mut config = Config::default();
+        config.notes_dir = dir.path().to_path_buf();
+        let notes_file = NotesFile::new(&dir.path().join("note.md"))
+            .with_header(YamlMeta::from_task(task, &HeaderFields::default()));
+        notes_file.write().expect("writing notes file succeeds");
+        (config, notes_file, dir)
+    }
+
+    fn reread(notes_file: &NotesFile) -> NotesFile {
+        NotesFile::read(notes_file.path()).expect("notes file readable")
+    }
+
+    #[test]
+    fn nothing_to_do_when_in_sync() {
+        let task = Task::new("Dummy Task").with_tag("wiki");
+        let (config, notes_file, _dir) = setup(&task);
+        let changes = Sync::new(&config).plan(vec![task], vec![reread(&notes_file)]);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn newer_notes_header_changes_task() {
+        let mut task = Task::new("Dummy Task").with_tag("wiki");
+        task.modified = Utc::now() - Duration::hours(1);
+        let (config, mut notes_file, _dir) = setup(&task);
+
+        let header = notes_file.header_mut().unwrap();
+        header.set_title("Edited in the notes");
+        header.set_status(Some("completed"));
+        notes_file.write().unwrap();
+
+        let sync = Sync::new(&config);
+        let changes = sync.plan(vec![task.clone()], vec![reread(&notes_file)]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].winner, Winner::Notes);
+        assert_eq!(
+            changes[0].diff,
+            vec![
+                "description: \"Dummy Task\" -> \"Edited in the notes\"",
+                "status: \"pending\" -> \"completed\"",
+            ]
+        );
+
+        let changed = changes[0].task.as_ref().expect("task changed");
+        assert_eq!(changed.status, Status::Completed);
+        assert!(changed.end.is_some());
+        assert!(changed.has_tag("wiki"));
+        assert!(changed.modified > task.modified);
+
+        let tasks = sync.apply(changes).expect("applying succeeds");
+        assert_eq!(tasks.len(), 1);
+        let header = reread(&notes_file).header().unwrap().clone();
+        assert_eq!(header.modified(), Some(tasks[0].modified));
+    }
+
+    #[test]
+    fn newer_task_rewrites_notes_header() {
+        let mut task = Task::new("Dummy Task").with_tag("wiki");
+        let (config, notes_file, _dir) = setup(&task);
+        task.description = String::from("Edited in taskwarrior");
+        task.tags.insert(String::from("later"));
+        task.modified = Utc::now() + Duration::hours(1);
+
+        let sync = Sync::new(&config);
+        let changes = sync.plan(vec![task.clone()], vec![reread(&notes_file)]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].winner, Winner::Task);
+        assert!(changes[0].task.is_none());
+        assert_eq!(
+            changes[0].to_string(),
+            format!(
+                "{} (task -> notes)\n  \
+                 title: \"Dummy Task\" -> \"Edited in taskwarrior\"\n  \
+                 keywords: \"wiki\" -> \"later, wiki\"",
+                notes_file.path().display()
+            )
+        );
+
+        let tasks = sync.apply(changes).expect("applying succeeds");
+        assert!(tasks.is_empty());
+        let notes_file = reread(&notes_file);
+        assert_eq!(
+            notes_file.header().unwrap().title(),
+            "Edited in taskwarrior"
+        );
+    }
+
+    #[test]
+    fn notes_without_task_are_skipped() {
+        let task = Task::new("Dummy Task").with_tag("wiki");
+        let (config, notes_file, _dir) = setup(&task);
+        let changes = Sync::new(&config).plan(vec![], vec![reread(&notes_file)]);
+        assert!(changes.is_empty());
+    }
+}
@@ -1,14 +1,20 @@
-use crate::datetime_format;
+use crate::{datetime_format, depends_format, Error};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
 use uuid::Uuid;
 
 /// A Taskwarrior task
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Task {
+    /// Working set index as reported by `task export`, not part of hook input.
+    #[serde(default)]
+    pub id: Option<u64>,
+
     /// The state of the task, one of ["pending", "deleted", "completed", "waiting", "recurring"].
     pub status: Status,
 
@@ -40,6 +46,59 @@ pub struct Task {
     #[serde(with = "datetime_format")]
     pub modified: DateTime<Utc>,
 
+    /// Datetime the task is due.
+    #[serde(default, with = "datetime_format::option")]
+    pub due: Option<DateTime<Utc>>,
+
+    /// Datetime before which the task is not considered ready to be worked on.
+    #[serde(default, with = "datetime_format::option")]
+    pub scheduled: Option<DateTime<Utc>>,
+
+    /// Datetime until which the task is hidden from typical view.
+    #[serde(default, with = "datetime_format::option")]
+    pub wait: Option<DateTime<Utc>>,
+
+    /// Datetime after which the task is automatically deleted.
+    #[serde(default, with = "datetime_format::option")]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Datetime the task has been started, i.e. it is active.
+    #[serde(default, with = "datetime_format::option")]
+    pub start: Option<DateTime<Utc>>,
+
+    /// Datetime the task has been completed or deleted.
+    #[serde(default, with = "datetime_format::option")]
+    pub end: Option<DateTime<Utc>>,
+
+    /// Priority of the task, usually one of "H", "M" or "L".
+    #[serde(default)]
+    pub priority: Option<Priority>,
+
+    /// UUIDs of the tasks this task depends on.
+    /// Accepts both a JSON array and the legacy comma separated string form.
+    #[serde(default, with = "depends_format")]
+    pub depends: Vec<Uuid>,
+
+    /// Recurrence period of a recurring task, e.g. "weekly" or "3d".
+    #[serde(default)]
+    pub recur: Option<String>,
+
+    /// UUID of the recurring parent task this task is an instance of.
+    #[serde(default)]
+    pub parent: Option<Uuid>,
+
+    /// Index of this recurring instance within the recurrence of its parent.
+    #[serde(default)]
+    pub imask: Option<u64>,
+
+    /// Status of all recurring instances of a parent task, one character per instance.
+    #[serde(default)]
+    pub mask: Option<String>,
+
+    /// Urgency as computed by taskwarrior, only part of `task export` output.
+    #[serde(default)]
+    pub urgency: Option<f64>,
+
     /// All other attributes not explicitly captured by any other given field.
     #[serde(flatten)]
     pub unknown_fields: HashMap<String, Value>,
@@ -49,6 +108,7 @@ impl Task {
     pub fn new(description: &str) -> Self {
         let now = Utc::now();
         Self {
+            id: None,
             status: Status::Pending,
             uuid: Uuid::new_v4(),
             entry: now,
@@ -57,6 +117,19 @@ impl Task {
             tags: HashSet::new(),
             annotations: vec![],
             modified: now,
+            due: None,
+            scheduled: None,
+            wait: None,
+            until: None,
+            start: None,
+            end: None,
+            priority: None,
+            depends: vec![],
+            recur: None,
+            parent: None,
+            imask: None,
+            mask: None,
+            urgency: None,
             unknown_fields: HashMap::new(),
         }
     }
@@ -67,19 +140,277 @@ impl Task {
     }
 
     pub fn has_tag(&self, tag: &str) -> bool {
-        self.tags.contains(&tag.to_string())
+        self.tags.contains(tag)
+    }
+}
+
+impl Task {
+    /// Deserialize a task from its JSON representation.
+    /// If an attribute has an invalid value, the returned error names that attribute.
+    pub fn from_value(value: Value) -> crate::Result<Self> {
+        if let Value::Object(attributes) = &value {
+            for (name, attribute) in attributes {
+                check_attribute(name, attribute).map_err(Error::json(format!(
+                    "cannot deserialize task: invalid value {} for attribute '{}'",
+                    attribute, name
+                )))?;
+            }
+        }
+        serde_json::from_value(value).map_err(Error::json("cannot deserialize task"))
+    }
+
+    /// Parse a task leniently: odd values are normalized where possible (e.g. empty `tags`,
+    /// numbers given as strings) and otherwise kept untyped in `unknown_fields` instead of
+    /// rejecting the whole task. Only a missing or invalid `uuid`, `status` or `description`
+    /// is an error. Returns a warning for every attribute that needed fixing up.
+    pub fn from_str_lenient(s: &str) -> crate::Result<(Self, Vec<String>)> {
+        let value: Value =
+            serde_json::from_str(s).map_err(Error::json("cannot parse task JSON"))?;
+        let mut attributes = match value {
+            Value::Object(attributes) => attributes,
+            _ => return Self::from_value(value).map(|task| (task, vec![])),
+        };
+
+        let mut warnings = vec![];
+        let mut untyped = Map::new();
+        for name in attributes.keys().cloned().collect::<Vec<_>>() {
+            if REQUIRED_ATTRIBUTES.contains(&name.as_str()) {
+                continue;
+            }
+            let attribute = attributes.get_mut(&name).expect("key exists");
+            if let Some(malformed) = split_malformed_annotations(&name, attribute) {
+                warnings.push(format!("kept malformed annotations {} untyped", malformed));
+                untyped.insert(name.clone(), malformed);
+            }
+            if let Some(warning) = normalize_attribute(&name, attribute) {
+                warnings.push(warning);
+            }
+            if check_attribute(&name, attribute).is_err() {
+                let attribute = attributes.remove(&name).expect("key exists");
+                warnings.push(format!(
+                    "kept invalid value {} for attribute '{}' untyped",
+                    attribute, name
+                ));
+                untyped.insert(name, attribute);
+            }
+        }
+
+        if !attributes.contains_key("entry") {
+            warnings.push(String::from(
+                "missing attribute 'entry', using current time",
+            ));
+            let now = datetime_format::format(&Utc::now());
+            attributes.insert(String::from("entry"), Value::from(now));
+        }
+        if !attributes.contains_key("modified") {
+            warnings.push(String::from("missing attribute 'modified', using 'entry'"));
+            let entry = attributes["entry"].clone();
+            attributes.insert(String::from("modified"), entry);
+        }
+
+        let mut task = Self::from_value(Value::Object(attributes))?;
+        task.unknown_fields.extend(untyped);
+        Ok((task, warnings))
     }
 }
 
 impl FromStr for Task {
-    type Err = &'static str;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // serde_json::from_str(s).map_err(|_| "Cannot deserialize task")
-        Ok(serde_json::from_str(s).unwrap())
+        let value = serde_json::from_str(s).map_err(Error::json("cannot parse task JSON"))?;
+        Self::from_value(value)
     }
 }
 
+/// Attributes a task cannot be parsed without, even leniently
+const REQUIRED_ATTRIBUTES: [&str; 3] = ["uuid", "status", "description"];
+
+/// Check whether `value` is a valid value for the typed attribute `name`.
+/// Attributes that are not typed fields of [`Task`] are always valid.
+fn check_attribute(name: &str, value: &Value) -> Result<(), serde_json::Error> {
+    match name {
+        "id" | "imask" => check::<u64>(value),
+        "urgency" => check::<f64>(value),
+        "uuid" | "parent" => check::<Uuid>(value),
+        "status" => check::<Status>(value),
+        "description" | "project" | "priority" | "recur" | "mask" => check::<String>(value),
+        "entry" | "modified" | "due" | "scheduled" | "wait" | "until" | "start" | "end" => {
+            datetime_format::deserialize(value).map(|_| ())
+        }
+        "depends" => depends_format::deserialize(value).map(|_| ()),
+        "tags" => check::<Vec<String>>(value),
+        "annotations" => check::<Vec<Annotation>>(value),
+        _ => Ok(()),
+    }
+}
+
+/// Fix up common oddities in the value of attribute `name`, returning a warning if the value
+/// has been changed
+fn normalize_attribute(name: &str, value: &mut Value) -> Option<String> {
+    let normalized = match (name, &*value) {
+        // numbers given as strings
+        ("id" | "imask", Value::String(s)) => s.trim().parse::<u64>().ok().map(Value::from),
+        ("urgency", Value::String(s)) => s.trim().parse::<f64>().ok().map(Value::from),
+        // tags given as (possibly empty) comma separated string
+        ("tags", Value::String(s)) => Some(Value::from(
+            s.split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>(),
+        )),
+        // empty or non-string tags within the array
+        ("tags", Value::Array(tags))
+            if tags.iter().any(|t| t.as_str().unwrap_or("").is_empty()) =>
+        {
+            Some(Value::from(
+                tags.iter()
+                    .filter_map(Value::as_str)
+                    .filter(|tag| !tag.is_empty())
+                    .collect::<Vec<_>>(),
+            ))
+        }
+        _ => None,
+    }?;
+
+    let warning = format!(
+        "normalized attribute '{}' from {} to {}",
+        name, value, normalized
+    );
+    *value = normalized;
+    Some(warning)
+}
+
+/// Remove the annotations that are not valid on their own from the `annotations` array
+/// `value`, returning them so they can be kept untyped and written back unchanged
+fn split_malformed_annotations(name: &str, value: &mut Value) -> Option<Value> {
+    let annotations = match (name, value) {
+        ("annotations", Value::Array(annotations)) => annotations,
+        _ => return None,
+    };
+    if annotations.iter().all(|a| check::<Annotation>(a).is_ok()) {
+        return None;
+    }
+    let (valid, malformed) = annotations
+        .drain(..)
+        .partition(|a| check::<Annotation>(a).is_ok());
+    *annotations = valid;
+    Some(Value::Array(malformed))
+}
+
+/// Check whether `value` can be deserialized as `T`
+fn check<T: DeserializeOwned>(value: &Value) -> Result<(), serde_json::Error> {
+    T::deserialize(value).map(|_| ())
+}
+
+/// Tasks are serialized in the same layout as `task export` writes them: `id` first, then all
+/// remaining attributes sorted by name, followed by `annotations`, `tags` and finally `urgency`.
+/// Tags are written in sorted order. Annotations kept untyped by lenient parsing are written
+/// after the typed ones.
+impl Serialize for Task {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let dates = [
+            ("entry", Some(self.entry)),
+            ("modified", Some(self.modified)),
+            ("due", self.due),
+            ("scheduled", self.scheduled),
+            ("wait", self.wait),
+            ("until", self.until),
+            ("start", self.start),
+            ("end", self.end),
+        ];
+
+        let mut attributes: BTreeMap<&str, Value> = self
+            .unknown_fields
+            .iter()
+            .filter(|(key, _)| key.as_str() != "annotations")
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect();
+        attributes.insert("status", Value::from(self.status.as_str()));
+        attributes.insert("uuid", Value::from(self.uuid.to_string()));
+        attributes.insert("description", Value::from(self.description.as_str()));
+        for (key, date) in dates {
+            if let Some(date) = date {
+                attributes.insert(key, Value::from(datetime_format::format(&date)));
+            }
+        }
+        if let Some(project) = &self.project {
+            attributes.insert("project", Value::from(project.as_str()));
+        }
+        if let Some(priority) = &self.priority {
+            attributes.insert("priority", Value::from(priority.as_str()));
+        }
+        if !self.depends.is_empty() {
+            let depends = self
+                .depends
+                .iter()
+                .map(|uuid| uuid.to_string().into())
+                .collect();
+            attributes.insert("depends", Value::Array(depends));
+        }
+        if let Some(recur) = &self.recur {
+            attributes.insert("recur", Value::from(recur.as_str()));
+        }
+        if let Some(parent) = &self.parent {
+            attributes.insert("parent", Value::from(parent.to_string()));
+        }
+        if let Some(imask) = self.imask {
+            attributes.insert("imask", Value::from(imask));
+        }
+        if let Some(mask) = &self.mask {
+            attributes.insert("mask", Value::from(mask.as_str()));
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(id) = self.id {
+            map.serialize_entry("id", &id)?;
+        }
+        for (key, value) in &attributes {
+            map.serialize_entry(key, value)?;
+        }
+        let mut annotations: Vec<_> = self.annotations.iter().map(AnnotationRef::Typed).collect();
+        match self.unknown_fields.get("annotations") {
+            Some(Value::Array(untyped)) => {
+                annotations.extend(untyped.iter().map(AnnotationRef::Untyped))
+            }
+            Some(untyped) if annotations.is_empty() => {
+                map.serialize_entry("annotations", untyped)?;
+            }
+            Some(untyped) => annotations.push(AnnotationRef::Untyped(untyped)),
+            None => {}
+        }
+        if !annotations.is_empty() {
+            map.serialize_entry("annotations", &annotations)?;
+        }
+        if !self.tags.is_empty() {
+            let mut tags: Vec<&String> = self.tags.iter().collect();
+            tags.sort();
+            map.serialize_entry("tags", &tags)?;
+        }
+        if let Some(urgency) = self.urgency {
+            // taskwarrior writes integral urgencies without a fractional part
+            if urgency.fract() == 0.0 && urgency.abs() < i64::MAX as f64 {
+                map.serialize_entry("urgency", &(urgency as i64))?;
+            } else {
+                map.serialize_entry("urgency", &urgency)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// An annotation as written by [`Task`]'s serialization, either typed or kept untyped by
+/// lenient parsing
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AnnotationRef<'a> {
+    Typed(&'a Annotation),
+    Untyped(&'a Value),
+}
+
 impl std::fmt::Display for Task {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let task_str = serde_json::to_string(&self).map_err(|_| std::fmt::Error {})?;
@@ -106,6 +437,77 @@ pub enum Status {
     Recurring,
 }
 
+impl Status {
+    /// The name of this status as used by taskwarrior
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Deleted => "deleted",
+            Status::Completed => "completed",
+            Status::Waiting => "waiting",
+            Status::Recurring => "recurring",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Status::Pending),
+            "deleted" => Ok(Status::Deleted),
+            "completed" => Ok(Status::Completed),
+            "waiting" => Ok(Status::Waiting),
+            "recurring" => Ok(Status::Recurring),
+            _ => Err(format!("'{}' is not a task status", s)),
+        }
+    }
+}
+
+/// Priority field of a taskwarrior task
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Priority {
+    /// Priority "H"
+    High,
+    /// Priority "M"
+    Medium,
+    /// Priority "L"
+    Low,
+    /// Any other value allowed by a customized `uda.priority.values`
+    Other(String),
+}
+
+impl Priority {
+    /// The value of this priority as used by taskwarrior
+    pub fn as_str(&self) -> &str {
+        match self {
+            Priority::High => "H",
+            Priority::Medium => "M",
+            Priority::Low => "L",
+            Priority::Other(value) => value,
+        }
+    }
+}
+
+impl From<String> for Priority {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "H" => Priority::High,
+            "M" => Priority::Medium,
+            "L" => Priority::Low,
+            _ => Priority::Other(value),
+        }
+    }
+}
+
+impl From<Priority> for String {
+    fn from(priority: Priority) -> Self {
+        priority.as_str().to_string()
+    }
+}
+
 /// Annotations to a taskwarrior are pairs of "entry" (datetime) and "description" (String)
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Annotation {
@@ -156,13 +558,19 @@ mod tests {
         assert_eq!(task.description, "Dummy Task");
         assert_eq!(task.project, Some("dummy".to_string()));
         assert_eq!(task.status, Status::Pending);
-        assert_eq!(task.entry, Utc.ymd(2022, 1, 10).and_hms(17, 16, 19));
-        assert_eq!(task.modified, Utc.ymd(2022, 1, 11).and_hms(7, 41, 12));
+        assert_eq!(
+            task.entry,
+            Utc.with_ymd_and_hms(2022, 1, 10, 17, 16, 19).unwrap()
+        );
+        assert_eq!(
+            task.modified,
+            Utc.with_ymd_and_hms(2022, 1, 11, 7, 41, 12).unwrap()
+        );
         assert!(task.tags.contains(&String::from("wiki")));
         assert_eq!(
             task.annotations,
             vec![Annotation {
-                entry: Utc.ymd(2022, 1, 11).and_hms(7, 41, 12),
+                entry: Utc.with_ymd_and_hms(2022, 1, 11, 7, 41, 12).unwrap(),
                 description: String::from("note:dp"),
             }]
         );
@@ -189,4 +597,174 @@ mod tests {
         assert!(task.has_tag("wiki"));
         assert!(!task.has_tag("foobar"));
     }
+
+    /// A completed task with dependencies as written by `task export`
+    const EXPORT_JSON: &str = concat!(
+        r#"{"id":0,"depends":["dde3720b-003f-4776-8e15-61e5d90376af"],"description":"Export Task","#,
+        r#""due":"20220120T230000Z","end":"20220112T080000Z","entry":"20220110T171619Z","#,
+        r#""estimate":"PT2H","modified":"20220112T080000Z","priority":"H","project":"dummy","#,
+        r#""status":"completed","uuid":"9d417e14-efac-4173-930e-a59fd743d23b","#,
+        r#""annotations":[{"entry":"20220111T074112Z","description":"note:dp"}],"#,
+        r#""tags":["next","wiki"],"urgency":5.8}"#
+    );
+
+    #[test]
+    fn deserialize_typed_attributes() {
+        let task: Task = serde_json::from_str(EXPORT_JSON).expect("deserialization succeeded");
+
+        assert_eq!(task.id, Some(0));
+        assert_eq!(task.status, Status::Completed);
+        assert_eq!(
+            task.due,
+            Some(Utc.with_ymd_and_hms(2022, 1, 20, 23, 0, 0).unwrap())
+        );
+        assert_eq!(
+            task.end,
+            Some(Utc.with_ymd_and_hms(2022, 1, 12, 8, 0, 0).unwrap())
+        );
+        assert_eq!(task.scheduled, None);
+        assert_eq!(task.priority, Some(Priority::High));
+        assert_eq!(
+            task.depends,
+            vec![Uuid::parse_str("dde3720b-003f-4776-8e15-61e5d90376af").unwrap()]
+        );
+        assert_eq!(task.urgency, Some(5.8));
+        assert_eq!(task.unknown_fields.len(), 1);
+        assert_eq!(task.unknown_fields["estimate"], "PT2H");
+    }
+
+    #[test]
+    fn round_trip_task_export_byte_compatibly() {
+        let task: Task = serde_json::from_str(EXPORT_JSON).expect("deserialization succeeded");
+        assert_eq!(task.to_string(), EXPORT_JSON);
+    }
+
+    #[test]
+    fn round_trip_hook_input_byte_compatibly() {
+        let hook_json = concat!(
+            r#"{"description":"Dummy Task","entry":"20220110T171619Z","#,
+            r#""modified":"20220111T074112Z","project":"dummy","status":"pending","#,
+            r#""uuid":"dde3720b-003f-4776-8e15-61e5d90376af","tags":["wiki"]}"#
+        );
+        let task: Task = serde_json::from_str(hook_json).expect("deserialization succeeded");
+        assert_eq!(task.to_string(), hook_json);
+    }
+
+    #[test]
+    fn deserialize_legacy_depends_and_custom_priority() {
+        let json_str = r#"{
+            "description": "Legacy", "entry": "20220110T171619Z", "modified": "20220110T171619Z",
+            "status": "pending", "uuid": "dde3720b-003f-4776-8e15-61e5d90376af",
+            "depends": "9d417e14-efac-4173-930e-a59fd743d23b,b40b6020-13e6-4194-b422-b049f0a9f4d1",
+            "priority": "urgent"
+        }"#;
+        let task: Task = serde_json::from_str(json_str).expect("deserialization succeeded");
+
+        assert_eq!(task.depends.len(), 2);
+        assert_eq!(task.priority, Some(Priority::Other("urgent".to_string())));
+        let serialized = task.to_string();
+        assert!(serialized.contains(r#""priority":"urgent""#));
+        assert!(serialized.contains(r#""depends":["9d417e14"#));
+    }
+
+    #[test]
+    fn parse_example_fixtures() {
+        let fixtures = [
+            include_str!("../examples/add_task.json"),
+            include_str!("../examples/add_tag.json"),
+            include_str!("../examples/task_completed.json"),
+            include_str!("../examples/task_deleted.json"),
+        ];
+        for line in fixtures.iter().flat_map(|fixture| fixture.lines()) {
+            let task = Task::from_str(line).expect("fixture parses");
+            let (lenient, warnings) = Task::from_str_lenient(line).expect("fixture parses");
+            assert_eq!(task, lenient);
+            assert!(warnings.is_empty());
+        }
+
+        let completed = include_str!("../examples/task_completed.json");
+        let task = Task::from_str(completed.lines().nth(1).unwrap()).expect("parses");
+        assert_eq!(task.status, Status::Completed);
+        assert!(task.end.is_some());
+    }
+
+    #[test]
+    fn invalid_attribute_is_named_instead_of_panicking() {
+        let line = include_str!("../examples/add_task.json")
+            .replace(r#""entry": "20220110T171619Z""#, r#""entry": "yesterday""#);
+        let err = Task::from_str(&line).expect_err("parsing fails");
+        assert!(err.to_string().contains("'entry'"));
+
+        let err = Task::from_str("{not json").expect_err("parsing fails");
+        assert!(matches!(err, Error::Json { .. }));
+
+        let err = Task::from_str("[]").expect_err("parsing fails");
+        assert!(matches!(err, Error::Json { .. }));
+    }
+
+    #[test]
+    fn missing_required_attribute_is_named() {
+        let line = include_str!("../examples/add_task.json")
+            .replace(r#""uuid": "dde3720b-003f-4776-8e15-61e5d90376af", "#, "");
+        let err = Task::from_str(&line).expect_err("parsing fails");
+        assert!(err.chain().contains("uuid"));
+        assert!(Task::from_str_lenient(&line).is_err());
+    }
+
+    #[test]
+    fn lenient_parsing_normalizes_odd_values() {
+        let line = r#"{"description": "Odd Task", "entry": "20220110T171619Z",
+            "status": "pending", "uuid": "dde3720b-003f-4776-8e15-61e5d90376af",
+            "tags": "", "urgency": "4.5", "imask": "3",
+            "annotations": [{"description": "no entry"}]}"#;
+        assert!(Task::from_str(line).is_err());
+
+        let (task, warnings) = Task::from_str_lenient(line).expect("lenient parsing succeeds");
+        assert!(task.tags.is_empty());
+        assert_eq!(task.urgency, Some(4.5));
+        assert_eq!(task.imask, Some(3));
+        assert_eq!(task.modified, task.entry);
+        assert_eq!(warnings.len(), 5);
+    }
+
+    #[test]
+    fn lenient_parsing_keeps_malformed_annotations() {
+        let line = r#"{"description": "Odd Task", "entry": "20220110T171619Z",
+            "status": "pending", "uuid": "dde3720b-003f-4776-8e15-61e5d90376af",
+            "annotations": [{"entry": "20220111T074112Z", "description": "valid"},
+                            {"description": "no entry"}]}"#;
+        let (mut task, warnings) = Task::from_str_lenient(line).expect("lenient parsing succeeds");
+        assert_eq!(task.annotations.len(), 1);
+        assert!(warnings[0].contains("no entry"));
+
+        task.annotations.push(Annotation::new("added"));
+        let written = task.to_string();
+        assert_eq!(written.matches(r#""annotations""#).count(), 1);
+        let annotations = serde_json::from_str::<Value>(&written).unwrap()["annotations"].clone();
+        let descriptions: Vec<_> = annotations
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["description"].as_str().unwrap())
+            .collect();
+        assert_eq!(descriptions, ["valid", "added", "no entry"]);
+    }
+
+    #[test]
+    fn lenient_parsing_keeps_invalid_values_untyped() {
+        let line = include_str!("../examples/add_task.json")
+            .lines()
+            .next()
+            .unwrap()
+            .replace(
+                r#""project": "dummy""#,
+                r#""due": "someday", "project": "dummy""#,
+            );
+        let (task, warnings) = Task::from_str_lenient(&line).expect("lenient parsing succeeds");
+
+        assert_eq!(task.due, None);
+        assert_eq!(task.unknown_fields["due"], "someday");
+        assert!(warnings[0].contains("'due'"));
+        assert!(task.to_string().contains(r#""due":"someday""#));
+    }
 }
@@ -0,0 +1,448 @@
+use crate::ff4::{epoch_to_wire, is_date, is_numeric_uda, parse_number, wire_to_epoch};
+use crate::storage::Storage;
+use crate::uda::UdaRegistry;
+use crate::{Error, Result, Status, Task};
+use chrono::Utc;
+use log::warn;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Name of the replica database in the data location of taskwarrior 3
+const DATABASE_NAME: &str = "taskchampion.sqlite3";
+
+/// Attributes taskwarrior computes when loading tasks, which are never stored
+const COMPUTED_ATTRIBUTES: [&str; 2] = ["id", "urgency"];
+
+/// The key/value representation of a task in a TaskChampion replica
+pub type TaskMap = BTreeMap<String, String>;
+
+/// The TaskChampion replica taskwarrior 3 stores its tasks in, a SQLite database with a
+/// `tasks` table mapping UUIDs to JSON objects of string properties:
+///
+/// `{"description": "Buy milk", "entry": "1641835000", "status": "pending", "tag_wiki": ""}`
+///
+/// Tags, annotations and dependencies are stored as `tag_<name>`, `annotation_<epoch>` and
+/// `dep_<uuid>` properties. Saved changes are recorded as operations, so taskwarrior can undo
+/// and synchronize them.
+pub struct Replica {
+    path: PathBuf,
+    udas: UdaRegistry,
+}
+
+impl Replica {
+    /// Open the replica in the data location `dir`, which must exist
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(DATABASE_NAME);
+        Self::connect(&path)?;
+        Ok(Self {
+            path,
+            udas: UdaRegistry::default(),
+        })
+    }
+
+    /// Convert date and numeric UDAs as declared in `udas`, which are strings otherwise
+    pub fn with_udas(mut self, udas: UdaRegistry) -> Self {
+        self.udas = udas;
+        self
+    }
+
+    fn connect(path: &Path) -> Result<Connection> {
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE).map_err(Error::sqlite(
+            format!("cannot open TaskChampion replica {}", path.display()),
+        ))
+    }
+
+    fn context(&self, action: &str) -> String {
+        format!(
+            "cannot {} TaskChampion replica {}",
+            action,
+            self.path.display()
+        )
+    }
+
+    /// The ids of the tasks in the working set, i.e. the pending ones
+    fn working_set(&self, connection: &Connection) -> Result<HashMap<Uuid, u64>> {
+        let context = || self.context("read the working set of");
+        let mut statement = connection
+            .prepare("SELECT id, uuid FROM working_set")
+            .map_err(Error::sqlite(context()))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(Error::sqlite(context()))?;
+
+        let mut working_set = HashMap::new();
+        for row in rows {
+            let (id, uuid) = row.map_err(Error::sqlite(context()))?;
+            if let Ok(uuid) = Uuid::parse_str(&uuid) {
+                working_set.insert(uuid, id);
+            }
+        }
+        Ok(working_set)
+    }
+}
+
+impl Storage for Replica {
+    /// All tasks, those in the working set with their ids, all others with id 0
+    fn load(&self) -> Result<Vec<Task>> {
+        let connection = Self::connect(&self.path)?;
+        let context = || self.context("read tasks from");
+        let working_set = self.working_set(&connection)?;
+
+        let mut statement = connection
+            .prepare("SELECT uuid, data FROM tasks")
+            .map_err(Error::sqlite(context()))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(Error::sqlite(context()))?;
+
+        let mut tasks = vec![];
+        for row in rows {
+            let (uuid, data) = row.map_err(Error::sqlite(context()))?;
+            let uuid = Uuid::parse_str(&uuid).map_err(|err| {
+                Error::TaskData(format!(
+                    "{}: invalid uuid '{}': {}",
+                    DATABASE_NAME, uuid, err
+                ))
+            })?;
+            let map: TaskMap = serde_json::from_str(&data)
+                .map_err(Error::json(format!("cannot parse task {}", uuid)))?;
+            let mut task = task_from_map(uuid, &map, &self.udas)?;
+            task.id = Some(working_set.get(&uuid).copied().unwrap_or(0));
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    /// Replace the properties of `tasks`, recording an operation for every change. New
+    /// pending tasks are added to the working set.
+    fn save(&self, tasks: &[Task]) -> Result<()> {
+        let mut connection = Self::connect(&self.path)?;
+        let context = || self.context("write tasks to");
+        let transaction = connection.transaction().map_err(Error::sqlite(context()))?;
+        let timestamp = json!(Utc::now());
+        let mut operations = vec![json!("UndoPoint")];
+        let working_set = self.working_set(&transaction)?;
+        let mut next_id = working_set.values().max().copied().unwrap_or(0) + 1;
+
+        for task in tasks {
+            let uuid = task.uuid.to_string();
+            let existing: Option<String> = transaction
+                .query_row("SELECT data FROM tasks WHERE uuid = ?", [&uuid], |row| {
+                    row.get(0)
+                })
+                .optional()
+                .map_err(Error::sqlite(context()))?;
+            let old: TaskMap = match &existing {
+                Some(data) => serde_json::from_str(data)
+                    .map_err(Error::json(format!("cannot parse task {}", uuid)))?,
+                None => {
+                    operations.push(json!({"Create": {"uuid": uuid}}));
+                    TaskMap::new()
+                }
+            };
+            let new = task_to_map(task, &self.udas)?;
+
+            let properties = old
+                .keys()
+                .chain(new.keys().filter(|key| !old.contains_key(*key)));
+            for property in properties {
+                let (old_value, value) = (old.get(property), new.get(property));
+                if old_value != value {
+                    operations.push(json!({"Update": {
+                        "uuid": uuid,
+                        "property": property,
+                        "old_value": old_value,
+                        "value": value,
+                        "timestamp": timestamp,
+                    }}));
+                }
+            }
+
+            let data = serde_json::to_string(&new)
+                .map_err(Error::json(format!("cannot serialize task {}", uuid)))?;
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO tasks (uuid, data) VALUES (?, ?)",
+                    [&uuid, &data],
+                )
+                .map_err(Error::sqlite(context()))?;
+
+            let pending = matches!(
+                task.status,
+                Status::Pending | Status::Waiting | Status::Recurring
+            );
+            if pending && !working_set.contains_key(&task.uuid) {
+                transaction
+                    .execute(
+                        "INSERT INTO working_set (id, uuid) VALUES (?, ?)",
+                        rusqlite::params![next_id, uuid],
+                    )
+                    .map_err(Error::sqlite(context()))?;
+                next_id += 1;
+            }
+        }
+
+        if operations.len() > 1 {
+            for operation in operations {
+                transaction
+                    .execute(
+                        "INSERT INTO operations (data) VALUES (?)",
+                        [operation.to_string()],
+                    )
+                    .map_err(Error::sqlite(context()))?;
+            }
+        }
+        transaction.commit().map_err(Error::sqlite(context()))
+    }
+}
+
+/// The task `uuid` with the properties `map` of a TaskChampion replica
+pub fn task_from_map(uuid: Uuid, map: &TaskMap, udas: &UdaRegistry) -> Result<Task> {
+    let mut attributes = Map::new();
+    let mut tags = vec![];
+    let mut depends = vec![];
+    let mut annotations = vec![];
+
+    for (key, value) in map {
+        if let Some(tag) = key.strip_prefix("tag_") {
+            tags.push(Value::from(tag));
+        } else if let Some(dep) = key.strip_prefix("dep_") {
+            depends.push(Value::from(dep));
+        } else if let Some(epoch) = key.strip_prefix("annotation_") {
+            match epoch_to_wire(epoch) {
+                Some(entry) => annotations.push(json!({"entry": entry, "description": value})),
+                None => warn!("task {}: invalid annotation timestamp '{}'", uuid, epoch),
+            }
+        } else {
+            let value = match key.as_str() {
+                "imask" => parse_number(value),
+                name if is_date(name, udas) => match epoch_to_wire(value) {
+                    Some(date) => Value::from(date),
+                    None => Value::from(value.as_str()),
+                },
+                name if is_numeric_uda(name, udas) => parse_number(value),
+                _ => Value::from(value.as_str()),
+            };
+            attributes.insert(key.clone(), value);
+        }
+    }
+
+    attributes.insert(String::from("uuid"), Value::from(uuid.to_string()));
+    if !tags.is_empty() {
+        attributes.insert(String::from("tags"), Value::Array(tags));
+    }
+    if !depends.is_empty() {
+        attributes.insert(String::from("depends"), Value::Array(depends));
+    }
+    if !annotations.is_empty() {
+        attributes.insert(String::from("annotations"), Value::Array(annotations));
+    }
+    Task::from_value(Value::Object(attributes))
+}
+
+/// The properties representing `task` in a TaskChampion replica
+pub fn task_to_map(task: &Task, udas: &UdaRegistry) -> Result<TaskMap> {
+    let attributes = match serde_json::to_value(task) {
+        Ok(Value::Object(attributes)) => attributes,
+        Ok(_) => unreachable!("tasks are serialized as objects"),
+        Err(err) => return Err(Error::json("cannot serialize task")(err)),
+    };
+
+    let mut map = TaskMap::new();
+    for (name, value) in attributes {
+        if COMPUTED_ATTRIBUTES.contains(&name.as_str()) || name == "uuid" {
+            continue;
+        }
+        match (name.as_str(), value) {
+            ("annotations", Value::Array(annotations)) => {
+                for annotation in annotations {
+                    let description = annotation["description"].as_str().unwrap_or_default();
+                    let epoch = annotation["entry"].as_str().and_then(wire_to_epoch);
+                    // annotations made within the same second must not overwrite each other
+                    let mut epoch = epoch.unwrap_or_default();
+                    while map.contains_key(&format!("annotation_{}", epoch)) {
+                        epoch += 1;
+                    }
+                    map.insert(format!("annotation_{}", epoch), description.to_string());
+                }
+            }
+            ("tags", Value::Array(tags)) => {
+                for tag in tags.iter().filter_map(Value::as_str) {
+                    map.insert(format!("tag_{}", tag), String::new());
+                }
+            }
+            ("depends", Value::Array(depends)) => {
+                for dep in depends.iter().filter_map(Value::as_str) {
+                    map.insert(format!("dep_{}", dep), String::new());
+                }
+            }
+            (name, Value::String(date)) if is_date(name, udas) => {
+                let epoch = wire_to_epoch(&date).map(|epoch| epoch.to_string());
+                map.insert(name.to_string(), epoch.unwrap_or(date));
+            }
+            (_, Value::String(s)) => {
+                map.insert(name, s);
+            }
+            (_, Value::Null) => {}
+            (_, value) => {
+                map.insert(name, value.to_string());
+            }
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uda::{Uda, UdaType};
+    use tempfile::{tempdir, TempDir};
+
+    const UUID: &str = "dde3720b-003f-4776-8e15-61e5d90376af";
+
+    /// A replica with the schema of TaskChampion, holding a single pending task
+    fn replica() -> (Replica, TempDir) {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let connection = Connection::open(dir.path().join(DATABASE_NAME)).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE tasks (uuid STRING PRIMARY KEY, data STRING);
+                 CREATE TABLE operations (id INTEGER PRIMARY KEY AUTOINCREMENT, data STRING,
+                     synced bool DEFAULT false);
+                 CREATE TABLE sync_meta (key STRING PRIMARY KEY, value STRING);
+                 CREATE TABLE working_set (id INTEGER PRIMARY KEY, uuid STRING);",
+            )
+            .unwrap();
+        let data = json!({
+            "description": "Dummy Task",
+            "entry": "1641834979",
+            "modified": "1641886872",
+            "project": "dummy",
+            "status": "pending",
+            "tag_wiki": "",
+            "annotation_1641886872": "note:dp",
+            "estimate": "2.5",
+        });
+        connection
+            .execute(
+                "INSERT INTO tasks (uuid, data) VALUES (?, ?)",
+                [UUID, &data.to_string()],
+            )
+            .unwrap();
+        connection
+            .execute("INSERT INTO working_set (id, uuid) VALUES (1, ?)", [UUID])
+            .unwrap();
+
+        let mut udas = UdaRegistry::default();
+        udas.insert(Uda::new("estimate", UdaType::Numeric));
+        let replica = Replica::open(dir.path())
+            .expect("replica exists")
+            .with_udas(udas);
+        (replica, dir)
+    }
+
+    fn operations(dir: &TempDir) -> Vec<Value> {
+        let connection = Connection::open(dir.path().join(DATABASE_NAME)).unwrap();
+        let mut statement = connection
+            .prepare("SELECT data FROM operations ORDER BY id")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap();
+        rows.map(|data| serde_json::from_str(&data.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn load_tasks_like_task_export() {
+        let (replica, _dir) = replica();
+        let tasks = replica.load().expect("loading succeeds");
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(
+            tasks[0].to_string(),
+            concat!(
+                r#"{"id":1,"description":"Dummy Task","entry":"20220110T171619Z","#,
+                r#""estimate":2.5,"modified":"20220111T074112Z","project":"dummy","#,
+                r#""status":"pending","uuid":"dde3720b-003f-4776-8e15-61e5d90376af","#,
+                r#""annotations":[{"entry":"20220111T074112Z","description":"note:dp"}],"#,
+                r#""tags":["wiki"]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn map_tasks_to_properties_and_back() {
+        let (replica, _dir) = replica();
+        let mut task = replica.load().unwrap().remove(0);
+        task.depends = vec![Uuid::from_u128(1)];
+        let map = task_to_map(&task, &replica.udas).expect("mapping succeeds");
+        assert_eq!(map["entry"], "1641834979");
+        assert_eq!(map["tag_wiki"], "");
+        assert_eq!(map["dep_00000000-0000-0000-0000-000000000001"], "");
+        assert_eq!(map["annotation_1641886872"], "note:dp");
+        assert!(!map.contains_key("uuid") && !map.contains_key("id"));
+
+        let mapped = task_from_map(task.uuid, &map, &replica.udas).unwrap();
+        assert_eq!(mapped, Task { id: None, ..task });
+    }
+
+    #[test]
+    fn save_tasks_recording_operations() {
+        let (replica, dir) = replica();
+        let mut task = replica.load().unwrap().remove(0);
+        task.tags.remove("wiki");
+        task.project = Some(String::from("other"));
+        let added = Task::new("Added task");
+        replica
+            .save(&[task.clone(), added.clone()])
+            .expect("saving succeeds");
+
+        let tasks = replica.load().unwrap();
+        let saved = tasks.iter().find(|t| t.uuid == task.uuid).unwrap();
+        assert_eq!(saved.project.as_deref(), Some("other"));
+        assert!(!saved.has_tag("wiki"));
+        let new = tasks.iter().find(|t| t.uuid == added.uuid).unwrap();
+        assert_eq!(new.id, Some(2));
+
+        let operations = operations(&dir);
+        assert_eq!(operations[0], json!("UndoPoint"));
+        let updates: Vec<_> = operations
+            .iter()
+            .filter(|op| op["Update"]["uuid"] == UUID)
+            .map(|op| {
+                let update = &op["Update"];
+                (update["property"].clone(), update["value"].clone())
+            })
+            .collect();
+        assert_eq!(
+            updates,
+            vec![
+                (json!("project"), json!("other")),
+                (json!("tag_wiki"), Value::Null),
+            ]
+        );
+        assert!(operations.contains(&json!({"Create": {"uuid": added.uuid.to_string()}})));
+
+        // saving unchanged tasks records nothing
+        let count = operations.len();
+        replica.save(&tasks).unwrap();
+        assert_eq!(self::operations(&dir).len(), count);
+    }
+
+    #[test]
+    fn fail_on_missing_replica() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        assert!(matches!(
+            Replica::open(dir.path()),
+            Err(Error::Sqlite { .. })
+        ));
+        assert!(!dir.path().join(DATABASE_NAME).exists());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::{expand_tilde, ConfigError, ConfigLayer, Source};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Maximum nesting depth of `include` directives, guarding against include cycles
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Settings read from a taskwarrior `.taskrc` file
+#[derive(Debug, Default)]
+pub struct Taskrc {
+    /// Path of the top-level rc file, if any has been read
+    path: Option<PathBuf>,
+    /// Settings read from the rc file and its includes
+    settings: HashMap<String, String>,
+    /// Settings overridden on the command line via `rc.<key>=<value>`
+    overrides: HashMap<String, String>,
+}
+
+impl Taskrc {
+    /// Location of the rc file taskwarrior uses by default, i.e. `$TASKRC` or `~/.taskrc`
+    pub fn default_path() -> Option<PathBuf> {
+        match std::env::var_os("TASKRC") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(std::env::var_os("HOME")?).join(".taskrc")),
+        }
+    }
+
+    /// The data location taskwarrior uses if the rc file sets none: `$TASKDATA` or `~/.task`
+    pub fn default_data_location() -> Option<PathBuf> {
+        match std::env::var_os("TASKDATA") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(std::env::var_os("HOME")?).join(".task")),
+        }
+    }
+
+    /// Read the rc file at `path`, resolving all `include` directives
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let mut taskrc = Self {
+            path: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+        taskrc.read_file(path, 0)?;
+        Ok(taskrc)
+    }
+
+    fn read_file(&mut self, path: &Path, depth: usize) -> Result<(), ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|err| ConfigError::Read {
+            path: path.to_path_buf(),
+            source: err,
+        })?;
+
+        for (idx, line) in content.lines().enumerate() {
+            // everything following a '#' is a comment
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(include) = line.strip_prefix("include ") {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(ConfigError::Taskrc {
+                        path: path.to_path_buf(),
+                        line: idx + 1,
+                        message: String::from("includes nested too deeply"),
+                    });
+                }
+                let include = expand_tilde(Path::new(include.trim()));
+                let include = match path.parent() {
+                    Some(dir) if include.is_relative() => dir.join(include),
+                    _ => include,
+                };
+                self.read_file(&include, depth + 1)?;
+                continue;
+            }
+
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    self.settings
+                        .insert(key.trim().to_string(), value.trim().to_string());
+                }
+                None => {
+                    return Err(ConfigError::Taskrc {
+                        path: path.to_path_buf(),
+                        line: idx + 1,
+                        message: format!("malformed entry '{}'", line),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply `rc.<key>=<value>` or `rc.<key>:<value>` overrides found among `args`.
+    /// All other arguments are ignored.
+    pub fn apply_overrides<'a, I: IntoIterator<Item = &'a str>>(&mut self, args: I) {
+        for arg in args {
+            let setting = match arg.strip_prefix("rc.") {
+                Some(setting) => setting,
+                None => continue,
+            };
+            if let Some((key, value)) = setting.split_once(['=', ':']) {
+                self.set_override(key, value);
+            }
+        }
+    }
+
+    /// Override the setting `key` as if given on the command line
+    pub fn set_override(&mut self, key: &str, value: &str) {
+        self.overrides.insert(key.to_string(), value.to_string());
+    }
+
+    /// Path of the top-level rc file, if any has been read
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The value of setting `key`, command line overrides taking precedence
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.overrides
+            .get(key)
+            .or_else(|| self.settings.get(key))
+            .map(String::as_str)
+    }
+
+    /// All settings (including overrides) whose keys start with `prefix`
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.settings
+            .keys()
+            .chain(
+                self.overrides
+                    .keys()
+                    .filter(|k| !self.settings.contains_key(*k)),
+            )
+            .filter(move |key| key.starts_with(prefix))
+            .filter_map(move |key| Some((key.as_str(), self.get(key)?)))
+    }
+
+    /// The directory taskwarrior stores its data files in
+    pub fn data_location(&self) -> Option<PathBuf> {
+        self.get("data.location")
+            .map(|location| expand_tilde(Path::new(location)))
+    }
+
+    /// Taskwiki settings from the `taskwiki.` namespace of the rc file
+    pub fn file_layer(&self, source: &Source) -> Result<ConfigLayer, ConfigError> {
+        Self::layer(&self.settings, source)
+    }
+
+    /// Taskwiki settings from the `taskwiki.` namespace given as `rc.` overrides
+    pub fn override_layer(&self) -> Result<ConfigLayer, ConfigError> {
+        Self::layer(&self.overrides, &Source::RcOverride)
+    }
+
+    /// Settings are named like `taskwiki.notes.dir` for the `notes_dir` setting
+    fn layer(
+        settings: &HashMap<String, String>,
+        source: &Source,
+    ) -> Result<ConfigLayer, ConfigError> {
+        ConfigLayer::from_lookup(
+            |name| {
+                let key = format!("taskwiki.{}", name.replacen('_', ".", 1));
+                settings.get(&key).cloned()
+            },
+            source,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TASKRC: &str = "\
+        # taskwarrior configuration\n\
+        data.location=~/.task\n\
+        \n\
+        taskwiki.notes.tag=notes   # trailing comment\n\
+        taskwiki.notes.ext = wiki\n\
+        include extra.rc\n";
+
+    fn write_taskrc(dir: &Path) -> PathBuf {
+        let path = dir.join("taskrc");
+        std::fs::write(&path, TASKRC).expect("writing taskrc");
+        std::fs::write(dir.join("extra.rc"), "taskwiki.notes.dir=/tmp/notes\n")
+            .expect("writing include");
+        path
+    }
+
+    #[test]
+    fn read_settings_and_resolve_includes() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let taskrc = Taskrc::load(&write_taskrc(dir.path())).expect("loading succeeds");
+
+        assert_eq!(taskrc.get("taskwiki.notes.tag"), Some("notes"));
+        assert_eq!(taskrc.get("taskwiki.notes.ext"), Some("wiki"));
+        assert_eq!(taskrc.get("taskwiki.notes.dir"), Some("/tmp/notes"));
+        assert_eq!(taskrc.get("does.not.exist"), None);
+
+        let layer = taskrc.file_layer(&Source::Default).expect("valid settings");
+        assert_eq!(layer.notes_tag, Some("notes".to_string()));
+        assert_eq!(layer.notes_dir, Some(PathBuf::from("/tmp/notes")));
+    }
+
+    #[test]
+    fn expand_home_in_data_location() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let taskrc = Taskrc::load(&write_taskrc(dir.path())).expect("loading succeeds");
+        let data_location = taskrc.data_location().expect("data.location is set");
+        assert!(!data_location.starts_with("~"));
+        assert!(data_location.ends_with(".task"));
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let mut taskrc = Taskrc::load(&write_taskrc(dir.path())).expect("loading succeeds");
+        taskrc.apply_overrides(
+            "task rc.taskwiki.notes.tag=override rc.data.location:/data add foo".split_whitespace(),
+        );
+
+        assert_eq!(taskrc.get("taskwiki.notes.tag"), Some("override"));
+        assert_eq!(taskrc.data_location(), Some(PathBuf::from("/data")));
+        let layer = taskrc.override_layer().expect("valid settings");
+        assert_eq!(layer.notes_tag, Some("override".to_string()));
+        assert_eq!(layer.notes_ext, None);
+    }
+
+    #[test]
+    fn list_settings_by_prefix() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let mut taskrc = Taskrc::load(&write_taskrc(dir.path())).expect("loading succeeds");
+        taskrc.set_override("taskwiki.extra", "value");
+
+        let mut keys: Vec<_> = taskrc.with_prefix("taskwiki.").map(|(k, _)| k).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec![
+                "taskwiki.extra",
+                "taskwiki.notes.dir",
+                "taskwiki.notes.ext",
+                "taskwiki.notes.tag"
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_malformed_lines_and_missing_includes() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("taskrc");
+
+        std::fs::write(&path, "foo=bar\nmalformed\n").expect("writing taskrc");
+        let err = Taskrc::load(&path).expect_err("loading fails");
+        assert!(err.to_string().contains(":2"));
+
+        std::fs::write(&path, "include missing.rc\n").expect("writing taskrc");
+        assert!(matches!(Taskrc::load(&path), Err(ConfigError::Read { .. })));
+    }
+
+    #[test]
+    fn reject_include_cycles() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("taskrc");
+        std::fs::write(&path, "include taskrc\n").expect("writing taskrc");
+        assert!(Taskrc::load(&path).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{Error, Result, Task};
+use log::debug;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a `task` command may take by default before it is killed
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a running `task` command is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Client running the taskwarrior `task` binary to query and change the task database.
+///
+/// Every command runs with `rc.hooks=off`, so taskwiki's own hooks are not triggered by the
+/// changes it makes, and with `rc.confirmation=off` as there is nobody to answer prompts.
+#[derive(Clone, Debug)]
+pub struct TaskWarrior {
+    binary: PathBuf,
+    overrides: Vec<(String, String)>,
+    hooks: bool,
+    timeout: Duration,
+    env: Vec<(String, PathBuf)>,
+}
+
+impl TaskWarrior {
+    pub fn new() -> Self {
+        Self {
+            binary: PathBuf::from("task"),
+            overrides: vec![],
+            hooks: false,
+            timeout: DEFAULT_TIMEOUT,
+            env: vec![],
+        }
+    }
+
+    /// Run `binary` instead of looking up `task` in the `PATH`
+    pub fn with_binary(mut self, binary: &Path) -> Self {
+        self.binary = binary.to_path_buf();
+        self
+    }
+
+    /// Pass `rc.<key>=<value>` to every command
+    pub fn with_override(mut self, key: &str, value: &str) -> Self {
+        self.overrides.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Use the task database in `dir`, like setting `TASKDATA`
+    pub fn with_data_location(self, dir: &Path) -> Self {
+        self.with_env("TASKDATA", dir)
+    }
+
+    /// Use the rc file at `path`, like setting `TASKRC`
+    pub fn with_taskrc(self, path: &Path) -> Self {
+        self.with_env("TASKRC", path)
+    }
+
+    fn with_env(mut self, key: &str, value: &Path) -> Self {
+        self.env.push((key.to_string(), value.to_path_buf()));
+        self
+    }
+
+    /// Let taskwarrior run its hooks, which are disabled by default
+    pub fn with_hooks(mut self, hooks: bool) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Kill commands taking longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// All tasks matching `filter`, e.g. `["+wiki", "status:pending"]`
+    pub fn export(&self, filter: &[&str]) -> Result<Vec<Task>> {
+        let mut args: Vec<&str> = filter.to_vec();
+        args.push("export");
+        let stdout = self.run(&args, None)?;
+
+        let values: Vec<serde_json::Value> = serde_json::from_slice(&stdout)
+            .map_err(Error::json("cannot parse output of task export"))?;
+        values.into_iter().map(Task::from_value).collect()
+    }
+
+    /// Add or replace `tasks`, matched by their UUIDs
+    pub fn import(&self, tasks: &[Task]) -> Result<()> {
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        let input: String = tasks.iter().map(|task| format!("{}\n", task)).collect();
+        self.run(&["import"], Some(input.as_bytes()))?;
+        Ok(())
+    }
+
+    /// Run `task` with the configured overrides followed by `args`, passing `input` on stdin.
+    /// Returns the standard output of the command.
+    pub fn run(&self, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let hooks = if self.hooks { "on" } else { "off" };
+        let mut command = Command::new(&self.binary);
+        command
+            .arg(format!("rc.hooks={}", hooks))
+            .arg("rc.confirmation=off")
+            .arg("rc.verbose=nothing");
+        for (key, value) in &self.overrides {
+            command.arg(format!("rc.{}={}", key, value));
+        }
+        command.args(args);
+        for (key, value) in &self.env {
+            command.env(key, value);
+        }
+        let description = format!("task {}", args.join(" "));
+        debug!("running {:?}", command);
+
+        let mut child = command
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| TaskError::Spawn {
+                command: description.clone(),
+                source,
+            })?;
+
+        // feed stdin and drain stdout and stderr concurrently, so no pipe buffer fills up
+        let writer = input.map(|input| {
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            let input = input.to_vec();
+            std::thread::spawn(move || stdin.write_all(&input))
+        });
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let status = self.wait(&mut child).map_err(|err| match err {
+            WaitError::Io(source) => TaskError::Spawn {
+                command: description.clone(),
+                source,
+            },
+            WaitError::Timeout => TaskError::Timeout {
+                command: description.clone(),
+                timeout: self.timeout,
+            },
+        })?;
+
+        if let Some(writer) = writer {
+            let written = writer.join().unwrap_or(Ok(()));
+            written.map_err(Error::io(format!("cannot pass input to {}", description)))?;
+        }
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            return Err(TaskError::Failed {
+                command: description,
+                status: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+            }
+            .into());
+        }
+        Ok(stdout)
+    }
+
+    fn wait(&self, child: &mut Child) -> std::result::Result<ExitStatus, WaitError> {
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait().map_err(WaitError::Io)? {
+                return Ok(status);
+            }
+            if started.elapsed() >= self.timeout {
+                // the child may have exited just now, in which case killing it fails
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(WaitError::Timeout);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Default for TaskWarrior {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum WaitError {
+    Io(std::io::Error),
+    Timeout,
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            // a broken pipe leaves what has been read so far
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Errors running the `task` binary
+#[derive(Debug)]
+pub enum TaskError {
+    /// The command could not be started, e.g. because `task` is not installed
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    /// The command did not finish in time and has been killed
+    Timeout { command: String, timeout: Duration },
+    /// The command exited with a non-zero status
+    Failed {
+        command: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+}
+
+impl std::fmt::Display for TaskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::Spawn { command, .. } => write!(f, "cannot run '{}'", command),
+            TaskError::Timeout { command, timeout } => write!(
+                f,
+                "'{}' did not finish within {} seconds",
+                command,
+                timeout.as_secs_f64()
+            ),
+            TaskError::Failed {
+                command,
+                status,
+                stderr,
+            } => {
+                match status {
+                    Some(code) => write!(f, "'{}' failed with exit status {}", command, code)?,
+                    None => write!(f, "'{}' was terminated by a signal", command)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TaskError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TaskError::Spawn { source, .. } => Some(source),
+            TaskError::Timeout { .. } | TaskError::Failed { .. } => None,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::str::FromStr;
+    use tempfile::{tempdir, TempDir};
+
+    /// A fake `task` binary running `script`, which can find its directory in `$DIR`
+    fn fake_task(script: &str) -> (TaskWarrior, TempDir) {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("task");
+        let script = format!("#!/bin/sh\nDIR='{}'\n{}\n", dir.path().display(), script);
+        std::fs::write(&path, script).expect("writing fake task succeeds");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("making fake task executable succeeds");
+        (TaskWarrior::new().with_binary(&path), dir)
+    }
+
+    #[test]
+    fn export_tasks_matching_filter() {
+        let (task, dir) = fake_task(
+            "echo \"$@\" > \"$DIR/args\"\n\
+             echo '['\n\
+             cat \"$DIR/task.json\"\n\
+             echo ']'",
+        );
+        let fixture = include_str!("../examples/add_task.json");
+        std::fs::write(dir.path().join("task.json"), fixture).unwrap();
+
+        let tasks = task
+            .with_override("data.location", "/tmp/data")
+            .export(&["+wiki", "status:pending"])
+            .expect("export succeeds");
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0], Task::from_str(fixture.trim()).unwrap());
+
+        let args = std::fs::read_to_string(dir.path().join("args")).unwrap();
+        assert_eq!(
+            args.trim(),
+            "rc.hooks=off rc.confirmation=off rc.verbose=nothing \
+             rc.data.location=/tmp/data +wiki status:pending export"
+        );
+    }
+
+    #[test]
+    fn import_tasks_via_stdin() {
+        let (task, dir) = fake_task("echo \"$TASKDATA\" > \"$DIR/env\"\ncat > \"$DIR/imported\"");
+        let tasks = vec![Task::new("First"), Task::new("Second")];
+
+        task.with_data_location(Path::new("/tmp/data"))
+            .import(&tasks)
+            .expect("import succeeds");
+
+        let imported = std::fs::read_to_string(dir.path().join("imported")).unwrap();
+        assert_eq!(imported, format!("{}\n{}\n", tasks[0], tasks[1]));
+        let env = std::fs::read_to_string(dir.path().join("env")).unwrap();
+        assert_eq!(env.trim(), "/tmp/data");
+    }
+
+    #[test]
+    fn report_failures_with_stderr() {
+        let (task, _dir) = fake_task("echo 'Unrecognized filter' >&2\nexit 2");
+        let err = task.export(&["+wiki"]).expect_err("export fails");
+        assert!(matches!(
+            err,
+            Error::Task(TaskError::Failed {
+                status: Some(2),
+                ..
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "'task +wiki export' failed with exit status 2: Unrecognized filter"
+        );
+    }
+
+    #[test]
+    fn kill_commands_running_too_long() {
+        let (task, _dir) = fake_task("sleep 5");
+        let started = Instant::now();
+        let err = task
+            .with_timeout(Duration::from_millis(100))
+            .export(&[])
+            .expect_err("export times out");
+        assert!(matches!(err, Error::Task(TaskError::Timeout { .. })));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn report_missing_binary() {
+        let err = TaskWarrior::new()
+            .with_binary(Path::new("/does/not/exist/task"))
+            .export(&[])
+            .expect_err("export fails");
+        assert!(matches!(err, Error::Task(TaskError::Spawn { .. })));
+    }
+
+    #[test]
+    fn reject_malformed_export() {
+        let (task, _dir) = fake_task("echo 'not json'");
+        let err = task.export(&[]).expect_err("export fails");
+        assert!(matches!(err, Error::Json { .. }));
+    }
+}
@@ -0,0 +1,339 @@
+use crate::dates::DateContext;
+use crate::filename::short_uuid;
+use crate::uda::{UdaRegistry, UdaValue};
+use crate::{Error, Result, Task};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::path::Path;
+
+/// Template for the body of new notes files.
+///
+/// `{{field}}` is replaced by the value of a task field: `description`, `project`, `tags`,
+/// `uuid`, `short_uuid`, `id`, `status`, `priority`, `recur`, the dates `entry`, `modified`,
+/// `due`, `scheduled`, `wait`, `until`, `start` and `end`, or any UDA. `{{tag.<name>}}` is set
+/// if the task has the tag `<name>`. Dates are written like `2022-02-18` in the configured time
+/// zone.
+///
+/// `{{today}}` and `{{now}}` are the date and time the notes file is created, `{{date.<expr>}}`
+/// the date of a taskwarrior date expression like `eow`, `tomorrow` or `now+3d`.
+///
+/// `{{#field}}...{{/field}}` is only rendered if the field is set, `{{^field}}...{{/field}}`
+/// only if it is not. Section tags on a line of their own don't leave an empty line behind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotesTemplate {
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Field(String),
+    Section {
+        name: String,
+        inverted: bool,
+        nodes: Vec<Node>,
+    },
+}
+
+impl NotesTemplate {
+    /// Read the template at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(Error::io(format!(
+            "cannot read notes template {}",
+            path.display()
+        )))?;
+        text.parse()
+            .map_err(|message| Error::Template(format!("{}: {}", path.display(), message)))
+    }
+
+    /// Render the template with the values of `task`, interpreting UDAs as declared in `udas`
+    pub fn render(&self, task: &Task, udas: &UdaRegistry) -> String {
+        self.render_in(task, udas, &DateContext::new())
+    }
+
+    /// Render the template like [`render`](Self::render), computing and formatting dates in
+    /// `dates`
+    pub fn render_in(&self, task: &Task, udas: &UdaRegistry, dates: &DateContext) -> String {
+        let mut out = String::new();
+        render_nodes(
+            &self.nodes,
+            &|name| date_field(dates, name).or_else(|| task_field(task, udas, dates, name)),
+            &mut out,
+        );
+        out
+    }
+}
+
+fn render_nodes(nodes: &[Node], lookup: &dyn Fn(&str) -> Option<String>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field(name) => out.push_str(&lookup(name).unwrap_or_default()),
+            Node::Section {
+                name,
+                inverted,
+                nodes,
+            } => {
+                let is_set = lookup(name).is_some_and(|value| !value.is_empty());
+                if is_set != *inverted {
+                    render_nodes(nodes, lookup, out);
+                }
+            }
+        }
+    }
+}
+
+/// `today`, `now` or `date.<expr>`, independent of the task
+fn date_field(dates: &DateContext, name: &str) -> Option<String> {
+    match name {
+        "today" => Some(format_date(dates, &dates.now())),
+        "now" => Some(dates.format(dates.now(), "%Y-%m-%d %H:%M")),
+        _ => {
+            let date = dates.parse_date(name.strip_prefix("date.")?).ok()?;
+            Some(format_date(dates, &date))
+        }
+    }
+}
+
+/// The value of the field `name` of `task` as shown in notes templates
+pub fn task_field(
+    task: &Task,
+    udas: &UdaRegistry,
+    dates: &DateContext,
+    name: &str,
+) -> Option<String> {
+    let format_date = |date: &DateTime<Utc>| format_date(dates, date);
+    let date = |date: &Option<DateTime<Utc>>| date.map(|date| format_date(&date));
+    match name {
+        "description" => Some(task.description.clone()),
+        "project" => task.project.clone(),
+        "uuid" => Some(task.uuid.to_string()),
+        "short_uuid" => Some(short_uuid(task)),
+        "id" => task.id.map(|id| id.to_string()),
+        "status" => Some(task.status.as_str().to_string()),
+        "priority" => task.priority.as_ref().map(|p| p.as_str().to_string()),
+        "recur" => task.recur.clone(),
+        "tags" => {
+            let mut tags: Vec<_> = task.tags.iter().map(String::as_str).collect();
+            tags.sort_unstable();
+            Some(tags.join(", ")).filter(|tags| !tags.is_empty())
+        }
+        "entry" => Some(format_date(&task.entry)),
+        "modified" => Some(format_date(&task.modified)),
+        "due" => date(&task.due),
+        "scheduled" => date(&task.scheduled),
+        "wait" => date(&task.wait),
+        "until" => date(&task.until),
+        "start" => date(&task.start),
+        "end" => date(&task.end),
+        _ => match name.strip_prefix("tag.") {
+            Some(tag) => task.has_tag(tag).then(|| tag.to_string()),
+            None => uda_field(task, udas, dates, name),
+        },
+    }
+}
+
+fn uda_field(task: &Task, udas: &UdaRegistry, dates: &DateContext, name: &str) -> Option<String> {
+    if udas.get(name).is_some() {
+        return match task.uda(udas, name).ok()?? {
+            UdaValue::Date(date) => Some(format_date(dates, &date)),
+            value => Some(value.to_string()),
+        };
+    }
+    match task.unknown_fields.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn format_date(dates: &DateContext, date: &DateTime<Utc>) -> String {
+    dates.format(*date, "%Y-%m-%d")
+}
+
+/// Name of a section being parsed and whether it is inverted, `None` for the template itself
+type OpenSection = Option<(String, bool)>;
+
+impl std::str::FromStr for NotesTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        // stack of open sections, each with the nodes collected so far
+        let mut stack: Vec<(OpenSection, Vec<Node>)> = vec![(None, vec![])];
+        let mut rest = s;
+
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .map(|end| start + end)
+                .ok_or_else(|| String::from("unclosed '{{'"))?;
+            let tag = rest[start + 2..end].trim();
+            let mut text = &rest[..start];
+            let mut after = &rest[end + 2..];
+
+            if tag.starts_with(['#', '^', '/']) {
+                // a section tag on a line of its own takes the whole line with it
+                let tag_start = s.len() - rest.len() + start;
+                let line_start = s[..tag_start].rfind('\n').map_or(0, |idx| idx + 1);
+                let line_end = after.find('\n').map_or(after.len(), |idx| idx + 1);
+                if s[line_start..tag_start].trim().is_empty() && after[..line_end].trim().is_empty()
+                {
+                    text = text.trim_end_matches([' ', '\t']);
+                    after = &after[line_end..];
+                }
+            }
+
+            let nodes = &mut stack.last_mut().expect("root is never popped").1;
+            if !text.is_empty() {
+                nodes.push(Node::Text(text.to_string()));
+            }
+
+            if let Some(name) = tag.strip_prefix('#') {
+                stack.push((Some((name.trim().to_string(), false)), vec![]));
+            } else if let Some(name) = tag.strip_prefix('^') {
+                stack.push((Some((name.trim().to_string(), true)), vec![]));
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim();
+                match stack.pop() {
+                    Some((Some((open, inverted)), nodes)) if open == name => {
+                        stack
+                            .last_mut()
+                            .expect("root is never popped")
+                            .1
+                            .push(Node::Section {
+                                name: open,
+                                inverted,
+                                nodes,
+                            });
+                    }
+                    Some((Some((open, _)), _)) => {
+                        return Err(format!("'{{{{/{}}}}}' closes '{}'", name, open))
+                    }
+                    _ => return Err(format!("'{{{{/{}}}}}' closes no section", name)),
+                }
+            } else if tag.is_empty() {
+                return Err(String::from("empty placeholder '{{}}'"));
+            } else if let Some(expr) = tag.strip_prefix("date.") {
+                DateContext::new().parse_date(expr)?;
+                nodes.push(Node::Field(tag.to_string()));
+            } else {
+                nodes.push(Node::Field(tag.to_string()));
+            }
+            rest = after;
+        }
+
+        if let Some((Some((open, _)), _)) = stack.last() {
+            return Err(format!("section '{}' is never closed", open));
+        }
+        let (_, mut nodes) = stack.pop().expect("root is never popped");
+        if !rest.is_empty() {
+            nodes.push(Node::Text(rest.to_string()));
+        }
+        Ok(Self { nodes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uda::{Uda, UdaType};
+    use chrono::TimeZone;
+    use std::str::FromStr;
+
+    /// Wednesday, February 16th 2022, 23:30 in UTC
+    fn dates() -> DateContext {
+        let now = Utc.with_ymd_and_hms(2022, 2, 16, 23, 30, 0).unwrap();
+        DateContext::at(now).with_zone("UTC".parse().unwrap())
+    }
+
+    fn render(template: &str, task: &Task) -> String {
+        render_in(template, task, &dates())
+    }
+
+    fn render_in(template: &str, task: &Task, dates: &DateContext) -> String {
+        let mut udas = UdaRegistry::default();
+        udas.insert(Uda::new("estimate", UdaType::Numeric));
+        NotesTemplate::from_str(template)
+            .expect("valid template")
+            .render_in(task, &udas, dates)
+    }
+
+    #[test]
+    fn substitute_task_fields() {
+        let mut task = Task::new("Weekly sync")
+            .with_tag("meeting")
+            .with_tag("team");
+        task.project = Some(String::from("Work"));
+        task.due = Some(Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap());
+        task.unknown_fields
+            .insert(String::from("estimate"), Value::from(2));
+        task.unknown_fields
+            .insert(String::from("room"), Value::from("B12"));
+
+        assert_eq!(
+            render(
+                "# {{ description }} ({{project}})\ndue {{due}}, tags {{tags}}, \
+                 estimate {{estimate}}h in {{room}}{{missing}}",
+                &task
+            ),
+            "# Weekly sync (Work)\ndue 2022-03-01, tags meeting, team, estimate 2h in B12"
+        );
+        assert_eq!(render("{{uuid}}", &task), task.uuid.to_string());
+    }
+
+    #[test]
+    fn render_conditional_sections() {
+        let template = "# {{description}}\n\
+                        {{#due}}\n\
+                        Due: {{due}}\n\
+                        {{/due}}\n\
+                        {{^project}}\n\
+                        No project\n\
+                        {{/project}}\n\
+                        {{#tag.meeting}}## Attendees{{/tag.meeting}}\n";
+
+        let task = Task::new("Plain");
+        assert_eq!(render(template, &task), "# Plain\nNo project\n\n");
+
+        let mut task = Task::new("Sync").with_tag("meeting");
+        task.project = Some(String::from("Work"));
+        task.due = Some(Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap());
+        assert_eq!(
+            render(template, &task),
+            "# Sync\nDue: 2022-03-01\n## Attendees\n"
+        );
+    }
+
+    #[test]
+    fn render_dates_in_the_time_zone() {
+        let task = Task::new("Plan the week");
+        let template = "Created {{now}}, review on {{date.eow}}, \
+                        follow up {{date.today+P1W}} ({{today}})";
+        assert_eq!(
+            render(template, &task),
+            "Created 2022-02-16 23:30, review on 2022-02-19, \
+             follow up 2022-02-23 (2022-02-16)"
+        );
+
+        let mut task = task;
+        task.due = Some(Utc.with_ymd_and_hms(2022, 2, 18, 23, 30, 0).unwrap());
+        let berlin = dates()
+            .with_zone("Europe/Berlin".parse().unwrap())
+            .with_week_start(chrono::Weekday::Mon);
+        assert_eq!(
+            render_in("{{today}}: due {{due}}, {{date.eow}}", &task, &berlin),
+            "2022-02-17: due 2022-02-19, 2022-02-20"
+        );
+    }
+
+    #[test]
+    fn reject_malformed_templates() {
+        assert!(NotesTemplate::from_str("{{date.whenever}}").is_err());
+        assert!(NotesTemplate::from_str("{{description").is_err());
+        assert!(NotesTemplate::from_str("{{#due}}open").is_err());
+        assert!(NotesTemplate::from_str("{{#due}}{{/project}}").is_err());
+        assert!(NotesTemplate::from_str("{{/due}}").is_err());
+        assert!(NotesTemplate::from_str("{{}}").is_err());
+    }
+}
@@ -0,0 +1,533 @@
+use crate::dates::DateContext;
+use crate::datetime_format;
+use crate::taskrc::Taskrc;
+use crate::Task;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Attributes configured via `uda.<name>.*` that are typed fields of [`Task`] already
+const BUILTIN_ATTRIBUTES: [&str; 1] = ["priority"];
+
+/// Type of a user defined attribute as given by `uda.<name>.type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UdaType {
+    String,
+    Numeric,
+    Date,
+    Duration,
+}
+
+impl std::str::FromStr for UdaType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(UdaType::String),
+            "numeric" => Ok(UdaType::Numeric),
+            "date" => Ok(UdaType::Date),
+            "duration" => Ok(UdaType::Duration),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for UdaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UdaType::String => write!(f, "string"),
+            UdaType::Numeric => write!(f, "numeric"),
+            UdaType::Date => write!(f, "date"),
+            UdaType::Duration => write!(f, "duration"),
+        }
+    }
+}
+
+/// A typed value of a user defined attribute
+#[derive(Clone, Debug, PartialEq)]
+pub enum UdaValue {
+    String(String),
+    Numeric(f64),
+    Date(DateTime<Utc>),
+    /// A duration in taskwarrior's notation, e.g. `PT2H` or `3d`
+    Duration(String),
+}
+
+impl UdaValue {
+    /// The type of this value
+    pub fn uda_type(&self) -> UdaType {
+        match self {
+            UdaValue::String(_) => UdaType::String,
+            UdaValue::Numeric(_) => UdaType::Numeric,
+            UdaValue::Date(_) => UdaType::Date,
+            UdaValue::Duration(_) => UdaType::Duration,
+        }
+    }
+
+    /// The representation of this value in taskwarrior's JSON format
+    pub fn to_json(&self) -> Value {
+        match self {
+            UdaValue::String(s) | UdaValue::Duration(s) => Value::from(s.as_str()),
+            UdaValue::Numeric(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                Value::from(*n as i64)
+            }
+            UdaValue::Numeric(n) => Value::from(*n),
+            UdaValue::Date(date) => Value::from(datetime_format::format(date)),
+        }
+    }
+}
+
+impl std::fmt::Display for UdaValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UdaValue::String(s) | UdaValue::Duration(s) => write!(f, "{}", s),
+            UdaValue::Numeric(n) => write!(f, "{}", n),
+            UdaValue::Date(date) => write!(f, "{}", datetime_format::format(date)),
+        }
+    }
+}
+
+/// Declaration of a single user defined attribute
+#[derive(Clone, Debug, PartialEq)]
+pub struct Uda {
+    pub name: String,
+    pub uda_type: UdaType,
+    /// Column label shown in reports
+    pub label: Option<String>,
+    /// Allowed values of a string UDA, empty if any value is allowed
+    pub values: Vec<String>,
+    /// Value set on newly added tasks not providing one
+    pub default: Option<String>,
+}
+
+impl Uda {
+    pub fn new(name: &str, uda_type: UdaType) -> Self {
+        Self {
+            name: name.to_string(),
+            uda_type,
+            label: None,
+            values: vec![],
+            default: None,
+        }
+    }
+
+    pub fn with_values(mut self, values: &[&str]) -> Self {
+        self.values = values.iter().map(|v| v.to_string()).collect();
+        self
+    }
+
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// Convert the raw JSON `value` of this attribute into a typed value
+    pub fn parse(&self, value: &Value) -> Result<UdaValue, UdaError> {
+        let invalid = || UdaError::InvalidValue {
+            name: self.name.clone(),
+            value: value.to_string(),
+            expected: self.uda_type,
+        };
+        let typed = match (self.uda_type, value) {
+            (UdaType::String, Value::String(s)) => UdaValue::String(s.clone()),
+            (UdaType::Numeric, Value::Number(n)) => {
+                UdaValue::Numeric(n.as_f64().ok_or_else(invalid)?)
+            }
+            (UdaType::Numeric, Value::String(s)) => {
+                UdaValue::Numeric(s.trim().parse().map_err(|_| invalid())?)
+            }
+            (UdaType::Date, Value::String(s)) => {
+                UdaValue::Date(datetime_format::parse(s).map_err(|_| invalid())?)
+            }
+            (UdaType::Duration, Value::String(s)) => UdaValue::Duration(s.clone()),
+            _ => return Err(invalid()),
+        };
+        self.validate(&typed)?;
+        Ok(typed)
+    }
+
+    /// Check `value` has the type of this attribute and is one of its allowed values
+    pub fn validate(&self, value: &UdaValue) -> Result<(), UdaError> {
+        if value.uda_type() != self.uda_type {
+            return Err(UdaError::InvalidValue {
+                name: self.name.clone(),
+                value: value.to_string(),
+                expected: self.uda_type,
+            });
+        }
+        if let UdaValue::String(s) = value {
+            if !self.values.is_empty() && !self.values.contains(s) {
+                return Err(UdaError::NotAllowed {
+                    name: self.name.clone(),
+                    value: s.clone(),
+                    allowed: self.values.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The typed default value of this attribute, if any. Date defaults may be date
+    /// expressions like `now` or `2022-03-01`, which are evaluated in `dates`.
+    pub fn default_value(&self, dates: &DateContext) -> Option<Result<UdaValue, UdaError>> {
+        let default = self.default.as_deref()?;
+        Some(match self.uda_type {
+            UdaType::Date => {
+                dates
+                    .parse_date(default)
+                    .map(UdaValue::Date)
+                    .map_err(|_| UdaError::InvalidValue {
+                        name: self.name.clone(),
+                        value: Value::from(default).to_string(),
+                        expected: self.uda_type,
+                    })
+            }
+            _ => self.parse(&Value::from(default)),
+        })
+    }
+}
+
+/// All user defined attributes declared in the taskwarrior configuration
+#[derive(Clone, Debug, Default)]
+pub struct UdaRegistry {
+    udas: HashMap<String, Uda>,
+}
+
+impl UdaRegistry {
+    /// Collect all `uda.<name>.type`, `.label`, `.values` and `.default` declarations.
+    /// Fails for unknown types. Defaults not matching their declaration are ignored with a
+    /// warning, so one bad line does not block every hook.
+    pub fn from_taskrc(taskrc: &Taskrc) -> Result<Self, UdaError> {
+        let mut registry = Self::default();
+
+        for (key, value) in taskrc.with_prefix("uda.") {
+            let name = match key
+                .strip_prefix("uda.")
+                .and_then(|k| k.strip_suffix(".type"))
+            {
+                Some(name) if !BUILTIN_ATTRIBUTES.contains(&name) => name,
+                _ => continue,
+            };
+            let uda_type = value.parse().map_err(|_| UdaError::UnknownType {
+                name: name.to_string(),
+                uda_type: value.to_string(),
+            })?;
+
+            let mut uda = Uda::new(name, uda_type);
+            uda.label = taskrc.get(&format!("uda.{}.label", name)).map(String::from);
+            if let Some(values) = taskrc.get(&format!("uda.{}.values", name)) {
+                // an empty entry (e.g. "H,M,L,") allows the attribute to be unset
+                uda.values = values
+                    .split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            uda.default = taskrc
+                .get(&format!("uda.{}.default", name))
+                .filter(|default| !default.is_empty())
+                .map(String::from);
+            if let Some(Err(err)) = uda.default_value(&DateContext::new()) {
+                warn!("ignoring the default of UDA '{}': {}", name, err);
+                uda.default = None;
+            }
+
+            registry.insert(uda);
+        }
+        Ok(registry)
+    }
+
+    pub fn insert(&mut self, uda: Uda) {
+        self.udas.insert(uda.name.clone(), uda);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Uda> {
+        self.udas.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Uda> {
+        self.udas.values()
+    }
+
+    fn lookup(&self, name: &str) -> Result<&Uda, UdaError> {
+        self.get(name)
+            .ok_or_else(|| UdaError::Unknown(name.to_string()))
+    }
+}
+
+impl Task {
+    /// The typed value of the user defined attribute `name`
+    pub fn uda(&self, registry: &UdaRegistry, name: &str) -> Result<Option<UdaValue>, UdaError> {
+        let uda = registry.lookup(name)?;
+        self.unknown_fields
+            .get(name)
+            .map(|value| uda.parse(value))
+            .transpose()
+    }
+
+    /// Set the user defined attribute `name`, validating type and allowed values
+    pub fn set_uda(
+        &mut self,
+        registry: &UdaRegistry,
+        name: &str,
+        value: UdaValue,
+    ) -> Result<(), UdaError> {
+        registry.lookup(name)?.validate(&value)?;
+        self.unknown_fields
+            .insert(name.to_string(), value.to_json());
+        Ok(())
+    }
+
+    /// Check the values of all declared user defined attributes of this task have their type
+    /// and are allowed
+    pub fn validate_udas(&self, registry: &UdaRegistry) -> Result<(), UdaError> {
+        for uda in registry.iter() {
+            if let Some(value) = self.unknown_fields.get(&uda.name) {
+                uda.parse(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the user defined attribute `name`, returning its raw value
+    pub fn remove_uda(&mut self, name: &str) -> Option<Value> {
+        self.unknown_fields.remove(name)
+    }
+
+    /// Set the default of all user defined attributes this task has no value for, evaluating
+    /// date defaults in `dates`. Returns the names of the attributes that have been set.
+    pub fn apply_uda_defaults(
+        &mut self,
+        registry: &UdaRegistry,
+        dates: &DateContext,
+    ) -> Result<Vec<String>, UdaError> {
+        let mut applied = vec![];
+        for uda in registry.iter() {
+            if self.unknown_fields.contains_key(&uda.name) {
+                continue;
+            }
+            if let Some(default) = uda.default_value(dates) {
+                self.unknown_fields
+                    .insert(uda.name.clone(), default?.to_json());
+                applied.push(uda.name.clone());
+            }
+        }
+        Ok(applied)
+    }
+}
+
+/// Errors regarding user defined attributes
+#[derive(Debug, PartialEq)]
+pub enum UdaError {
+    /// No UDA of this name has been declared
+    Unknown(String),
+    /// `uda.<name>.type` is not one of string, numeric, date or duration
+    UnknownType { name: String, uda_type: String },
+    /// The value cannot be interpreted as the declared type
+    InvalidValue {
+        name: String,
+        value: String,
+        expected: UdaType,
+    },
+    /// The value is not listed in `uda.<name>.values`
+    NotAllowed {
+        name: String,
+        value: String,
+        allowed: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for UdaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UdaError::Unknown(name) => write!(f, "unknown UDA '{}'", name),
+            UdaError::UnknownType { name, uda_type } => {
+                write!(f, "UDA '{}' has unknown type '{}'", name, uda_type)
+            }
+            UdaError::InvalidValue {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "UDA '{}' expects a {} value, got {}",
+                name, expected, value
+            ),
+            UdaError::NotAllowed {
+                name,
+                value,
+                allowed,
+            } => write!(
+                f,
+                "'{}' is not an allowed value for UDA '{}' (allowed: {})",
+                value,
+                name,
+                allowed.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UdaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    const TASKRC: &str = "\
+        uda.estimate.type=numeric\n\
+        uda.estimate.label=Est\n\
+        uda.client.type=string\n\
+        uda.client.values=acme,globex,\n\
+        uda.client.default=acme\n\
+        uda.reviewed.type=date\n\
+        uda.reviewed.default=now\n\
+        uda.effort.type=duration\n\
+        uda.priority.type=string\n\
+        uda.priority.values=H,M,L,\n";
+
+    fn test_registry() -> UdaRegistry {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("taskrc");
+        std::fs::write(&path, TASKRC).expect("writing taskrc");
+        let taskrc = Taskrc::load(&path).expect("loading taskrc");
+        UdaRegistry::from_taskrc(&taskrc).expect("valid UDA declarations")
+    }
+
+    #[test]
+    fn build_registry_from_taskrc() {
+        let registry = test_registry();
+
+        assert_eq!(registry.iter().count(), 4);
+        assert!(registry.get("priority").is_none());
+
+        let estimate = registry.get("estimate").expect("estimate declared");
+        assert_eq!(estimate.uda_type, UdaType::Numeric);
+        assert_eq!(estimate.label, Some("Est".to_string()));
+
+        let client = registry.get("client").expect("client declared");
+        assert_eq!(client.values, vec!["acme", "globex"]);
+        assert_eq!(client.default, Some("acme".to_string()));
+    }
+
+    #[test]
+    fn reject_unknown_uda_type() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("taskrc");
+        std::fs::write(&path, "uda.foo.type=integer\n").expect("writing taskrc");
+        let taskrc = Taskrc::load(&path).expect("loading taskrc");
+        assert!(matches!(
+            UdaRegistry::from_taskrc(&taskrc),
+            Err(UdaError::UnknownType { .. })
+        ));
+    }
+
+    #[test]
+    fn ignore_invalid_uda_default() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        let path = dir.path().join("taskrc");
+        std::fs::write(
+            &path,
+            "uda.foo.type=string\nuda.foo.values=a,b\nuda.foo.default=c\n",
+        )
+        .expect("writing taskrc");
+        let taskrc = Taskrc::load(&path).expect("loading taskrc");
+        let registry = UdaRegistry::from_taskrc(&taskrc).expect("bad defaults are ignored");
+        assert_eq!(registry.get("foo").unwrap().default, None);
+    }
+
+    #[test]
+    fn typed_getters_on_task() {
+        let registry = test_registry();
+        let mut task = Task::new("Dummy Task");
+        task.unknown_fields
+            .insert("estimate".to_string(), Value::from("2.5"));
+        task.unknown_fields
+            .insert("reviewed".to_string(), Value::from("20220110T171619Z"));
+
+        assert_eq!(
+            task.uda(&registry, "estimate"),
+            Ok(Some(UdaValue::Numeric(2.5)))
+        );
+        assert_eq!(
+            task.uda(&registry, "reviewed"),
+            Ok(Some(UdaValue::Date(
+                Utc.with_ymd_and_hms(2022, 1, 10, 17, 16, 19).unwrap()
+            )))
+        );
+        assert_eq!(task.uda(&registry, "client"), Ok(None));
+        assert!(matches!(
+            task.uda(&registry, "undeclared"),
+            Err(UdaError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn setters_validate_type_and_allowed_values() {
+        let registry = test_registry();
+        let mut task = Task::new("Dummy Task");
+
+        task.set_uda(&registry, "estimate", UdaValue::Numeric(3.0))
+            .expect("valid numeric value");
+        assert_eq!(task.unknown_fields["estimate"], Value::from(3));
+
+        task.set_uda(&registry, "client", UdaValue::String("globex".to_string()))
+            .expect("allowed value");
+        assert!(matches!(
+            task.set_uda(&registry, "client", UdaValue::String("initech".to_string())),
+            Err(UdaError::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            task.set_uda(&registry, "estimate", UdaValue::String("lots".to_string())),
+            Err(UdaError::InvalidValue { .. })
+        ));
+        assert_eq!(task.unknown_fields["client"], "globex");
+    }
+
+    #[test]
+    fn apply_defaults_only_to_missing_attributes() {
+        let registry = test_registry();
+        let dates = DateContext::at(Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap());
+
+        let mut task = Task::new("Dummy Task");
+        let mut applied = task
+            .apply_uda_defaults(&registry, &dates)
+            .expect("valid defaults");
+        applied.sort();
+        assert_eq!(applied, vec!["client".to_string(), "reviewed".to_string()]);
+        assert_eq!(task.unknown_fields["client"], "acme");
+        assert_eq!(task.unknown_fields["reviewed"], "20220301T120000Z");
+
+        let mut task = Task::new("Dummy Task");
+        task.unknown_fields
+            .insert("client".to_string(), Value::from("globex"));
+        task.unknown_fields
+            .insert("reviewed".to_string(), Value::from("20220110T171619Z"));
+        let applied = task
+            .apply_uda_defaults(&registry, &dates)
+            .expect("valid defaults");
+        assert!(applied.is_empty());
+        assert_eq!(task.unknown_fields["client"], "globex");
+    }
+
+    #[test]
+    fn validate_uda_values_of_task() {
+        let registry = test_registry();
+        let mut task = Task::new("Dummy Task");
+        task.unknown_fields
+            .insert("client".to_string(), Value::from("globex"));
+        task.unknown_fields
+            .insert("undeclared".to_string(), Value::from("anything"));
+        assert_eq!(task.validate_udas(&registry), Ok(()));
+
+        task.unknown_fields
+            .insert("client".to_string(), Value::from("initech"));
+        assert!(matches!(
+            task.validate_udas(&registry),
+            Err(UdaError::NotAllowed { .. })
+        ));
+    }
+}
@@ -0,0 +1,955 @@
+use crate::config::Config;
+use crate::dates::DateContext;
+use crate::filename::short_uuid;
+use crate::filter::Filter;
+use crate::storage::Storage;
+use crate::sync::task_diff;
+use crate::{datetime_format, Error, Result, Status, Task};
+use chrono::{DateTime, Utc};
+use log::debug;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+/// The comment marking the start of a task list, like `<!-- taskwiki: project:work +next -->`,
+/// on a line of its own or at the end of a heading. Rendering stamps it with the time the list
+/// was rendered at, as in `<!-- taskwiki: +next | rendered:20220301T120000Z -->`.
+const DIRECTIVE: &str = r"<!--\s*taskwiki:(.*?)(?:\|\s*rendered:(\d{8}T\d{6}Z)\s*)?-->";
+
+/// The checkbox of a task line, showing the state of its task
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Marker {
+    /// `[ ]`, a pending or waiting task
+    Pending,
+    /// `[S]`, a pending task that has been started
+    Started,
+    /// `[x]`, a completed task
+    Completed,
+    /// `[-]`, a deleted task, like vimwiki's rejected items
+    Deleted,
+}
+
+impl Marker {
+    pub fn of(task: &Task) -> Self {
+        match task.status {
+            Status::Completed => Marker::Completed,
+            Status::Deleted => Marker::Deleted,
+            _ if task.start.is_some() => Marker::Started,
+            _ => Marker::Pending,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            Marker::Pending => ' ',
+            Marker::Started => 'S',
+            Marker::Completed => 'x',
+            Marker::Deleted => '-',
+        }
+    }
+
+    /// The marker shown as `c`, completed tasks being ticked with `x` or `X`
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            ' ' => Some(Marker::Pending),
+            'S' | 's' => Some(Marker::Started),
+            'x' | 'X' => Some(Marker::Completed),
+            '-' => Some(Marker::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// A line of a task list like `- [ ] Write the report  #1a2b3c4d`, with the short UUID of its
+/// task at the end. Lines the user added have none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskLine {
+    pub indent: String,
+    pub marker: Marker,
+    pub description: String,
+    pub uuid: Option<String>,
+}
+
+impl TaskLine {
+    pub fn from_task(task: &Task) -> Self {
+        Self {
+            indent: String::new(),
+            marker: Marker::of(task),
+            description: task.description.clone(),
+            uuid: Some(short_uuid(task)),
+        }
+    }
+
+    /// The task line `line`, if it is a checkbox item of a `-` or `*` list
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim_start();
+        let indent = &line[..line.len() - rest.len()];
+        let rest = rest
+            .strip_prefix("- [")
+            .or_else(|| rest.strip_prefix("* ["))?;
+        let mut chars = rest.chars();
+        let marker = Marker::from_char(chars.next()?)?;
+        let rest = chars.as_str().strip_prefix(']')?;
+        if !(rest.is_empty() || rest.starts_with(' ')) {
+            return None;
+        }
+
+        let rest = rest.trim();
+        let (description, uuid) = match rest.rsplit_once('#') {
+            Some((description, uuid))
+                if (description.is_empty() || description.ends_with(char::is_whitespace))
+                    && is_short_uuid(uuid) =>
+            {
+                (description.trim_end(), Some(uuid.to_lowercase()))
+            }
+            _ => (rest, None),
+        };
+        Some(Self {
+            indent: indent.to_string(),
+            marker,
+            description: description.to_string(),
+            uuid,
+        })
+    }
+
+    /// Whether the line belongs to `task`
+    pub fn is_of(&self, task: &Task) -> bool {
+        self.uuid.as_deref() == Some(short_uuid(task).as_str())
+    }
+
+    /// `task` changed at `now` to match the description and checkbox of the line
+    pub fn apply_to(&self, task: &Task, now: DateTime<Utc>) -> Task {
+        let mut changed = task.clone();
+        if !self.description.is_empty() {
+            changed.description = self.description.clone();
+        }
+        if self.marker != Marker::of(task) {
+            let is_closed = matches!(task.status, Status::Completed | Status::Deleted);
+            match self.marker {
+                Marker::Completed => {
+                    changed.status = Status::Completed;
+                    changed.end = Some(now);
+                }
+                Marker::Deleted => {
+                    changed.status = Status::Deleted;
+                    changed.end = Some(now);
+                }
+                Marker::Started | Marker::Pending => {
+                    if is_closed {
+                        changed.status = Status::Pending;
+                        changed.end = None;
+                    }
+                    changed.start = match self.marker {
+                        Marker::Started => task.start.or(Some(now)),
+                        _ => None,
+                    };
+                }
+            }
+        }
+        if changed != *task {
+            changed.modified = now;
+        }
+        changed
+    }
+}
+
+impl fmt::Display for TaskLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}- [{}] {}",
+            self.indent,
+            self.marker.as_char(),
+            self.description
+        )?;
+        match &self.uuid {
+            Some(uuid) => write!(f, "  #{}", uuid),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_short_uuid(s: &str) -> bool {
+    s.len() == 8 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// A task list in a wiki page: a directive and the task lines directly following it
+#[derive(Clone, Debug)]
+pub struct Region {
+    /// Index of the line holding the directive
+    pub directive: usize,
+    /// The filter of the directive, all pending tasks if it has none
+    pub filter: Option<Filter>,
+    /// When the list was last rendered, if ever
+    pub rendered: Option<DateTime<Utc>>,
+    /// Indices of the task lines
+    pub lines: Range<usize>,
+}
+
+impl Region {
+    /// Whether `task` belongs to the list, `listed` telling whether it is listed already.
+    /// Completed and deleted tasks are only added by a filter on the status, but stay listed
+    /// once they are, so the list shows what became of them.
+    pub fn wants(&self, task: &Task, listed: bool, dates: &DateContext) -> bool {
+        let is_open = !matches!(task.status, Status::Completed | Status::Deleted);
+        task.status != Status::Recurring
+            && match &self.filter {
+                Some(filter) => {
+                    filter.matches_in(task, dates)
+                        && (is_open || listed || filter.mentions("status"))
+                }
+                None => is_open || listed,
+            }
+    }
+}
+
+/// The task lists in the wiki page `content`, skipping fenced code blocks
+pub fn regions(content: &str) -> Result<Vec<Region>> {
+    let directive = Regex::new(DIRECTIVE).expect("valid regex");
+    let lines: Vec<&str> = content.lines().collect();
+    let mut regions = vec![];
+    let mut in_code = false;
+    for (idx, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+        }
+        let (filter, rendered) = match directive.captures(line).filter(|_| !in_code) {
+            Some(captures) => (
+                captures[1].trim().to_string(),
+                captures
+                    .get(2)
+                    .and_then(|stamp| datetime_format::parse(stamp.as_str()).ok()),
+            ),
+            None => continue,
+        };
+        let filter =
+            match filter.is_empty() {
+                true => None,
+                false => Some(filter.parse().map_err(|message| {
+                    Error::Filter(format!("{} in line {}", message, idx + 1))
+                })?),
+            };
+        let end = (idx + 1..lines.len())
+            .find(|&end| TaskLine::parse(lines[end]).is_none())
+            .unwrap_or(lines.len());
+        regions.push(Region {
+            directive: idx,
+            filter,
+            rendered,
+            lines: idx + 1..end,
+        });
+    }
+    Ok(regions)
+}
+
+/// Fill the task lists of the wiki page `content` with the matching `tasks`, leaving the rest
+/// of the page as it is. Listed tasks keep their place and indentation, other tasks follow by
+/// decreasing urgency. Lines without a UUID, which the user added, are kept, as are lines whose
+/// UUID matches none of `tasks`. The directives are
+/// stamped with the current time, which later edits are checked against.
+pub fn render(content: &str, tasks: &[Task], dates: &DateContext) -> Result<String> {
+    let directive = Regex::new(DIRECTIVE).expect("valid regex");
+    let stamp = datetime_format::format(&dates.now());
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    for region in regions(content)?.into_iter().rev() {
+        let stamped = directive.replace(&lines[region.directive], |captures: &regex::Captures| {
+            let filter = captures[1].trim();
+            let separator = if filter.is_empty() { "" } else { " " };
+            format!(
+                "<!-- taskwiki: {}{}| rendered:{} -->",
+                filter, separator, stamp
+            )
+        });
+        lines[region.directive] = stamped.into_owned();
+        let listed: Vec<_> = lines[region.lines.clone()]
+            .iter()
+            .filter_map(|line| TaskLine::parse(line).map(|task_line| (line, task_line)))
+            .collect();
+        let is_listed = |task: &Task| listed.iter().any(|(_, line)| line.is_of(task));
+        let mut shown: Vec<&Task> = tasks
+            .iter()
+            .filter(|task| region.wants(task, is_listed(task), dates))
+            .collect();
+
+        let mut rendered = vec![];
+        for (line, task_line) in &listed {
+            if task_line.uuid.is_none() || !tasks.iter().any(|task| task_line.is_of(task)) {
+                rendered.push(line.to_string());
+            } else if let Some(idx) = shown.iter().position(|task| task_line.is_of(task)) {
+                let task = shown.remove(idx);
+                let mut updated = TaskLine::from_task(task);
+                updated.indent = task_line.indent.clone();
+                rendered.push(updated.to_string());
+            }
+        }
+        shown.sort_by(|a, b| {
+            let urgency = |task: &Task| task.urgency.unwrap_or_default();
+            urgency(b)
+                .total_cmp(&urgency(a))
+                .then(a.entry.cmp(&b.entry))
+        });
+        rendered.extend(
+            shown
+                .into_iter()
+                .map(|task| TaskLine::from_task(task).to_string()),
+        );
+        lines.splice(region.lines, rendered);
+    }
+
+    let mut rendered = lines.join("\n");
+    if content.ends_with('\n') {
+        rendered.push('\n');
+    }
+    Ok(rendered)
+}
+
+/// A task changed or added by a line of a wiki page
+#[derive(Clone, Debug)]
+pub struct Edit {
+    /// Index of the line
+    pub line: usize,
+    /// The task as changed by the line
+    pub task: Task,
+    /// Human readable list of changed attributes, empty for new tasks
+    pub diff: Vec<String>,
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diff.is_empty() {
+            true => write!(
+                f,
+                "line {}: new task {:?}",
+                self.line + 1,
+                self.task.description
+            ),
+            false => write!(
+                f,
+                "line {}: task {} ({})",
+                self.line + 1,
+                short_uuid(&self.task),
+                self.diff.join(", ")
+            ),
+        }
+    }
+}
+
+/// An edited line whose task has also been modified in taskwarrior since its list was rendered.
+/// The task wins, the line is not applied.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    /// Index of the line
+    pub line: usize,
+    /// The line as edited
+    pub text: String,
+    /// The task as modified in taskwarrior
+    pub task: Task,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: task {} has been modified in taskwarrior too, ignoring {:?}",
+            self.line + 1,
+            short_uuid(&self.task),
+            self.text.trim()
+        )
+    }
+}
+
+/// A task line whose UUID matches no task. It is left on the page as it is.
+#[derive(Clone, Debug)]
+pub struct Unknown {
+    /// Index of the line
+    pub line: usize,
+    /// The line as found
+    pub text: String,
+}
+
+impl fmt::Display for Unknown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: no task matches, keeping {:?}",
+            self.line + 1,
+            self.text.trim()
+        )
+    }
+}
+
+/// The changes the user made to the task lists of a wiki page
+#[derive(Clone, Debug, Default)]
+pub struct Edits {
+    /// Listed tasks whose line has been edited
+    pub changed: Vec<Edit>,
+    /// New tasks for lines without a UUID
+    pub added: Vec<Edit>,
+    pub conflicts: Vec<Conflict>,
+    /// Lines of tasks that do not exist
+    pub unknown: Vec<Unknown>,
+}
+
+impl Edits {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+            && self.added.is_empty()
+            && self.conflicts.is_empty()
+            && self.unknown.is_empty()
+    }
+
+    /// The changed and new tasks, to be imported into taskwarrior
+    pub fn tasks(&self) -> Vec<Task> {
+        self.changed
+            .iter()
+            .chain(&self.added)
+            .map(|edit| edit.task.clone())
+            .collect()
+    }
+
+    /// `content` with the lines of new tasks tagged with their UUID
+    pub fn tag_added(&self, content: &str) -> String {
+        let mut lines: Vec<String> = content.lines().map(String::from).collect();
+        for edit in &self.added {
+            if let Some(line) = lines.get_mut(edit.line) {
+                let indent = TaskLine::parse(line).map(|line| line.indent);
+                let mut tagged = TaskLine::from_task(&edit.task);
+                tagged.indent = indent.unwrap_or_default();
+                *line = tagged.to_string();
+            }
+        }
+        let mut tagged = lines.join("\n");
+        if content.ends_with('\n') {
+            tagged.push('\n');
+        }
+        tagged
+    }
+}
+
+impl fmt::Display for Edits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<(usize, String)> = self
+            .changed
+            .iter()
+            .chain(&self.added)
+            .map(|edit| (edit.line, edit.to_string()))
+            .chain(
+                self.conflicts
+                    .iter()
+                    .map(|conflict| (conflict.line, conflict.to_string())),
+            )
+            .chain(
+                self.unknown
+                    .iter()
+                    .map(|unknown| (unknown.line, unknown.to_string())),
+            )
+            .collect();
+        lines.sort();
+        for (idx, (_, line)) in lines.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The changes the user made to the task lists of the wiki page `content`. A ticked or cleared
+/// checkbox changes the status of the task, edited text its description, and a line without a
+/// UUID becomes a new task with the project and tags of its list. Lines of tasks modified in
+/// taskwarrior after their list was rendered are conflicts.
+pub fn edits(content: &str, tasks: &[Task], now: DateTime<Utc>) -> Result<Edits> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut edits = Edits::default();
+    let mut seen = HashSet::new();
+    for region in regions(content)? {
+        for idx in region.lines.clone() {
+            let task_line = match TaskLine::parse(lines[idx]) {
+                Some(task_line) => task_line,
+                None => continue,
+            };
+            if task_line.uuid.is_none() {
+                if task_line.description.is_empty() {
+                    continue;
+                }
+                let mut task = Task::new(&task_line.description);
+                task.entry = now;
+                task.modified = now;
+                if let Some(filter) = &region.filter {
+                    filter.fill_in(&mut task);
+                }
+                let task = task_line.apply_to(&task, now);
+                edits.added.push(Edit {
+                    line: idx,
+                    task,
+                    diff: vec![],
+                });
+                continue;
+            }
+
+            let task = match tasks.iter().find(|task| task_line.is_of(task)) {
+                Some(task) => task,
+                None => {
+                    edits.unknown.push(Unknown {
+                        line: idx,
+                        text: lines[idx].to_string(),
+                    });
+                    continue;
+                }
+            };
+            if !seen.insert(task.uuid) {
+                debug!("task {} is listed again in line {}", task.uuid, idx + 1);
+                continue;
+            }
+            let changed = task_line.apply_to(task, now);
+            let diff = task_diff(task, &changed);
+            if diff.is_empty() {
+                continue;
+            }
+            if region
+                .rendered
+                .is_some_and(|rendered| task.modified > rendered)
+            {
+                edits.conflicts.push(Conflict {
+                    line: idx,
+                    text: lines[idx].to_string(),
+                    task: task.clone(),
+                });
+            } else {
+                edits.changed.push(Edit {
+                    line: idx,
+                    task: changed,
+                    diff,
+                });
+            }
+        }
+    }
+    Ok(edits)
+}
+
+/// Apply the edits of the wiki page at `path` to the tasks in `storage`, then refresh its
+/// task lists, writing a report to `output`. With `dry_run` nothing is changed and the report
+/// shows what would be.
+pub fn run<W: Write>(
+    config: &Config,
+    storage: &dyn Storage,
+    path: &Path,
+    dry_run: bool,
+    output: &mut W,
+) -> Result<()> {
+    let content = std::fs::read_to_string(path).map_err(Error::io(format!(
+        "cannot read wiki page {}",
+        path.display()
+    )))?;
+    let dates = config.date_context();
+    let mut tasks = storage.load()?;
+
+    let context = || "cannot write feedback";
+    let edits = edits(&content, &tasks, dates.now())?;
+    if !edits.is_empty() {
+        writeln!(output, "{}", edits).map_err(Error::io(context()))?;
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let changed = edits.tasks();
+    if !changed.is_empty() {
+        storage.save(&changed)?;
+        debug!("imported {} changed tasks", changed.len());
+    }
+    for task in changed {
+        match tasks.iter_mut().find(|other| other.uuid == task.uuid) {
+            Some(other) => *other = task,
+            None => tasks.push(task),
+        }
+    }
+    config.urgency.apply(&mut tasks, dates.now());
+
+    let rendered = render(&edits.tag_added(&content), &tasks, &dates)?;
+    if rendered != content {
+        std::fs::write(path, &rendered).map_err(Error::io(format!(
+            "cannot write wiki page {}",
+            path.display()
+        )))?;
+    }
+    writeln!(
+        output,
+        "Rendered {} task lists in {}",
+        regions(&rendered)?.len(),
+        path.display()
+    )
+    .map_err(Error::io(context()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ff4::TaskData;
+    use crate::storage::Storage;
+    use chrono::{TimeZone, Utc};
+    use tempfile::tempdir;
+
+    fn dates() -> DateContext {
+        DateContext::at(Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).unwrap())
+    }
+
+    fn tasks() -> Vec<Task> {
+        let mut report = Task::new("Write the report");
+        report.project = Some(String::from("work"));
+        report.urgency = Some(3.0);
+        let mut call = Task::new("Call Alex").with_tag("next");
+        call.project = Some(String::from("work"));
+        call.start = Some(dates().now());
+        call.urgency = Some(15.0);
+        let mut filed = Task::new("File the taxes");
+        filed.project = Some(String::from("work"));
+        filed.status = Status::Completed;
+        let mut garden = Task::new("Water the plants");
+        garden.project = Some(String::from("home"));
+        let mut tasks = vec![report, call, filed, garden];
+        for task in &mut tasks {
+            task.entry = dates().now() - chrono::Duration::days(1);
+            task.modified = task.entry;
+        }
+        tasks
+    }
+
+    fn line(task: &Task) -> String {
+        TaskLine::from_task(task).to_string()
+    }
+
+    /// The directive with `filter` as rendered at `dates().now()`
+    fn directive(filter: &str) -> String {
+        format!("<!-- taskwiki: {} | rendered:20220301T120000Z -->", filter)
+    }
+
+    #[test]
+    fn parse_task_lines() {
+        let parsed = TaskLine::parse("  * [X] Write the report  #1A2B3C4D").expect("task line");
+        assert_eq!(parsed.indent, "  ");
+        assert_eq!(parsed.marker, Marker::Completed);
+        assert_eq!(parsed.description, "Write the report");
+        assert_eq!(parsed.uuid.as_deref(), Some("1a2b3c4d"));
+        assert_eq!(parsed.to_string(), "  - [x] Write the report  #1a2b3c4d");
+
+        let parsed = TaskLine::parse("- [ ] Buy milk #2").expect("task line");
+        assert_eq!(parsed.description, "Buy milk #2");
+        assert_eq!(parsed.uuid, None);
+        assert_eq!(TaskLine::parse("- [S] ").unwrap().marker, Marker::Started);
+
+        for line in [
+            "- Buy milk",
+            "- [?] Buy milk",
+            "- [ ]Buy milk",
+            "[ ] Buy milk",
+        ] {
+            assert_eq!(TaskLine::parse(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn find_regions() {
+        let content = "# Work <!-- taskwiki: project:work -->\n\
+                       - [ ] Write the report  #1a2b3c4d\n\
+                       - [ ] New task\n\
+                       \n\
+                       ```\n\
+                       <!-- taskwiki: project:home -->\n\
+                       ```\n\
+                       <!--taskwiki:| rendered:20220301T120000Z-->\n";
+        let regions = regions(content).expect("valid directives");
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].rendered, None);
+        assert_eq!(regions[1].rendered, Some(dates().now()));
+        assert_eq!(regions[0].directive, 0);
+        assert_eq!(
+            regions[0].filter.as_ref().unwrap().to_string(),
+            "project:work"
+        );
+        assert_eq!(regions[0].lines, 1..3);
+        assert!(regions[1].filter.is_none());
+        assert_eq!(regions[1].lines, 8..8);
+
+        let err = super::regions("text\n<!-- taskwiki: (project:work -->").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn render_task_lists() {
+        let tasks = tasks();
+        let [report, call, filed, garden] = [&tasks[0], &tasks[1], &tasks[2], &tasks[3]];
+        let content = format!(
+            "# Plans\n\
+             \n\
+             ## Work <!-- taskwiki: project:work -->\n\
+             \x20 - [ ] Old description  #{}\n\
+             - [ ] An idea\n\
+             - [ ] Gone  #00000000\n\
+             Some text\n\
+             \n\
+             ## Everything <!-- taskwiki: -->\n",
+            short_uuid(report)
+        );
+
+        let rendered = render(&content, &tasks, &dates()).expect("valid directives");
+        assert_eq!(
+            rendered,
+            format!(
+                "# Plans\n\
+                 \n\
+                 ## Work {}\n\
+                 \x20 {}\n\
+                 - [ ] An idea\n\
+                 - [ ] Gone  #00000000\n\
+                 {}\n\
+                 Some text\n\
+                 \n\
+                 ## Everything <!-- taskwiki: | rendered:20220301T120000Z -->\n\
+                 {}\n\
+                 {}\n\
+                 {}\n",
+                directive("project:work"),
+                line(report),
+                line(call),
+                line(call),
+                line(report),
+                line(garden),
+            )
+        );
+        assert!(line(call).starts_with("- [S] Call Alex  #"));
+        assert_eq!(render(&rendered, &tasks, &dates()).unwrap(), rendered);
+
+        // completed tasks stay listed, with their box ticked
+        let mut tasks = tasks.clone();
+        tasks[0].status = Status::Completed;
+        let completed = render(&rendered, &tasks, &dates()).unwrap();
+        assert!(completed.contains(&format!(
+            "  - [x] Write the report  #{}",
+            short_uuid(report)
+        )));
+        assert!(!completed.contains(&filed.description));
+        let done = render("<!-- taskwiki: status:completed -->", &tasks, &dates()).unwrap();
+        assert_eq!(done.lines().count(), 3);
+        assert!(done.contains(&line(filed)));
+    }
+
+    #[test]
+    fn render_wiki_page_in_place() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        let storage = TaskData::open(&dir.path().join("data")).writable();
+        let tasks = tasks();
+        storage.save(&tasks).expect("saving succeeds");
+        let path = dir.path().join("work.md");
+        std::fs::write(&path, "Intro\n<!-- taskwiki: +next -->\nOutro").unwrap();
+
+        let mut output = vec![];
+        run(&Config::default(), &storage, &path, false, &mut output).expect("renders");
+        let content = std::fs::read_to_string(&path).unwrap();
+        let rendered = regions(&content).unwrap()[0]
+            .rendered
+            .expect("directive stamped");
+        assert!(rendered <= Utc::now());
+        assert_eq!(
+            content,
+            format!(
+                "Intro\n<!-- taskwiki: +next | rendered:{} -->\n{}\nOutro",
+                datetime_format::format(&rendered),
+                line(&tasks[1])
+            )
+        );
+        assert!(String::from_utf8(output).unwrap().contains("1 task lists"));
+        assert!(run(
+            &Config::default(),
+            &storage,
+            &dir.path().join("missing.md"),
+            false,
+            &mut vec![]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_edits() {
+        let tasks = tasks();
+        let [report, call, filed, garden] = [&tasks[0], &tasks[1], &tasks[2], &tasks[3]];
+        let later = dates().now() + chrono::Duration::hours(1);
+        let content = format!(
+            "## Work {}\n\
+             - [x] Write the final report  #{}\n\
+             \x20 - [ ] Call Alex  #{}\n\
+             - [ ] Plan the offsite\n\
+             - [ ] File the taxes  #{}\n\
+             - [ ] \n\
+             - [x] Unknown  #00000000\n\
+             \n\
+             <!-- taskwiki: | rendered:20220301T120000Z -->\n\
+             - [S] Water the plants  #{}\n\
+             - [x] Write the report  #{}\n",
+            directive("project:work +next"),
+            short_uuid(report),
+            short_uuid(call),
+            short_uuid(filed),
+            short_uuid(garden),
+            short_uuid(report),
+        );
+
+        let edits = edits(&content, &tasks, later).expect("valid directives");
+        assert_eq!(edits.changed.len(), 4);
+        let changed = |line: usize| {
+            let edit = edits.changed.iter().find(|edit| edit.line == line);
+            &edit.expect("line changes a task").task
+        };
+        assert_eq!(changed(1).description, "Write the final report");
+        assert_eq!(changed(1).status, Status::Completed);
+        assert_eq!(changed(1).end, Some(later));
+        assert_eq!(changed(1).modified, later);
+        assert_eq!(changed(2).start, None);
+        assert_eq!(changed(4).status, Status::Pending);
+        assert_eq!(changed(4).end, None);
+        assert_eq!(changed(9).start, Some(later));
+        assert_eq!(
+            edits.changed[0].to_string(),
+            format!(
+                "line 2: task {} (description: \"Write the report\" -> \"Write the final report\", \
+                 status: \"pending\" -> \"completed\")",
+                short_uuid(report)
+            )
+        );
+
+        assert_eq!(edits.added.len(), 1);
+        let added = &edits.added[0].task;
+        assert_eq!(added.description, "Plan the offsite");
+        assert_eq!(added.project.as_deref(), Some("work"));
+        assert!(added.has_tag("next"));
+        assert_eq!(added.entry, later);
+        assert!(edits.conflicts.is_empty());
+        assert_eq!(edits.unknown.len(), 1);
+        assert_eq!(edits.unknown[0].line, 6);
+        assert!(edits
+            .to_string()
+            .contains("line 7: no task matches, keeping \"- [x] Unknown  #00000000\""));
+
+        let tagged = edits.tag_added(&content);
+        assert!(tagged.contains(&format!("- [ ] Plan the offsite  #{}\n", short_uuid(added))));
+        assert_eq!(tagged.lines().count(), content.lines().count());
+
+        // the report has been modified since the lists were rendered
+        let mut modified = tasks.clone();
+        modified[0].modified = dates().now() + chrono::Duration::minutes(30);
+        let edits = super::edits(&content, &modified, later).unwrap();
+        assert_eq!(edits.changed.len(), 3);
+        assert_eq!(edits.conflicts.len(), 1);
+        assert_eq!(edits.conflicts[0].line, 1);
+        assert!(edits.to_string().contains("modified in taskwarrior too"));
+        // lists that were never rendered have nothing to conflict with
+        let unstamped = content.replace(" | rendered:20220301T120000Z", "");
+        let edits = super::edits(&unstamped, &modified, later).unwrap();
+        assert_eq!(edits.changed.len(), 4);
+        assert!(edits.conflicts.is_empty());
+
+        // rendering undoes the edits, apart from the new line
+        let rendered = render(&content, &tasks, &dates()).unwrap();
+        let edits = super::edits(&rendered, &tasks, later).unwrap();
+        assert!(edits.changed.is_empty());
+        assert_eq!(edits.added.len(), 1);
+    }
+
+    #[test]
+    fn apply_edits_before_rendering() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        let storage = TaskData::open(&dir.path().join("data")).writable();
+        let tasks = tasks();
+        storage.save(&tasks).expect("saving succeeds");
+        let path = dir.path().join("work.md");
+        let content = format!(
+            "<!-- taskwiki: +next -->\n\
+             - [x] Call Alex  #{}\n\
+             - [ ] Book a room\n\
+             - [ ] Gone  #00000000\n",
+            short_uuid(&tasks[1])
+        );
+        std::fs::write(&path, &content).unwrap();
+
+        let mut output = vec![];
+        run(&Config::default(), &storage, &path, true, &mut output).expect("dry run succeeds");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+        let report = String::from_utf8(output).unwrap();
+        assert!(report.contains("line 2: task"));
+        assert!(report.contains("line 3: new task \"Book a room\""));
+        assert!(report.contains("line 4: no task matches"));
+
+        run(&Config::default(), &storage, &path, false, &mut vec![]).expect("renders");
+        let saved = storage.load().unwrap();
+        let call = saved
+            .iter()
+            .find(|task| task.uuid == tasks[1].uuid)
+            .unwrap();
+        assert_eq!(call.status, Status::Completed);
+        let room = saved
+            .iter()
+            .find(|task| task.description == "Book a room")
+            .expect("new task saved");
+        assert!(room.has_tag("next"));
+        let rendered = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            rendered,
+            format!(
+                "<!-- taskwiki: +next | rendered:{} -->\n{}\n{}\n- [ ] Gone  #00000000\n",
+                datetime_format::format(&regions(&rendered).unwrap()[0].rendered.unwrap()),
+                line(call),
+                line(room)
+            )
+        );
+    }
+
+    /// Edits of a task modified in taskwarrior since the page was rendered are not applied,
+    /// even though editing the page makes it newer than the task
+    #[test]
+    fn report_conflicting_edits() {
+        let dir = tempdir().expect("tempdir creation succeeds");
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        let storage = TaskData::open(&dir.path().join("data")).writable();
+        let tasks = tasks();
+        storage.save(&tasks).expect("saving succeeds");
+        let path = dir.path().join("work.md");
+        std::fs::write(&path, "<!-- taskwiki: project:work -->\n").unwrap();
+        run(&Config::default(), &storage, &path, false, &mut vec![]).expect("renders");
+        let content = std::fs::read_to_string(&path).unwrap();
+        let rendered = regions(&content).unwrap()[0].rendered.unwrap();
+
+        let mut report = tasks[0].clone();
+        report.description = String::from("Write the quarterly report");
+        report.modified = rendered + chrono::Duration::seconds(1);
+        storage.save(&[report.clone()]).expect("saving succeeds");
+        let edited = content.replace("[ ] Write the report", "[x] Write the report");
+        assert_ne!(edited, content);
+        std::fs::write(&path, &edited).unwrap();
+        // the user saves the page after the task was modified
+        let edited_at = rendered + chrono::Duration::minutes(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(edited_at.into()))
+            .unwrap();
+
+        let mut output = vec![];
+        run(&Config::default(), &storage, &path, false, &mut output).expect("renders");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("modified in taskwarrior too"), "{}", output);
+        let saved = storage.load().unwrap();
+        let saved = saved.iter().find(|task| task.uuid == report.uuid).unwrap();
+        assert_eq!(saved.status, Status::Pending);
+        assert_eq!(saved.description, "Write the quarterly report");
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains(&line(saved)));
+    }
+}